@@ -2,13 +2,12 @@ use crate::models::post::Post;
 use crate::schema::posts::dsl::*;
 use diesel::associations::HasTable;
 use diesel::prelude::*;
-use diesel::result::Error;
 
 use serde::Deserialize;
 use snx::StatusCode;
-use snx::{request::Request, Context, Json};
+use snx::{request::Request, Context, Error, Json};
 
-use super::{AppError, Result};
+use super::{json, Result};
 
 #[derive(Deserialize, Insertable, AsChangeset)]
 #[diesel(table_name = crate::schema::posts)]
@@ -18,12 +17,21 @@ struct PostPayload {
     published: bool,
 }
 
+/// Gets the identifier of the post from the route parameters.
+fn post_id(req: &Request) -> Result<i32> {
+    req.params
+        .get("id")
+        .and_then(|param| param.parse().ok())
+        .ok_or_else(|| json(Error::new(StatusCode::BadRequest, "invalid post id")))
+}
+
 /// Persists a post to the database.
 pub fn store(ctx: Context, req: Request) -> Result<(StatusCode, Json<Post>)> {
-    let payload = req.json::<PostPayload>()?;
+    let payload = req.json::<PostPayload>().map_err(json)?;
     let result = payload
         .insert_into(posts)
-        .get_result::<Post>(&mut ctx.db.get().unwrap())?;
+        .get_result::<Post>(&mut ctx.db.get().map_err(json)?)
+        .map_err(json)?;
 
     Ok((StatusCode::Created, Json(result)))
 }
@@ -32,39 +40,38 @@ pub fn store(ctx: Context, req: Request) -> Result<(StatusCode, Json<Post>)> {
 pub fn index(ctx: Context, _: Request) -> Result<Json<Vec<Post>>> {
     let results = posts
         .select(Post::as_select())
-        .load(&mut ctx.db.get().unwrap())?;
+        .load(&mut ctx.db.get().map_err(json)?)
+        .map_err(json)?;
 
     Ok(Json(results))
 }
 
 /// Retrieves a post from the database.
 pub fn get(ctx: Context, req: Request) -> Result<Json<Post>> {
-    let id_param = req.params.get("id").unwrap().parse::<i32>().unwrap();
+    let id_param = post_id(&req)?;
 
+    // a missing post is answered with a 404
     let result = posts
         .find(id_param)
-        .get_result(&mut ctx.db.get().unwrap())
-        .map_err(|e| match e {
-            Error::NotFound => AppError::ResourceNotFound,
-            _ => AppError::UnknownDatabaseError(e),
-        })
-        .unwrap();
+        .get_result(&mut ctx.db.get().map_err(json)?)
+        .map_err(json)?;
 
     Ok(Json(result))
 }
 
 /// Updates a post in the database.
 pub fn update(ctx: Context, req: Request) -> Result<()> {
-    let id_param = req.params.get("id").unwrap().parse::<i32>().unwrap();
-    let payload = req.json::<PostPayload>()?;
+    let id_param = post_id(&req)?;
+    let payload = req.json::<PostPayload>().map_err(json)?;
 
     let updated_rows = diesel::update(posts::table())
         .filter(id.eq(id_param))
         .set(payload)
-        .execute(&mut ctx.db.get().unwrap())?;
+        .execute(&mut ctx.db.get().map_err(json)?)
+        .map_err(json)?;
 
     if updated_rows == 0 {
-        return Err(AppError::ResourceNotFound);
+        return Err(json(StatusCode::NotFound));
     }
 
     Ok(())
@@ -72,14 +79,15 @@ pub fn update(ctx: Context, req: Request) -> Result<()> {
 
 /// Deletes a post from the database.
 pub fn destroy(ctx: Context, req: Request) -> Result<()> {
-    let id_param = req.params.get("id").unwrap().parse::<i32>().unwrap();
+    let id_param = post_id(&req)?;
 
     let deleted_rows = diesel::delete(posts::table())
         .filter(id.eq(id_param))
-        .execute(&mut ctx.db.get().unwrap())?;
+        .execute(&mut ctx.db.get().map_err(json)?)
+        .map_err(json)?;
 
     if deleted_rows == 0 {
-        return Err(AppError::ResourceNotFound);
+        return Err(json(StatusCode::NotFound));
     }
 
     Ok(())
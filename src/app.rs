@@ -2,7 +2,8 @@ use std::sync::{Arc, Mutex};
 
 use crate::{
     config::Config,
-    http::router::Router,
+    health::Health,
+    http::{access_log::AccessLogFile, handler::Handler, router::Router},
     middleware::{trace_requests, MiddlewareHandler},
     panic_hook::panic_hook,
     router, Context, Server,
@@ -26,7 +27,9 @@ pub trait App {
 
     /// Defines the application's global middleware.
     ///
-    /// Configures the request tracing middleware by default.
+    /// Configures the request tracing middleware by default. Global middleware wrap the route
+    /// middleware, and each global middleware wraps the ones listed before it, so the last one
+    /// runs first.
     fn with_global_middleware() -> Vec<MiddlewareHandler> {
         vec![
             Arc::new(Box::new(trace_requests)),
@@ -47,6 +50,31 @@ pub trait App {
     fn with_sessions(_: Context) -> Option<Box<dyn crate::session::SessionStore + Send + Sync>> {
         Some(Box::new(crate::session::MemorySessionStore::default()))
     }
+
+    /// Defines the application's rate limit store.
+    ///
+    /// Keeps rate limits in memory by default, use
+    /// [crate::rate_limit::DieselRateLimitStore] to share them between processes.
+    fn with_rate_limit_store(
+        _: Context,
+    ) -> Option<Box<dyn crate::rate_limit::RateLimitStore + Send + Sync>> {
+        Some(Box::new(crate::rate_limit::MemoryRateLimitStore::default()))
+    }
+
+    /// Defines the application's custom readiness checks.
+    ///
+    /// Only checks the database pool and session store by default.
+    fn with_health_checks(health: Health) -> Health {
+        health
+    }
+
+    /// Defines the application's handler for panics of other handlers in production, e.g. to
+    /// render a custom error page. Responses with a 200 status are answered with a 500 status.
+    ///
+    /// Responds with an empty 500 response by default.
+    fn with_error_handler() -> Option<Box<dyn Handler + Send + Sync>> {
+        None
+    }
 }
 
 /// Boots the snx framework and starts your application.
@@ -60,6 +88,18 @@ pub fn boot<A: App>() {
         ctx.session_store = A::with_sessions(ctx.clone()).map(|v| Arc::new(Mutex::new(v)));
     }
 
+    ctx.rate_limit_store = A::with_rate_limit_store(ctx.clone()).map(Arc::from);
+    ctx.health = A::with_health_checks(ctx.health);
+    ctx.error_handler = A::with_error_handler().map(Arc::new);
+
+    if let Some(access_log) = &config.access_log {
+        ctx.access_log = access_log.file.as_ref().map(|path| {
+            Arc::new(Mutex::new(
+                AccessLogFile::open(path, access_log).expect("failed to open access log file"),
+            ))
+        });
+    }
+
     let builder = Router::builder(&config.server.base_url);
     let router = A::with_routes(builder);
     let global_middleware = A::with_global_middleware();
@@ -68,8 +108,12 @@ pub fn boot<A: App>() {
     std::panic::set_hook(Box::new(panic_hook));
 
     let addr = format!("{}:{}", config.server.host, config.server.port);
-    Server::try_bind(addr, router, ctx, global_middleware)
+    let server = Server::try_bind(addr, router, ctx, global_middleware)
         .unwrap()
-        .num_threads(config.server.num_threads)
-        .serve();
+        .num_threads(config.server.num_threads);
+
+    #[cfg(feature = "signals")]
+    let server = server.handle_shutdown_signals();
+
+    server.serve();
 }
@@ -1,14 +1,36 @@
-use std::{fs::File, io::Read, num::ParseIntError, str::FromStr, time::Duration};
+use std::{fs::File, io::Read, net::IpAddr, num::ParseIntError, str::FromStr, time::Duration};
 
 use serde::Deserialize;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
+    /// Environment the application runs in, which must be set before any section.
+    ///
+    /// Defaults to production.
+    pub environment: Option<Environment>,
     pub server: ServerConfig,
     #[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
     pub database: DatabaseConfig,
     #[cfg(feature = "sessions")]
     pub session: Option<SessionConfig>,
+    #[cfg(feature = "compression")]
+    pub compression: Option<CompressionConfig>,
+    pub cors: Option<CorsConfig>,
+    #[cfg(feature = "sessions")]
+    pub csrf: Option<CsrfConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub security_headers: Option<SecurityHeadersConfig>,
+    pub request_id: Option<RequestIdConfig>,
+    pub access_log: Option<AccessLogConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Environment {
+    /// Shows debug error pages with the details of panics and requests.
+    Development,
+    #[default]
+    Production,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -17,8 +39,36 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub num_threads: usize,
+    /// Maximum size of a request body in bytes. Applies to the decompressed body if request
+    /// bodies are decompressed.
+    ///
+    /// Defaults to 2 MiB.
+    pub max_body_size: Option<usize>,
+    /// Status code used to answer requests that exceed the timeout of their route, either 503 or
+    /// 504. Other statuses are rejected when the config is loaded.
+    ///
+    /// Defaults to 503.
+    pub timeout_status: Option<u16>,
+    /// Time between receiving a termination signal and no longer accepting connections, during
+    /// which requests are still handled but the readiness endpoint reports unready, e.g. "10s".
+    /// Termination signals are only handled with the `signals` feature.
+    ///
+    /// Defaults to '5s'.
+    pub shutdown_delay: Option<String>,
+    /// Whether errors for requests that can't be parsed are rendered as problem details. Other
+    /// errors are rendered as problem details by the problem_details middleware.
+    ///
+    /// Defaults to false.
+    pub problem_details: Option<bool>,
+    /// Addresses of reverse proxies in front of the server. For requests from these addresses,
+    /// the client's address is taken from the `X-Forwarded-For` header, e.g. to rate limit
+    /// requests per client.
+    ///
+    /// Defaults to none.
+    pub trusted_proxies: Option<Vec<IpAddr>>,
 }
 
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
 #[derive(Deserialize, Debug, Clone)]
 pub struct DatabaseConfig {
     /// A database connection URL used to connect to the database.
@@ -45,16 +95,291 @@ pub struct DatabaseConfig {
     pub connection_timeout: Option<u64>,
 }
 
+#[cfg(feature = "sessions")]
 #[derive(Deserialize, Debug, Clone, Default)]
 pub struct SessionConfig {
     /// Cookie key used for storing the session.
     ///
     /// Defaults to 'snx-session'.
     pub cookie_key: Option<String>,
-    /// Duration after which the session will expire.
+    /// Duration after which the session will expire, which is also used as the `Max-Age` of the
+    /// session cookie.
     ///
     /// Defaults to 7 days.
     pub expires_after: Option<String>,
+    /// Path the session cookie is sent for.
+    ///
+    /// Defaults to '/'.
+    pub path: Option<String>,
+    /// Domain the session cookie is sent to, including its subdomains.
+    ///
+    /// Defaults to none, which only sends the cookie to the host that set it.
+    pub domain: Option<String>,
+    /// Whether the session cookie is hidden from JavaScript.
+    ///
+    /// Defaults to true.
+    pub http_only: Option<bool>,
+    /// Whether the session cookie is only sent over HTTPS. Always enabled if `same_site` is
+    /// `none`.
+    ///
+    /// Defaults to true, unless the environment is development.
+    pub secure: Option<bool>,
+    /// Whether the session cookie is sent with cross-site requests.
+    ///
+    /// Defaults to lax.
+    pub same_site: Option<SameSite>,
+}
+
+#[cfg(feature = "sessions")]
+impl SessionConfig {
+    /// Parses the duration after which sessions expire, which is checked when the config is
+    /// loaded.
+    pub(crate) fn expires_after(&self) -> Result<Duration, ParseDurationError> {
+        parse_duration(self.expires_after.as_deref().unwrap_or("7d"))
+    }
+}
+
+#[cfg(feature = "sessions")]
+#[derive(Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SameSite {
+    /// Only sent with same-site requests.
+    Strict,
+    /// Only sent with same-site requests and top-level navigations from other sites.
+    #[default]
+    Lax,
+    /// Sent with all requests, which requires the cookie to be secure.
+    None,
+}
+
+#[cfg(feature = "compression")]
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct CompressionConfig {
+    /// Minimum size in bytes of a response body before it is compressed. Streamed bodies of
+    /// unknown length are always compressed.
+    ///
+    /// Defaults to 1024.
+    pub min_size: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct CorsConfig {
+    /// Origins allowed to make cross-origin requests, e.g. `https://app.example.com`. Subdomains
+    /// can be allowed with a wildcard, e.g. `https://*.example.com`, and any origin with `*`.
+    ///
+    /// Defaults to none.
+    pub allowed_origins: Option<Vec<String>>,
+    /// Methods allowed in cross-origin requests.
+    ///
+    /// Defaults to GET, HEAD, POST, PUT, PATCH and DELETE.
+    pub allowed_methods: Option<Vec<String>>,
+    /// Request headers allowed in cross-origin requests.
+    ///
+    /// Defaults to the headers requested by the client.
+    pub allowed_headers: Option<Vec<String>>,
+    /// Response headers exposed to the client in addition to the CORS-safelisted ones.
+    ///
+    /// Defaults to none.
+    pub exposed_headers: Option<Vec<String>>,
+    /// Whether cross-origin requests may include credentials such as cookies. Cannot be enabled
+    /// if any origin is allowed with `*`.
+    ///
+    /// Defaults to false.
+    pub allow_credentials: Option<bool>,
+    /// Duration in seconds for which the result of a preflight request may be cached.
+    ///
+    /// Defaults to None, which leaves it up to the client.
+    pub max_age: Option<u64>,
+}
+
+#[cfg(feature = "sessions")]
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct CsrfConfig {
+    /// Paths that are not verified, e.g. webhooks. A trailing `*` matches any path with the
+    /// preceding prefix, e.g. `/webhooks/*`.
+    ///
+    /// Defaults to none.
+    pub except: Option<Vec<String>>,
+    /// Status code used to answer requests with a missing or mismatching token, either 403 or
+    /// 419.
+    ///
+    /// Defaults to 403.
+    pub status: Option<u16>,
+    /// Whether the token is stored in a cookie readable by JavaScript instead of in the session,
+    /// which is verified by comparing it to the token submitted in the `X-CSRF-Token` header.
+    ///
+    /// Defaults to false.
+    pub double_submit_cookie: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RateLimitConfig {
+    /// Rules limiting the requests to the paths they match.
+    ///
+    /// Defaults to none.
+    #[serde(default)]
+    pub rules: Vec<RateLimitRule>,
+    /// Session key containing the identifier of the authenticated user, used by rules limiting
+    /// requests per user.
+    ///
+    /// Defaults to 'user_id'.
+    pub user_key: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateLimitRule {
+    /// Name of the rule, which is used to keep the requests of different rules apart.
+    pub name: String,
+    /// Paths limited by the rule. A trailing `*` matches any path with the preceding prefix, e.g.
+    /// `/api/*`. Requests to all matching paths count towards the same limit.
+    pub paths: Vec<String>,
+    /// Methods limited by the rule.
+    ///
+    /// Defaults to all methods.
+    pub methods: Option<Vec<String>>,
+    /// Maximum number of requests per period, which must be at least 1.
+    pub limit: u32,
+    /// Duration of the period, e.g. "1m" for a minute, which must not be zero.
+    pub period: String,
+    /// Algorithm used to limit requests.
+    ///
+    /// Defaults to a token bucket.
+    pub algorithm: Option<RateLimitAlgorithm>,
+    /// Who the limit applies to.
+    ///
+    /// Defaults to the IP address of the client.
+    pub key: Option<RateLimitKey>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitAlgorithm {
+    /// Allows bursts up to the limit, after which requests are allowed at a steady rate.
+    #[default]
+    TokenBucket,
+    /// Allows up to the limit in any period, weighing the previous period by how much of it
+    /// overlaps with the sliding window.
+    SlidingWindow,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    /// Limits requests per IP address.
+    #[default]
+    Ip,
+    /// Limits requests per authenticated user, falling back to the IP address of guests.
+    User,
+    /// Limits all requests together.
+    Global,
+}
+
+/// Configures the headers added by the security headers middleware. Setting a header to an empty
+/// string disables it.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SecurityHeadersConfig {
+    /// Value of the `Strict-Transport-Security` header.
+    ///
+    /// Defaults to 'max-age=31536000; includeSubDomains'.
+    pub strict_transport_security: Option<String>,
+    /// Value of the `X-Content-Type-Options` header.
+    ///
+    /// Defaults to 'nosniff'.
+    pub content_type_options: Option<String>,
+    /// Value of the `X-Frame-Options` header.
+    ///
+    /// Defaults to 'DENY'.
+    pub frame_options: Option<String>,
+    /// Value of the `Referrer-Policy` header.
+    ///
+    /// Defaults to 'strict-origin-when-cross-origin'.
+    pub referrer_policy: Option<String>,
+    /// Value of the `Permissions-Policy` header.
+    ///
+    /// Defaults to 'camera=(), microphone=(), geolocation=()'.
+    pub permissions_policy: Option<String>,
+    /// Value of the `Content-Security-Policy` header. Occurrences of `{nonce}` are replaced by a
+    /// nonce generated for each request.
+    ///
+    /// Defaults to "default-src 'self'; script-src 'self' 'nonce-{nonce}'; style-src 'self'
+    /// 'nonce-{nonce}'; object-src 'none'; base-uri 'self'; frame-ancestors 'none'".
+    pub content_security_policy: Option<String>,
+    /// Whether the content security policy is only reported instead of enforced, using the
+    /// `Content-Security-Policy-Report-Only` header.
+    ///
+    /// Defaults to false.
+    pub content_security_policy_report_only: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RequestIdConfig {
+    /// Header containing the request identifier.
+    ///
+    /// Defaults to 'X-Request-Id'.
+    pub header: Option<String>,
+    /// Whether identifiers in incoming requests are used, e.g. when they are set by a reverse
+    /// proxy. Only enable this if the proxy overwrites the header sent by clients.
+    ///
+    /// Defaults to false.
+    pub trust_incoming: Option<bool>,
+    /// Format of generated identifiers.
+    ///
+    /// Defaults to UUIDv7.
+    pub format: Option<RequestIdFormat>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestIdFormat {
+    #[default]
+    UuidV7,
+    Ulid,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct AccessLogConfig {
+    /// Format of access log lines.
+    ///
+    /// Defaults to the compact format.
+    pub format: Option<AccessLogFormat>,
+    /// Template of access log lines when the format is custom, e.g.
+    /// `{remote_ip} "{method} {path}" {status} {latency_us}us {request_id}`.
+    ///
+    /// Available placeholders are `time`, `host`, `remote_addr`, `remote_ip`, `method`, `path`,
+    /// `route`, `protocol`, `status`, `bytes`, `referer`, `user_agent`, `request_id`, `latency_us`
+    /// and `latency_ms`.
+    ///
+    /// Defaults to the template of the compact format.
+    pub template: Option<String>,
+    /// Path of a file to write access log lines to instead of the tracing subscriber.
+    ///
+    /// Defaults to none.
+    pub file: Option<String>,
+    /// Size in bytes after which the access log file is rotated.
+    ///
+    /// Defaults to 10 MiB.
+    pub max_file_size: Option<u64>,
+    /// Amount of rotated access log files to keep.
+    ///
+    /// Defaults to 5.
+    pub max_files: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLogFormat {
+    /// `{host} {remote_addr} "{method} {path}" {status} {bytes}B {latency_ms}ms`
+    #[default]
+    Compact,
+    /// The Common Log Format.
+    Common,
+    /// The Combined Log Format, which adds the referer and user agent to the Common Log Format.
+    Combined,
+    /// A JSON object per line, including the route, request identifier and latency in
+    /// microseconds.
+    Json,
+    /// The configured template.
+    Custom,
 }
 
 impl Config {
@@ -69,10 +394,136 @@ impl Config {
         let mut contents = String::new();
         File::open("./snx.toml")?.read_to_string(&mut contents)?;
 
-        Ok(toml::from_str::<Config>(&contents)?)
+        let config = toml::from_str::<Config>(&contents)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Checks combinations of settings which cannot be expressed by the types of the config.
+    ///
+    /// ```
+    /// use snx::Config;
+    ///
+    /// # let database = if cfg!(any(feature = "sqlite", feature = "postgres", feature = "mysql")) {
+    /// #     "[database]\nurl = \":memory:\"\n"
+    /// # } else {
+    /// #     ""
+    /// # };
+    /// let config: Config = toml::from_str(&format!(
+    ///     r#"
+    ///     {database}
+    ///     [server]
+    ///     base_url = "localhost"
+    ///     host = "127.0.0.1"
+    ///     port = 3000
+    ///     num_threads = 4
+    ///
+    ///     [cors]
+    ///     allowed_origins = ["*"]
+    ///     allow_credentials = true
+    ///     "#
+    /// ))
+    /// .unwrap();
+    ///
+    /// assert!(config.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), InvalidConfigError> {
+        if let Some(status) = self
+            .server
+            .timeout_status
+            .filter(|s| ![503, 504].contains(s))
+        {
+            return Err(InvalidConfigError::TimeoutStatus(status));
+        }
+
+        if let Some(cors) = &self.cors {
+            let any = cors
+                .allowed_origins
+                .as_ref()
+                .is_some_and(|origins| origins.iter().any(|o| o == "*"));
+
+            if any && cors.allow_credentials.unwrap_or_default() {
+                return Err(InvalidConfigError::WildcardOriginWithCredentials);
+            }
+        }
+
+        if let Some(rule) = self
+            .rate_limit
+            .iter()
+            .flat_map(|rate_limit| &rate_limit.rules)
+            .find(|rule| {
+                rule.limit == 0 || !parse_duration(&rule.period).is_ok_and(|p| !p.is_zero())
+            })
+        {
+            return Err(InvalidConfigError::RateLimitRule(rule.name.clone()));
+        }
+
+        #[cfg(feature = "sessions")]
+        if let Some(session) = &self.session {
+            let expires_at = session
+                .expires_after()
+                .ok()
+                .and_then(|duration| jiff::Zoned::now().checked_add(duration).ok());
+
+            if expires_at.is_none() {
+                return Err(InvalidConfigError::SessionExpiresAfter(
+                    session.expires_after.clone().unwrap_or_default(),
+                ));
+            }
+        }
+
+        #[cfg(feature = "sessions")]
+        if let Some(status) = self
+            .csrf
+            .as_ref()
+            .and_then(|csrf| csrf.status)
+            .filter(|s| ![403, 419].contains(s))
+        {
+            return Err(InvalidConfigError::CsrfStatus(status));
+        }
+
+        Ok(())
     }
 }
 
+#[cfg(test)]
+impl Config {
+    /// Creates a config for tests with a minimal server section, followed by the given TOML, which
+    /// may continue the server section or add other sections. With a database feature, an in-memory
+    /// SQLite database with a single connection is configured before the server section.
+    pub(crate) fn for_tests(toml: &str) -> Self {
+        #[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+        let database = "[database]\nurl = \":memory:\"\nmax_size = 1\n";
+        #[cfg(not(any(feature = "sqlite", feature = "postgres", feature = "mysql")))]
+        let database = "";
+
+        toml::from_str(&format!(
+            "{database}[server]\nbase_url = \"localhost\"\nhost = \"127.0.0.1\"\nport = 0\nnum_threads = 1\n{toml}"
+        ))
+        .unwrap()
+    }
+}
+
+/// Represents an invalid combination of settings in the config.
+#[derive(thiserror::Error, Debug)]
+pub enum InvalidConfigError {
+    #[error("cors: `allowed_origins` cannot contain `*` if `allow_credentials` is enabled")]
+    WildcardOriginWithCredentials,
+    #[error("server: `timeout_status` must be 503 or 504, not {0}")]
+    TimeoutStatus(u16),
+    #[error(
+        "rate_limit: rule `{0}` must have a `limit` of at least 1 and a non-zero `period` such as \"1m\""
+    )]
+    RateLimitRule(String),
+    #[cfg(feature = "sessions")]
+    #[error("csrf: `status` must be 403 or 419, not {0}")]
+    CsrfStatus(u16),
+    #[cfg(feature = "sessions")]
+    #[error("session: `expires_after` must be a duration such as \"7d\", not \"{0}\"")]
+    SessionExpiresAfter(String),
+}
+
 /// Represents an error that occurred during duration parsing.
 #[derive(thiserror::Error, Debug)]
 pub enum ParseDurationError {
@@ -84,7 +535,7 @@ pub enum ParseDurationError {
 
 /// Parses a duration string into a Duration struct.
 ///
-/// Examples: "30m" for 30 minutes and "7d" for 7 days.
+/// Examples: "10s" for 10 seconds, "30m" for 30 minutes, "12h" for 12 hours and "7d" for 7 days.
 pub fn parse_duration(value: &str) -> Result<Duration, ParseDurationError> {
     if value.len() < 2 {
         return Err(ParseDurationError::InvalidFormat);
@@ -93,9 +544,14 @@ pub fn parse_duration(value: &str) -> Result<Duration, ParseDurationError> {
     let (value, unit) = value.split_at(value.len() - 1);
     let value = u64::from_str(value)?;
 
-    match unit {
-        "m" => Ok(Duration::from_secs(value * 60)),
-        "d" => Ok(Duration::from_secs(value * 24 * 60 * 60)),
-        _ => Err(ParseDurationError::InvalidFormat),
-    }
+    let secs = match unit {
+        "s" => Some(value),
+        "m" => value.checked_mul(60),
+        "h" => value.checked_mul(60 * 60),
+        "d" => value.checked_mul(24 * 60 * 60),
+        _ => None,
+    };
+
+    secs.map(Duration::from_secs)
+        .ok_or(ParseDurationError::InvalidFormat)
 }
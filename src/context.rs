@@ -1,23 +1,29 @@
-use std::{
-    sync::{Arc, Mutex},
-    time::Duration,
-};
+use std::sync::{Arc, Mutex};
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+use std::time::Duration;
 
-use crate::Config;
+use crate::{router::NamedRoutes, Config};
 
 #[derive(Clone)]
 pub struct Context {
     pub config: Config,
+    pub routes: NamedRoutes,
     #[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
     pub db: diesel::r2d2::Pool<diesel::r2d2::ConnectionManager<crate::db::DatabaseConnection>>,
     #[cfg(feature = "sessions")]
     pub session_store: Option<Arc<Mutex<Box<dyn crate::session::SessionStore + Send + Sync>>>>,
+    pub rate_limit_store: Option<Arc<dyn crate::rate_limit::RateLimitStore + Send + Sync>>,
+    pub metrics: Arc<crate::metrics::Registry>,
+    pub health: crate::health::Health,
+    pub error_handler: Option<Arc<Box<dyn crate::http::handler::Handler + Send + Sync>>>,
+    pub(crate) access_log: Option<Arc<Mutex<crate::http::access_log::AccessLogFile>>>,
 }
 
 impl Context {
     pub fn new(config: Config) -> Self {
         Self {
             config: config.clone(),
+            routes: NamedRoutes::default(),
             #[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
             db: {
                 let mut pool = diesel::r2d2::Pool::builder()
@@ -42,6 +48,11 @@ impl Context {
             },
             #[cfg(feature = "sessions")]
             session_store: None,
+            rate_limit_store: None,
+            metrics: Arc::default(),
+            health: crate::health::Health::default(),
+            error_handler: None,
+            access_log: None,
         }
     }
 }
@@ -0,0 +1,197 @@
+//! Protection against cross-site request forgery.
+//!
+//! The [crate::middleware::verify_csrf_token] middleware issues a token per session and verifies
+//! it on requests with unsafe methods. Forms submit the token in a hidden field and JavaScript
+//! clients in the `X-CSRF-Token` header.
+//!
+//! ```
+//! use snx::{csrf, request::Request, template, Html};
+//!
+//! fn edit(req: &Request) -> Html {
+//!     Html(template! {
+//!         <form method="post" action="/posts/1">
+//!             {csrf::field(req)}
+//!             <button type="submit">save</button>
+//!         </form>
+//!     })
+//! }
+//! ```
+
+use rand::Rng;
+
+use crate::request::Request;
+
+/// Name of the form field containing the token.
+pub const FIELD: &str = "_token";
+
+/// Name of the header containing the token.
+pub const HEADER: &str = "X-CSRF-Token";
+
+/// Name of the cookie containing the token in double-submit cookie mode.
+pub const COOKIE: &str = "XSRF-TOKEN";
+
+/// Key of the session data containing the token.
+pub(crate) const SESSION_KEY: &str = "_csrf_token";
+
+/// The token of the current request, which must be submitted with requests with unsafe methods.
+///
+/// Added to the extensions of a request by the [crate::middleware::verify_csrf_token]
+/// middleware.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct CsrfToken(pub(crate) String);
+
+impl CsrfToken {
+    /// Gets the token as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Gets the token for the current request.
+///
+/// Returns `None` if the request was not handled by the CSRF middleware.
+pub fn token(req: &Request) -> Option<String> {
+    req.extensions()
+        .get::<CsrfToken>()
+        .map(|token| token.as_str().to_string())
+}
+
+/// Renders a hidden form field containing the token.
+pub fn field(req: &Request) -> String {
+    format!(
+        "<input type=\"hidden\" name=\"{FIELD}\" value=\"{}\">",
+        token(req).unwrap_or_default()
+    )
+}
+
+/// Renders a meta tag containing the token, which can be read by JavaScript to set the
+/// `X-CSRF-Token` header.
+pub fn meta(req: &Request) -> String {
+    format!(
+        "<meta name=\"csrf-token\" content=\"{}\">",
+        token(req).unwrap_or_default()
+    )
+}
+
+/// Generates a new random token.
+pub(crate) fn generate() -> String {
+    crate::http::hex(&rand::rng().random::<[u8; 32]>())
+}
+
+/// Returns whether a token was generated by snx, guarding against malformed cookies.
+pub(crate) fn is_valid(token: &str) -> bool {
+    token.len() == 64 && token.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Gets the token submitted with a request, either in the `X-CSRF-Token` header or in the form
+/// field of an urlencoded or multipart form body.
+pub(crate) fn submitted(req: &Request) -> Option<String> {
+    if let Some(token) = req.headers().get(HEADER) {
+        return Some(token.trim().to_string());
+    }
+
+    let content_type = req.headers().get("content-type")?.to_lowercase();
+    let body = String::from_utf8_lossy(req.bytes());
+
+    if content_type.starts_with("application/x-www-form-urlencoded") {
+        return body
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == FIELD)
+            .map(|(_, value)| value.to_string());
+    }
+
+    if content_type.starts_with("multipart/form-data") {
+        let (_, rest) = body.split_once(&format!("name=\"{FIELD}\""))?;
+        let (_, value) = rest.split_once("\r\n\r\n")?;
+
+        return value.split("\r\n").next().map(str::to_string);
+    }
+
+    None
+}
+
+/// Compares two tokens in constant time, so the token cannot be guessed by timing comparisons.
+pub(crate) fn verify(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Method;
+
+    fn post(content_type: &str, body: &str) -> Request {
+        Request::builder()
+            .method(Method::Post)
+            .header("Content-Type", content_type)
+            .body(body.as_bytes().to_vec())
+            .build()
+    }
+
+    #[test]
+    fn gets_the_token_from_the_header() {
+        let req = Request::builder()
+            .method(Method::Post)
+            .header(HEADER, " abc ")
+            .build();
+
+        assert_eq!(submitted(&req).as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn gets_the_token_from_urlencoded_forms() {
+        let req = post(
+            "application/x-www-form-urlencoded; charset=utf-8",
+            "title=hello&_token=abc&body=world",
+        );
+        assert_eq!(submitted(&req).as_deref(), Some("abc"));
+
+        let req = post("application/x-www-form-urlencoded", "x_token=abc");
+        assert_eq!(submitted(&req), None);
+    }
+
+    #[test]
+    fn gets_the_token_from_multipart_forms() {
+        let req = post(
+            "multipart/form-data; boundary=XyZ",
+            "--XyZ\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+             hello\r\n\
+             --XyZ\r\n\
+             Content-Disposition: form-data; name=\"_token\"\r\n\r\n\
+             abc\r\n\
+             --XyZ--\r\n",
+        );
+
+        assert_eq!(submitted(&req).as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn ignores_tokens_in_other_bodies() {
+        assert_eq!(
+            submitted(&post("application/json", "{\"_token\":\"abc\"}")),
+            None
+        );
+        assert_eq!(
+            submitted(&Request::builder().body(b"_token=abc".to_vec()).build()),
+            None
+        );
+    }
+
+    #[test]
+    fn verifies_tokens() {
+        let token = generate();
+
+        assert!(is_valid(&token));
+        assert!(verify(&token, &token));
+        assert!(!verify(&token, &generate()));
+        assert!(!verify(&token, &token[1..]));
+        assert!(!is_valid("abc"));
+    }
+}
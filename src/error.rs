@@ -0,0 +1,459 @@
+use std::fmt;
+
+use crate::{
+    html,
+    http::escape_json,
+    response::{IntoResponse, Response},
+    validation::ValidationErrors,
+    HeaderMap, StatusCode,
+};
+
+/// An error that can be returned from handlers, with a status code, a message that is safe to
+/// show to clients and an optional internal source that is only logged.
+///
+/// Common errors convert into it, so handlers can use `?`.
+///
+/// ```
+/// use snx::{request::Request, Context, Error, StatusCode};
+///
+/// fn show(_: Context, req: Request) -> Result<String, Error> {
+///     let id = req
+///         .params
+///         .get("id")
+///         .ok_or(Error::new(StatusCode::BadRequest, "missing id"))?;
+///
+///     Ok(format!("post {id}"))
+/// }
+/// ```
+pub struct Error {
+    status: StatusCode,
+    message: String,
+    source: Option<anyhow::Error>,
+    format: ErrorFormat,
+    validation_errors: Option<ValidationErrors>,
+    headers: HeaderMap,
+}
+
+/// The format in which an [Error] is rendered.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum ErrorFormat {
+    /// The message as plain text.
+    #[default]
+    Text,
+    /// A JSON object with the message, e.g. `{"message":"Not Found"}`.
+    Json,
+    /// A minimal HTML page with the status and message.
+    Html,
+    /// Problem details as defined in RFC 9457, with the message as detail.
+    #[cfg(feature = "json")]
+    Problem,
+}
+
+impl Error {
+    /// Creates an error with a status code and a public message.
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            source: None,
+            format: ErrorFormat::default(),
+            validation_errors: None,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Creates an error with a status code, using its reason phrase as the public message.
+    ///
+    /// ```
+    /// use snx::{Error, StatusCode};
+    ///
+    /// let error = Error::from_status(StatusCode::NotFound);
+    ///
+    /// assert_eq!(error.message(), "Not Found");
+    /// ```
+    pub fn from_status(status: StatusCode) -> Self {
+        let message = status.canonical_reason();
+
+        Self::new(status, message)
+    }
+
+    /// Creates an internal server error caused by the given source, which is logged but not shown
+    /// to clients.
+    pub fn internal(source: impl Into<anyhow::Error>) -> Self {
+        Self::from_status(StatusCode::InternalServerError).with_source(source)
+    }
+
+    /// Sets the internal source of the error.
+    pub fn with_source(mut self, source: impl Into<anyhow::Error>) -> Self {
+        self.source = Some(source.into());
+
+        self
+    }
+
+    /// Renders the error in the given format.
+    ///
+    /// ```
+    /// use snx::{error::ErrorFormat, response::IntoResponse, Error, StatusCode};
+    ///
+    /// let res = Error::from_status(StatusCode::NotFound)
+    ///     .format(ErrorFormat::Json)
+    ///     .into_response();
+    ///
+    /// assert_eq!(res.headers().get("content-type").unwrap(), "application/json");
+    /// ```
+    pub fn format(mut self, format: ErrorFormat) -> Self {
+        self.format = format;
+
+        self
+    }
+
+    /// Sets the per-field validation errors, which are rendered along with the message.
+    pub fn with_validation_errors(mut self, errors: ValidationErrors) -> Self {
+        self.validation_errors = Some(errors);
+
+        self
+    }
+
+    /// Adds a header to the response, e.g. `Location` or `Retry-After`.
+    ///
+    /// ```
+    /// use snx::{response::IntoResponse, Error, StatusCode};
+    ///
+    /// let res = Error::from_status(StatusCode::ServiceUnavailable)
+    ///     .header("Retry-After", "120")
+    ///     .into_response();
+    ///
+    /// assert_eq!(res.headers().get("retry-after").unwrap(), "120");
+    /// ```
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name, value);
+
+        self
+    }
+
+    /// Gets the status code of the error.
+    pub fn status(&self) -> &StatusCode {
+        &self.status
+    }
+
+    /// Gets the public message of the error.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Gets the internal source of the error, which is also returned as a trait object by
+    /// [std::error::Error::source].
+    pub fn internal_source(&self) -> Option<&anyhow::Error> {
+        self.source.as_ref()
+    }
+
+    /// Gets the per-field validation errors of the error.
+    pub fn validation_errors(&self) -> Option<&ValidationErrors> {
+        self.validation_errors.as_ref()
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Error")
+            .field("status", &self.status)
+            .field("message", &self.message)
+            .field("source", &self.source)
+            .field("format", &self.format)
+            .field("validation_errors", &self.validation_errors)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{}: {source:#}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_ref().map(|source| source.as_ref())
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        if let Some(source) = &self.source {
+            match u16::from(self.status.clone()) >= 500 {
+                true => tracing::error!("{}: {source:#}", self.message),
+                false => tracing::debug!("{}: {source:#}", self.message),
+            }
+        }
+
+        let (content_type, body) = match self.format {
+            ErrorFormat::Text => {
+                let mut body = self.message.clone();
+                for (_, messages) in self.validation_errors.iter().flat_map(|e| e.iter()) {
+                    for message in messages {
+                        body.push_str(&format!("\n{message}"));
+                    }
+                }
+
+                ("text/plain; charset=utf-8", body)
+            }
+            ErrorFormat::Json => (
+                "application/json",
+                match &self.validation_errors {
+                    Some(errors) => format!(
+                        "{{\"message\":\"{}\",\"errors\":{}}}",
+                        escape_json(&self.message),
+                        errors.to_json()
+                    ),
+                    None => format!("{{\"message\":\"{}\"}}", escape_json(&self.message)),
+                },
+            ),
+            ErrorFormat::Html => {
+                let errors = self
+                    .validation_errors
+                    .iter()
+                    .flat_map(|e| e.iter())
+                    .flat_map(|(_, messages)| messages)
+                    .map(|message| format!("<li>{}</li>\n", html::escape(message)))
+                    .collect::<String>();
+                let errors = match errors.is_empty() {
+                    true => errors,
+                    false => format!("<ul>\n{errors}</ul>\n"),
+                };
+
+                (
+                    "text/html; charset=utf-8",
+                    format!(
+                        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{status}</title>\n</head>\n<body>\n<h1>{status}</h1>\n<p>{}</p>\n{errors}</body>\n</html>\n",
+                        html::escape(&self.message),
+                        status = format!("{} {}", self.status, self.status.canonical_reason()),
+                    ),
+                )
+            }
+            #[cfg(feature = "json")]
+            ErrorFormat::Problem => {
+                let mut problem = crate::Problem::new(self.status.clone());
+                if self.message != self.status.canonical_reason() {
+                    problem = problem.detail(&self.message);
+                }
+                if let Some(errors) = &self.validation_errors {
+                    problem = problem.extension("errors", errors);
+                }
+
+                let mut res = problem.into_response();
+                apply_headers(&mut res, &self.headers);
+
+                return res;
+            }
+        };
+
+        let mut res = Response::new(body);
+        *res.status_mut() = self.status;
+        res.headers_mut().insert("Content-Type", content_type);
+        apply_headers(&mut res, &self.headers);
+        #[cfg(feature = "json")]
+        res.extensions_mut().insert(ErrorDetails {
+            message: self.message,
+            validation_errors: self.validation_errors,
+        });
+
+        res
+    }
+}
+
+/// The public details of the [Error] a response was created from, which are added to its
+/// extensions so the response can be rendered as problem details.
+#[cfg(feature = "json")]
+#[derive(Debug)]
+pub(crate) struct ErrorDetails {
+    pub(crate) message: String,
+    pub(crate) validation_errors: Option<ValidationErrors>,
+}
+
+fn apply_headers(res: &mut Response, headers: &HeaderMap) {
+    for (name, values) in headers.iter() {
+        for value in values {
+            res.headers_mut().insert(name, value);
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(status: StatusCode) -> Self {
+        Self::from_status(status)
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(source: anyhow::Error) -> Self {
+        Self::internal(source)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<crate::InvalidJsonBodyError> for Error {
+    fn from(err: crate::InvalidJsonBodyError) -> Self {
+        Self::new(StatusCode::BadRequest, err.to_string())
+    }
+}
+
+#[cfg(feature = "forms")]
+impl From<crate::InvalidFormBodyError> for Error {
+    fn from(err: crate::InvalidFormBodyError) -> Self {
+        Self::new(StatusCode::BadRequest, err.to_string())
+    }
+}
+
+/// Responds with `422 Unprocessable Content` and the errors per field as JSON.
+impl From<ValidationErrors> for Error {
+    fn from(errors: ValidationErrors) -> Self {
+        Self::new(
+            StatusCode::UnprocessableContent,
+            "The given data was invalid.",
+        )
+        .with_validation_errors(errors)
+        .format(ErrorFormat::Json)
+    }
+}
+
+#[cfg(feature = "sessions")]
+impl From<crate::session::Error> for Error {
+    fn from(err: crate::session::Error) -> Self {
+        Self::internal(err)
+    }
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+impl From<diesel::result::Error> for Error {
+    fn from(err: diesel::result::Error) -> Self {
+        match err {
+            diesel::result::Error::NotFound => Self::from_status(StatusCode::NotFound),
+            err => Self::internal(err),
+        }
+    }
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+impl From<diesel::r2d2::PoolError> for Error {
+    fn from(err: diesel::r2d2::PoolError) -> Self {
+        Self::from_status(StatusCode::ServiceUnavailable).with_source(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(error: Error) -> (Response, String) {
+        let res = error.into_response();
+        let body = String::from_utf8(res.body().clone().unwrap().into_bytes().unwrap()).unwrap();
+
+        (res, body)
+    }
+
+    fn validation_errors() -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        errors.add("title", "The title is required.");
+        errors.add("body", "The body must be <b>longer</b>.");
+
+        errors
+    }
+
+    #[test]
+    fn renders_text() {
+        let (res, body) =
+            render(Error::new(StatusCode::BadRequest, "missing id").header("X-Reason", "id"));
+
+        assert_eq!(*res.status(), StatusCode::BadRequest);
+        assert_eq!(
+            res.headers().get("content-type").as_deref(),
+            Some("text/plain; charset=utf-8")
+        );
+        assert_eq!(res.headers().get("x-reason").as_deref(), Some("id"));
+        assert_eq!(body, "missing id");
+
+        let (_, body) = render(
+            Error::from_status(StatusCode::UnprocessableContent)
+                .with_validation_errors(validation_errors()),
+        );
+
+        assert!(body.starts_with("Unprocessable Content\n"));
+        assert!(body.contains("\nThe title is required."));
+        assert!(body.contains("\nThe body must be <b>longer</b>."));
+    }
+
+    #[test]
+    fn renders_json() {
+        let (res, body) =
+            render(Error::new(StatusCode::NotFound, "no \"post\"").format(ErrorFormat::Json));
+
+        assert_eq!(*res.status(), StatusCode::NotFound);
+        assert_eq!(
+            res.headers().get("content-type").as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(body, "{\"message\":\"no \\\"post\\\"\"}");
+
+        let mut errors = ValidationErrors::new();
+        errors.add("title", "The title is required.");
+        let (res, body) = render(errors.into());
+
+        assert_eq!(*res.status(), StatusCode::UnprocessableContent);
+        assert_eq!(
+            body,
+            "{\"message\":\"The given data was invalid.\",\"errors\":{\"title\":[\"The title is required.\"]}}"
+        );
+    }
+
+    #[test]
+    fn renders_html() {
+        let (res, body) = render(
+            Error::new(StatusCode::Forbidden, "<script>alert(1)</script>")
+                .with_validation_errors(validation_errors())
+                .format(ErrorFormat::Html),
+        );
+
+        assert_eq!(*res.status(), StatusCode::Forbidden);
+        assert_eq!(
+            res.headers().get("content-type").as_deref(),
+            Some("text/html; charset=utf-8")
+        );
+        assert!(body.contains("<title>403 Forbidden</title>"));
+        assert!(body.contains("<p>&lt;script&gt;alert(1)&lt;/script&gt;</p>"));
+        assert!(body.contains("<li>The body must be &lt;b&gt;longer&lt;/b&gt;.</li>"));
+        assert!(!body.contains("<script>"));
+    }
+
+    #[test]
+    fn hides_internal_sources() {
+        let (res, body) = render(Error::internal(anyhow::anyhow!("connection refused")));
+
+        assert_eq!(*res.status(), StatusCode::InternalServerError);
+        assert_eq!(body, "Internal Server Error");
+
+        let error = Error::internal(anyhow::anyhow!("connection refused"));
+        assert_eq!(
+            error.internal_source().unwrap().to_string(),
+            "connection refused"
+        );
+        assert!(std::error::Error::source(&error).is_some());
+    }
+
+    #[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+    #[test]
+    fn converts_diesel_errors() {
+        assert_eq!(
+            *Error::from(diesel::result::Error::NotFound).status(),
+            StatusCode::NotFound
+        );
+        assert_eq!(
+            *Error::from(diesel::result::Error::RollbackTransaction).status(),
+            StatusCode::InternalServerError
+        );
+    }
+}
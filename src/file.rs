@@ -0,0 +1,153 @@
+use std::{io, path::Path};
+
+use crate::response::{Body, IntoResponse, Response};
+
+/// Represents a file response, either displayed inline or downloaded as an attachment.
+///
+/// ```no_run
+/// use snx::{request::Request, Context, File};
+///
+/// fn export(_: Context, _: Request) -> File {
+///     File::open("./reports/2025.csv").unwrap().attachment()
+/// }
+/// ```
+pub struct File {
+    body: Body,
+    filename: Option<String>,
+    content_type: String,
+    attachment: bool,
+}
+
+impl File {
+    /// Opens a file from disk which will be streamed to the client.
+    ///
+    /// The content type is guessed from the extension of the file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+
+        Ok(Self {
+            body: Body::from_seekable(file, len),
+            filename: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string()),
+            content_type: guess_content_type(path),
+            attachment: false,
+        })
+    }
+
+    /// Creates a file from a buffer of bytes, e.g. a report generated in memory.
+    ///
+    /// The content type is guessed from the extension of the filename.
+    ///
+    /// ```
+    /// use snx::File;
+    ///
+    /// let file = File::from_bytes("report.csv", b"id,title\n1,hello world".to_vec()).attachment();
+    /// ```
+    pub fn from_bytes(filename: &str, bytes: Vec<u8>) -> Self {
+        Self {
+            body: Body::from(bytes),
+            filename: Some(filename.to_string()),
+            content_type: guess_content_type(Path::new(filename)),
+            attachment: false,
+        }
+    }
+
+    /// Marks the file as an attachment, which makes browsers download it instead of displaying
+    /// it.
+    pub fn attachment(mut self) -> Self {
+        self.attachment = true;
+
+        self
+    }
+
+    /// Sets the filename suggested to the client.
+    ///
+    /// ```
+    /// use snx::{response::IntoResponse, File};
+    ///
+    /// let res = File::from_bytes("report.csv", vec![])
+    ///     .filename("verslag über 2025.csv")
+    ///     .attachment()
+    ///     .into_response();
+    ///
+    /// assert_eq!(
+    ///     res.headers().get("Content-Disposition").unwrap(),
+    ///     "attachment; filename=\"verslag _ber 2025.csv\"; filename*=UTF-8''verslag%20%C3%BCber%202025.csv"
+    /// );
+    /// ```
+    pub fn filename(mut self, filename: &str) -> Self {
+        self.filename = Some(filename.to_string());
+
+        self
+    }
+
+    /// Overrides the guessed content type.
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.content_type = content_type.to_string();
+
+        self
+    }
+}
+
+impl IntoResponse for File {
+    fn into_response(self) -> Response {
+        let mut res = Response::new(self.body);
+
+        res.headers_mut().insert("Content-Type", &self.content_type);
+
+        let disposition = if self.attachment {
+            "attachment"
+        } else {
+            "inline"
+        };
+        res.headers_mut().insert(
+            "Content-Disposition",
+            &match self.filename {
+                Some(filename) => content_disposition(disposition, &filename),
+                None => disposition.to_string(),
+            },
+        );
+
+        res
+    }
+}
+
+/// Guesses the content type of a file based on its extension.
+fn guess_content_type(path: &Path) -> String {
+    mime_guess::from_path(path)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string()
+}
+
+/// Builds a `Content-Disposition` header value with an ASCII fallback filename and an RFC 8187
+/// encoded filename, as recommended by RFC 6266.
+fn content_disposition(disposition: &str, filename: &str) -> String {
+    let fallback = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' && c != '%' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+
+    if fallback == filename {
+        return format!("{disposition}; filename=\"{filename}\"");
+    }
+
+    let mut encoded = String::with_capacity(filename.len() * 3);
+    for byte in filename.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => encoded.push(byte as char),
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    format!("{disposition}; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
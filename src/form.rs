@@ -0,0 +1,20 @@
+#[derive(Debug, Clone)]
+pub struct InvalidFormBodyError {
+    message: String,
+}
+
+impl std::error::Error for InvalidFormBodyError {}
+
+impl std::fmt::Display for InvalidFormBodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<serde_urlencoded::de::Error> for InvalidFormBodyError {
+    fn from(value: serde_urlencoded::de::Error) -> Self {
+        Self {
+            message: value.to_string(),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Health and readiness endpoints for orchestrators.
+//!
+//! The [liveness] handler reports that the process is able to handle requests, the [readiness]
+//! handler additionally checks the database pool, the session store and the custom checks of the
+//! application, and reports unready once the server is shutting down.
+//!
+//! ```
+//! use snx::{health, router::Router};
+//!
+//! let router = Router::builder("http://localhost:3000")
+//!     .get("/healthz", health::liveness)
+//!     .get("/readyz", health::readiness)
+//!     .build()
+//!     .unwrap();
+//! ```
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{http::escape_json, request::Request, response::Response, Context, StatusCode};
+
+/// The maximum time to wait for a database connection during a readiness check.
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+const DATABASE_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A custom readiness check, which returns an error message if the dependency it checks is not
+/// ready.
+pub type Check = Arc<Box<dyn Fn(&Context) -> Result<(), String> + Send + Sync>>;
+
+/// The readiness checks of an application and whether it is shutting down.
+#[derive(Clone, Default)]
+pub struct Health {
+    checks: Vec<(String, Check)>,
+    shutting_down: Arc<AtomicBool>,
+}
+
+impl Health {
+    /// Adds a custom readiness check.
+    ///
+    /// ```
+    /// use snx::health::Health;
+    ///
+    /// let health = Health::default().check("cache", |_| Ok(()));
+    /// ```
+    pub fn check(
+        mut self,
+        name: &str,
+        check: impl Fn(&Context) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.checks
+            .push((name.to_string(), Arc::new(Box::new(check))));
+
+        self
+    }
+
+    /// Returns whether the server is shutting down.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// Marks the server as shutting down, after which it reports unready.
+    pub(crate) fn shut_down(&self) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Debug for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Health")
+            .field(
+                "checks",
+                &self.checks.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            )
+            .field("shutting_down", &self.is_shutting_down())
+            .finish()
+    }
+}
+
+/// The outcome of a single check.
+struct Outcome {
+    name: String,
+    result: Result<(), String>,
+    duration: Duration,
+}
+
+impl Outcome {
+    /// Runs a check and measures how long it took.
+    fn run(name: &str, check: impl FnOnce() -> Result<(), String>) -> Self {
+        let now = Instant::now();
+        let result = check();
+
+        Self {
+            name: name.to_string(),
+            result,
+            duration: now.elapsed(),
+        }
+    }
+
+    /// Renders the outcome as JSON. Errors are left out, as they can contain hostnames, usernames
+    /// or other details of the infrastructure and the endpoint is usually not authenticated.
+    fn to_json(&self) -> String {
+        let status = match &self.result {
+            Ok(()) => "ok",
+            Err(_) => "error",
+        };
+
+        format!(
+            "\"{}\":{{\"status\":\"{status}\",\"duration_ms\":{:.3}}}",
+            escape_json(&self.name),
+            self.duration.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// Handler that reports whether the process is alive, which is always the case if it responds.
+///
+/// Responds with `{"status":"ok"}`.
+pub fn liveness(_: Context, _: Request) -> Response {
+    json_response(StatusCode::OK, "{\"status\":\"ok\"}".to_string())
+}
+
+/// Handler that reports whether the application is ready to handle requests.
+///
+/// Checks whether a database connection can be checked out of the pool, whether the session store
+/// is reachable using `SessionStore::ping` and runs the custom checks of the application. Responds
+/// with 200 if every check passed and with 503 if one of them failed or the server is shutting
+/// down, with the status and duration of each check, e.g.
+/// `{"status":"ready","checks":{"database":{"status":"ok","duration_ms":0.412}}}`. The errors of
+/// failed checks are logged instead of included in the response.
+pub fn readiness(ctx: Context, _: Request) -> Response {
+    let mut outcomes = vec![];
+
+    #[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+    outcomes.push(Outcome::run("database", || {
+        ctx.db
+            .get_timeout(DATABASE_CHECK_TIMEOUT)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }));
+
+    #[cfg(feature = "sessions")]
+    if let Some(store) = &ctx.session_store {
+        outcomes.push(Outcome::run("session_store", || {
+            store
+                .lock()
+                .map_err(|err| err.to_string())?
+                .ping()
+                .map_err(|err| err.to_string())
+        }));
+    }
+
+    for (name, check) in &ctx.health.checks {
+        outcomes.push(Outcome::run(name, || check(&ctx)));
+    }
+
+    for outcome in &outcomes {
+        if let Err(err) = &outcome.result {
+            tracing::warn!("readiness check `{}` failed: {err}", outcome.name);
+        }
+    }
+
+    let ready =
+        !ctx.health.is_shutting_down() && outcomes.iter().all(|outcome| outcome.result.is_ok());
+
+    let checks = outcomes
+        .iter()
+        .map(Outcome::to_json)
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let body = format!(
+        "{{\"status\":\"{}\",\"shutting_down\":{},\"checks\":{{{checks}}}}}",
+        if ready { "ready" } else { "unready" },
+        ctx.health.is_shutting_down(),
+    );
+
+    match ready {
+        true => json_response(StatusCode::OK, body),
+        false => json_response(StatusCode::ServiceUnavailable, body),
+    }
+}
+
+fn json_response(status: StatusCode, body: String) -> Response {
+    let mut res = Response::new(body);
+    *res.status_mut() = status;
+    res.headers_mut().insert("Content-Type", "application/json");
+    res.headers_mut().insert("Cache-Control", "no-store");
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn check(ctx: Context) -> (StatusCode, String) {
+        let res = readiness(ctx, Request::builder().build());
+        let body = String::from_utf8(res.body().clone().unwrap().into_bytes().unwrap()).unwrap();
+
+        assert_eq!(
+            res.headers().get("content-type").as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(
+            res.headers().get("cache-control").as_deref(),
+            Some("no-store")
+        );
+
+        (res.status().clone(), body)
+    }
+
+    #[test]
+    fn reports_liveness() {
+        let res = liveness(
+            Context::new(Config::for_tests("")),
+            Request::builder().build(),
+        );
+
+        assert_eq!(*res.status(), StatusCode::OK);
+        assert_eq!(
+            res.body().clone().unwrap().into_bytes().unwrap(),
+            b"{\"status\":\"ok\"}"
+        );
+    }
+
+    #[test]
+    fn reports_ready_if_every_check_passes() {
+        let mut ctx = Context::new(Config::for_tests(""));
+        ctx.health = Health::default().check("cache", |_| Ok(()));
+
+        let (status, body) = check(ctx);
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.starts_with("{\"status\":\"ready\",\"shutting_down\":false,\"checks\":{"));
+        assert!(body.contains("\"cache\":{\"status\":\"ok\",\"duration_ms\":"));
+        assert!(body.ends_with("}}}"));
+    }
+
+    #[test]
+    fn reports_unready_if_a_check_fails() {
+        let mut ctx = Context::new(Config::for_tests(""));
+        ctx.health = Health::default()
+            .check("cache", |_| Ok(()))
+            .check("queue", |_| Err("connection \"refused\"".to_string()));
+
+        let (status, body) = check(ctx);
+
+        assert_eq!(status, StatusCode::ServiceUnavailable);
+        assert!(body.starts_with("{\"status\":\"unready\",\"shutting_down\":false,"));
+        assert!(body.contains("\"cache\":{\"status\":\"ok\","));
+        assert!(body.contains("\"queue\":{\"status\":\"error\",\"duration_ms\":"));
+        assert!(!body.contains("refused"));
+    }
+
+    #[test]
+    fn reports_unready_once_shutting_down() {
+        let ctx = Context::new(Config::for_tests(""));
+        ctx.health.shut_down();
+
+        let (status, body) = check(ctx.clone());
+
+        assert!(ctx.health.is_shutting_down());
+        assert_eq!(status, StatusCode::ServiceUnavailable);
+        assert!(body.starts_with("{\"status\":\"unready\",\"shutting_down\":true,"));
+    }
+
+    #[cfg(feature = "sessions")]
+    #[test]
+    fn pings_the_session_store() {
+        use std::sync::Mutex;
+
+        use crate::session::{self, MemorySessionStore, Session, SessionStore};
+
+        /// A session store whose server cannot be reached.
+        struct UnreachableStore;
+
+        impl SessionStore for UnreachableStore {
+            fn create(&mut self, _: Session) -> Result<(), session::Error> {
+                Ok(())
+            }
+
+            fn load(&mut self, _: u128) -> Result<Option<Session>, session::Error> {
+                Ok(None)
+            }
+
+            fn save(&mut self, _: &Session) -> Result<(), session::Error> {
+                Ok(())
+            }
+
+            fn delete(&mut self, _: u128) -> Result<(), session::Error> {
+                Ok(())
+            }
+
+            fn ping(&mut self) -> Result<(), session::Error> {
+                Err(session::Error::Poisoned)
+            }
+        }
+
+        let mut ctx = Context::new(Config::for_tests(""));
+        ctx.session_store = Some(Arc::new(Mutex::new(
+            Box::new(MemorySessionStore::default()),
+        )));
+        let (status, body) = check(ctx.clone());
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("\"session_store\":{\"status\":\"ok\","));
+
+        ctx.session_store = Some(Arc::new(Mutex::new(Box::new(UnreachableStore))));
+        let (status, body) = check(ctx);
+        assert_eq!(status, StatusCode::ServiceUnavailable);
+        assert!(body.contains("\"session_store\":{\"status\":\"error\","));
+    }
+}
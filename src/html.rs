@@ -13,3 +13,13 @@ impl IntoResponse for Html {
         res
     }
 }
+
+/// Escapes a string for use in HTML text and attribute values.
+pub(crate) fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
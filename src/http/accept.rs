@@ -0,0 +1,259 @@
+//! Parsing of the `Accept` header for content negotiation.
+
+/// A media range of an `Accept` header, e.g. `text/*;q=0.8`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MediaRange {
+    /// The type, which is `*` for any type.
+    pub type_: String,
+    /// The subtype, which is `*` for any subtype.
+    pub subtype: String,
+    /// The parameters other than the quality, e.g. `level=1`.
+    pub params: Vec<(String, String)>,
+    /// The quality between 0 and 1, where 0 means not acceptable.
+    pub quality: f32,
+}
+
+impl MediaRange {
+    /// Returns whether the range matches a media type, e.g. `text/*` matches `text/html`.
+    ///
+    /// A range with parameters only matches media types with the same parameters.
+    ///
+    /// ```
+    /// use snx::accept;
+    ///
+    /// let ranges = accept::parse("text/*, text/plain;format=flowed");
+    ///
+    /// assert!(ranges[1].matches("text/html"));
+    /// assert!(!ranges[1].matches("application/json"));
+    /// assert!(ranges[0].matches("text/plain; format=flowed"));
+    /// assert!(!ranges[0].matches("text/plain"));
+    /// ```
+    pub fn matches(&self, media_type: &str) -> bool {
+        let (type_, subtype, params) = split(media_type);
+
+        (self.type_ == "*" || self.type_ == type_)
+            && (self.subtype == "*" || self.subtype == subtype)
+            && self.params.iter().all(|param| params.contains(param))
+    }
+
+    /// Gets how specific the range is, where `*/*` is the least specific and ranges with
+    /// parameters are the most specific.
+    fn specificity(&self) -> usize {
+        match (self.type_.as_str(), self.subtype.as_str()) {
+            ("*", _) => 0,
+            (_, "*") => 1,
+            _ if self.params.is_empty() => 2,
+            _ => 3,
+        }
+    }
+}
+
+/// Parses the value of an `Accept` header into media ranges, ordered from most to least
+/// preferred. Invalid ranges are skipped.
+///
+/// ```
+/// use snx::accept;
+///
+/// let ranges = accept::parse("text/html;q=0.9, application/json, */*;q=0.1");
+///
+/// assert_eq!(ranges[0].subtype, "json");
+/// assert_eq!(ranges[1].subtype, "html");
+/// assert_eq!(ranges[2].quality, 0.1);
+/// ```
+pub fn parse(value: &str) -> Vec<MediaRange> {
+    let mut ranges = value
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media_type = parts.next()?.to_lowercase();
+            let (type_, subtype) = media_type.split_once('/')?;
+
+            if type_.is_empty() || subtype.is_empty() || (type_ == "*" && subtype != "*") {
+                return None;
+            }
+
+            let mut quality = 1.0;
+            let mut params = vec![];
+
+            for param in parts {
+                let Some((key, value)) = param.split_once('=') else {
+                    continue;
+                };
+                let (key, value) = (key.trim().to_lowercase(), value.trim().trim_matches('"'));
+
+                match key.as_str() {
+                    "q" => {
+                        quality = value
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|quality| quality.is_finite())?
+                            .clamp(0.0, 1.0)
+                    }
+                    _ => params.push((key, value.to_string())),
+                }
+            }
+
+            Some(MediaRange {
+                type_: type_.to_string(),
+                subtype: subtype.to_string(),
+                params,
+                quality,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    ranges.sort_by(|a, b| {
+        b.quality
+            .total_cmp(&a.quality)
+            .then(b.specificity().cmp(&a.specificity()))
+    });
+
+    ranges
+}
+
+/// Gets the quality of a media type according to the most specific range that matches it, or 0
+/// if no range matches.
+pub fn quality(ranges: &[MediaRange], media_type: &str) -> f32 {
+    ranges
+        .iter()
+        .filter(|range| range.matches(media_type))
+        .max_by_key(|range| range.specificity())
+        .map(|range| range.quality)
+        .unwrap_or(0.0)
+}
+
+/// Picks the offered media type with the highest quality according to an `Accept` header. Ties
+/// are broken by the order of the offered types. A missing header accepts any media type.
+///
+/// ```
+/// use snx::accept;
+///
+/// let offered = ["text/html", "application/json"];
+///
+/// assert_eq!(
+///     accept::preferred(Some("application/json, text/*;q=0.5"), &offered),
+///     Some("application/json")
+/// );
+/// assert_eq!(accept::preferred(None, &offered), Some("text/html"));
+/// assert_eq!(accept::preferred(Some("image/png"), &offered), None);
+/// ```
+pub fn preferred<'a>(header: Option<&str>, offered: &[&'a str]) -> Option<&'a str> {
+    let ranges = parse(header.unwrap_or("*/*"));
+
+    offered
+        .iter()
+        .map(|media_type| (*media_type, quality(&ranges, media_type)))
+        .filter(|(_, quality)| *quality > 0.0)
+        .fold(
+            None,
+            |best: Option<(&str, f32)>, (media_type, quality)| match best {
+                Some((_, best_quality)) if best_quality >= quality => best,
+                _ => Some((media_type, quality)),
+            },
+        )
+        .map(|(media_type, _)| media_type)
+}
+
+/// Splits a media type into its type, subtype and parameters.
+fn split(media_type: &str) -> (String, String, Vec<(String, String)>) {
+    let mut parts = media_type.split(';').map(str::trim);
+    let media_type = parts.next().unwrap_or_default().to_lowercase();
+
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.trim().to_lowercase(),
+                value.trim().trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+
+    match media_type.split_once('/') {
+        Some((type_, subtype)) => (type_.to_string(), subtype.to_string(), params),
+        None => (media_type, String::new(), params),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_ranges_with_unparseable_quality() {
+        let ranges = parse("text/html;q=abc, text/plain;q=NaN, image/png;q=inf, application/json");
+
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].subtype, "json");
+        assert_eq!(preferred(Some("text/html;q=abc"), &["text/html"]), None);
+    }
+
+    #[test]
+    fn skips_invalid_ranges() {
+        let ranges = parse("html, */json, text/, /plain, , text/html");
+
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].subtype, "html");
+    }
+
+    #[test]
+    fn clamps_quality() {
+        let ranges = parse("text/html;q=2, text/plain;q=-1");
+
+        assert_eq!(ranges[0].quality, 1.0);
+        assert_eq!(ranges[1].quality, 0.0);
+    }
+
+    #[test]
+    fn sorts_by_quality_then_specificity() {
+        let ranges = parse("*/*, text/*, text/html;level=1, text/html, image/png;q=0.5");
+
+        let sorted = ranges
+            .iter()
+            .map(|range| format!("{}/{}", range.type_, range.subtype))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            sorted,
+            ["text/html", "text/html", "text/*", "*/*", "image/png"]
+        );
+        assert_eq!(ranges[0].params, [("level".into(), "1".into())]);
+    }
+
+    #[test]
+    fn uses_the_quality_of_the_most_specific_match() {
+        let ranges = parse("text/*;q=0.3, text/html;q=0.7, text/html;level=1, */*;q=0.5");
+
+        assert_eq!(quality(&ranges, "text/html;level=1"), 1.0);
+        assert_eq!(quality(&ranges, "text/html"), 0.7);
+        assert_eq!(quality(&ranges, "text/plain"), 0.3);
+        assert_eq!(quality(&ranges, "image/jpeg"), 0.5);
+        assert_eq!(quality(&parse("text/html"), "image/jpeg"), 0.0);
+    }
+
+    #[test]
+    fn matches_case_insensitively() {
+        let ranges = parse("Text/HTML");
+
+        assert!(ranges[0].matches("text/html"));
+        assert_eq!(preferred(Some("TEXT/*"), &["text/html"]), Some("text/html"));
+    }
+
+    #[test]
+    fn prefers_offers_by_quality_and_order() {
+        let offered = ["text/html", "application/json"];
+
+        assert_eq!(preferred(None, &offered), Some("text/html"));
+        assert_eq!(preferred(Some("*/*"), &offered), Some("text/html"));
+        assert_eq!(
+            preferred(Some("text/html;q=0.5, application/json"), &offered),
+            Some("application/json")
+        );
+        assert_eq!(
+            preferred(Some("*/*, text/html;q=0"), &offered),
+            Some("application/json")
+        );
+        assert_eq!(preferred(Some("text/html;q=0"), &offered), None);
+        assert_eq!(preferred(Some(""), &offered), None);
+    }
+}
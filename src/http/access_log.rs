@@ -0,0 +1,366 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use jiff::{tz::TimeZone, Timestamp};
+
+use crate::config::{AccessLogConfig, AccessLogFormat};
+
+use super::escape_json;
+
+/// The template of the compact format.
+const COMPACT_TEMPLATE: &str =
+    "{host} {remote_addr} \"{method} {path}\" {status} {bytes}B {latency_ms}ms";
+
+/// The template of the Common Log Format.
+const COMMON_TEMPLATE: &str =
+    "{remote_ip} - - [{time}] \"{method} {path} {protocol}\" {status} {bytes}";
+
+/// The template of the Combined Log Format.
+const COMBINED_TEMPLATE: &str = "{remote_ip} - - [{time}] \"{method} {path} {protocol}\" {status} {bytes} \"{referer}\" \"{user_agent}\"";
+
+/// The format of timestamps in the Common and Combined Log Format.
+const CLF_TIME_FORMAT: &str = "%d/%b/%Y:%H:%M:%S %z";
+
+/// The default maximum size of an access log file before it is rotated, 10 MiB.
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// The default amount of rotated access log files that are kept.
+const DEFAULT_MAX_FILES: usize = 5;
+
+/// A handled request, as it is written to the access log.
+pub struct Entry {
+    pub time: SystemTime,
+    pub host: Option<String>,
+    pub remote_addr: Option<String>,
+    pub remote_ip: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub route: Option<String>,
+    pub status: u16,
+    pub bytes: u64,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    pub latency: Duration,
+}
+
+impl Entry {
+    /// Gets the value of a template placeholder, or None if the placeholder is unknown.
+    fn field(&self, name: &str) -> Option<String> {
+        let or_dash = |value: &Option<String>| value.clone().unwrap_or("-".to_string());
+
+        Some(match name {
+            "time" => Timestamp::try_from(self.time)
+                .unwrap_or(Timestamp::UNIX_EPOCH)
+                .to_zoned(TimeZone::UTC)
+                .strftime(CLF_TIME_FORMAT)
+                .to_string(),
+            "host" => or_dash(&self.host),
+            "remote_addr" => or_dash(&self.remote_addr),
+            "remote_ip" => or_dash(&self.remote_ip),
+            "method" => self.method.clone(),
+            "path" => self.path.clone(),
+            "route" => or_dash(&self.route),
+            "protocol" => "HTTP/1.1".to_string(),
+            "status" => self.status.to_string(),
+            "bytes" => self.bytes.to_string(),
+            "referer" => or_dash(&self.referer),
+            "user_agent" => or_dash(&self.user_agent),
+            "request_id" => or_dash(&self.request_id),
+            "latency_us" => self.latency.as_micros().to_string(),
+            "latency_ms" => self.latency.as_millis().to_string(),
+            _ => return None,
+        })
+    }
+
+    /// Formats the entry as a line of the configured format, without a trailing newline.
+    pub fn format(&self, config: &AccessLogConfig) -> String {
+        match config.format.unwrap_or_default() {
+            AccessLogFormat::Compact => self.render(COMPACT_TEMPLATE),
+            AccessLogFormat::Common => self.render(COMMON_TEMPLATE),
+            AccessLogFormat::Combined => self.render(COMBINED_TEMPLATE),
+            AccessLogFormat::Json => self.json(),
+            AccessLogFormat::Custom => {
+                self.render(config.template.as_deref().unwrap_or(COMPACT_TEMPLATE))
+            }
+        }
+    }
+
+    /// Replaces the placeholders in a template, leaving unknown placeholders untouched. Quotes and
+    /// backslashes in values are escaped, so quoted fields can't be broken out of.
+    fn render(&self, template: &str) -> String {
+        let mut line = String::with_capacity(template.len() * 2);
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            line.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            match rest
+                .find('}')
+                .and_then(|end| Some((end, self.field(&rest[1..end])?)))
+            {
+                Some((end, value)) => {
+                    line.push_str(&value.replace('\\', "\\\\").replace('"', "\\\""));
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    line.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        line.push_str(rest);
+        line
+    }
+
+    /// Formats the entry as a JSON object.
+    fn json(&self) -> String {
+        let string = |value: &str| format!("\"{}\"", escape_json(value));
+        let optional = |value: &Option<String>| value.as_deref().map_or("null".to_string(), string);
+
+        let fields = [
+            (
+                "time",
+                string(
+                    &Timestamp::try_from(self.time)
+                        .unwrap_or(Timestamp::UNIX_EPOCH)
+                        .to_string(),
+                ),
+            ),
+            ("host", optional(&self.host)),
+            ("remote_addr", optional(&self.remote_addr)),
+            ("method", string(&self.method)),
+            ("path", string(&self.path)),
+            ("route", optional(&self.route)),
+            ("protocol", string("HTTP/1.1")),
+            ("status", self.status.to_string()),
+            ("bytes", self.bytes.to_string()),
+            ("referer", optional(&self.referer)),
+            ("user_agent", optional(&self.user_agent)),
+            ("request_id", optional(&self.request_id)),
+            ("latency_us", self.latency.as_micros().to_string()),
+        ];
+
+        let fields = fields
+            .iter()
+            .map(|(name, value)| format!("\"{name}\":{value}"))
+            .collect::<Vec<_>>();
+
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+/// An access log file that is rotated once it exceeds its maximum size.
+///
+/// Rotated files get a numeric suffix, e.g. `access.log.1` is the most recently rotated file.
+pub struct AccessLogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl AccessLogFile {
+    /// Opens the access log file of the config, appending to it if it already exists.
+    pub fn open(path: &str, config: &AccessLogConfig) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(Self {
+            size: file.metadata()?.len(),
+            path,
+            file,
+            max_size: config.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE),
+            max_files: config.max_files.unwrap_or(DEFAULT_MAX_FILES),
+        })
+    }
+
+    /// Writes a line to the file, rotating it first if the line would exceed its maximum size.
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{line}")?;
+        self.size += len;
+
+        Ok(())
+    }
+
+    /// Shifts the rotated files by one, dropping the oldest, and starts a new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{n}"));
+            PathBuf::from(path)
+        };
+
+        if self.max_files > 0 {
+            for n in (1..self.max_files).rev() {
+                if rotated(n).exists() {
+                    fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+
+            fs::rename(&self.path, rotated(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::http::temp_dir;
+
+    fn entry() -> Entry {
+        Entry {
+            time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            host: Some("example.com".to_string()),
+            remote_addr: Some("203.0.113.7:51234".to_string()),
+            remote_ip: Some("203.0.113.7".to_string()),
+            method: "GET".to_string(),
+            path: "/posts/1?page=2".to_string(),
+            route: Some("/posts/{id}".to_string()),
+            status: 200,
+            bytes: 512,
+            referer: None,
+            user_agent: Some("curl/8.0".to_string()),
+            request_id: Some("7d0b3a52".to_string()),
+            latency: Duration::from_micros(1500),
+        }
+    }
+
+    fn format(format: AccessLogFormat, template: Option<&str>, entry: &Entry) -> String {
+        entry.format(&AccessLogConfig {
+            format: Some(format),
+            template: template.map(str::to_string),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn formats_compact_lines() {
+        assert_eq!(
+            format(AccessLogFormat::Compact, None, &entry()),
+            "example.com 203.0.113.7:51234 \"GET /posts/1?page=2\" 200 512B 1ms"
+        );
+    }
+
+    #[test]
+    fn formats_common_and_combined_lines() {
+        assert_eq!(
+            format(AccessLogFormat::Common, None, &entry()),
+            "203.0.113.7 - - [14/Nov/2023:22:13:20 +0000] \"GET /posts/1?page=2 HTTP/1.1\" 200 512"
+        );
+        assert_eq!(
+            format(AccessLogFormat::Combined, None, &entry()),
+            "203.0.113.7 - - [14/Nov/2023:22:13:20 +0000] \"GET /posts/1?page=2 HTTP/1.1\" 200 512 \"-\" \"curl/8.0\""
+        );
+    }
+
+    #[test]
+    fn formats_json_lines() {
+        assert_eq!(
+            format(AccessLogFormat::Json, None, &entry()),
+            "{\"time\":\"2023-11-14T22:13:20Z\",\"host\":\"example.com\",\
+             \"remote_addr\":\"203.0.113.7:51234\",\"method\":\"GET\",\
+             \"path\":\"/posts/1?page=2\",\"route\":\"/posts/{id}\",\"protocol\":\"HTTP/1.1\",\
+             \"status\":200,\"bytes\":512,\"referer\":null,\"user_agent\":\"curl/8.0\",\
+             \"request_id\":\"7d0b3a52\",\"latency_us\":1500}"
+        );
+    }
+
+    #[test]
+    fn formats_custom_templates() {
+        assert_eq!(
+            format(
+                AccessLogFormat::Custom,
+                Some("{remote_ip} {route} {latency_us}us {request_id} {unknown} {"),
+                &entry()
+            ),
+            "203.0.113.7 /posts/{id} 1500us 7d0b3a52 {unknown} {"
+        );
+        assert_eq!(
+            format(AccessLogFormat::Custom, None, &entry()),
+            format(AccessLogFormat::Compact, None, &entry())
+        );
+    }
+
+    #[test]
+    fn escapes_quotes_in_values() {
+        let entry = Entry {
+            path: "/\" 200 0 \"forged".to_string(),
+            user_agent: Some("evil\\\"agent".to_string()),
+            ..entry()
+        };
+
+        assert_eq!(
+            format(AccessLogFormat::Combined, None, &entry),
+            "203.0.113.7 - - [14/Nov/2023:22:13:20 +0000] \"GET /\\\" 200 0 \\\"forged HTTP/1.1\" 200 512 \"-\" \"evil\\\\\\\"agent\""
+        );
+        assert!(format(AccessLogFormat::Json, None, &entry)
+            .contains("\"path\":\"/\\\" 200 0 \\\"forged\""));
+    }
+
+    #[test]
+    fn rotates_files_exceeding_their_maximum_size() {
+        let dir = temp_dir("access-log");
+        let path = dir.join("access.log");
+        let config = AccessLogConfig {
+            max_file_size: Some(10),
+            max_files: Some(2),
+            ..Default::default()
+        };
+        let mut file = AccessLogFile::open(path.to_str().unwrap(), &config).unwrap();
+
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line).unwrap();
+        }
+
+        let rotated = |n: usize| fs::read_to_string(format!("{}.{n}", path.display())).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(rotated(1), "third\n");
+        assert_eq!(rotated(2), "second\n");
+        assert!(!PathBuf::from(format!("{}.3", path.display())).exists());
+    }
+
+    #[test]
+    fn keeps_appending_to_existing_files() {
+        let dir = temp_dir("access-log");
+        let path = dir.join("access.log");
+        fs::write(&path, "old\n").unwrap();
+        let config = AccessLogConfig {
+            max_file_size: Some(10),
+            max_files: Some(0),
+            ..Default::default()
+        };
+        let mut file = AccessLogFile::open(path.to_str().unwrap(), &config).unwrap();
+
+        file.write_line("new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\nnew\n");
+
+        file.write_line("newest").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "newest\n");
+        assert!(!PathBuf::from(format!("{}.1", path.display())).exists());
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    date,
+    request::Request,
+    response::{into_parts::IntoParts, Body, IntoResponse, Parts, Response},
+    Method, StatusCode,
+};
+
+/// Represents an entity tag used to validate cached representations.
+///
+/// Entity tags can be returned from handlers as part of a response.
+///
+/// ```
+/// use snx::{ETag, Json};
+///
+/// let res = (ETag::strong("42"), Json(vec!["hello", "world"]));
+/// ```
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ETag {
+    tag: String,
+    weak: bool,
+}
+
+impl ETag {
+    /// Creates a strong entity tag, which indicates that representations are byte-for-byte
+    /// identical, e.g. based on the version of a database row.
+    pub fn strong(tag: &str) -> Self {
+        Self {
+            tag: tag.to_string(),
+            weak: false,
+        }
+    }
+
+    /// Creates a weak entity tag, which indicates that representations are semantically
+    /// equivalent.
+    pub fn weak(tag: &str) -> Self {
+        Self {
+            tag: tag.to_string(),
+            weak: true,
+        }
+    }
+
+    /// Parses an entity tag from a header value.
+    ///
+    /// ```
+    /// use snx::ETag;
+    ///
+    /// let etag = ETag::parse("W/\"42\"").unwrap();
+    ///
+    /// assert_eq!(etag, ETag::weak("42"));
+    /// ```
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (weak, value) = match value.strip_prefix("W/") {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+
+        let tag = value.strip_prefix('"')?.strip_suffix('"')?;
+        if tag.contains('"') {
+            return None;
+        }
+
+        Some(Self {
+            tag: tag.to_string(),
+            weak,
+        })
+    }
+
+    /// Returns whether this is a weak entity tag.
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Compares two entity tags using the strong comparison function, both must be strong and
+    /// identical.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Compares two entity tags using the weak comparison function, ignoring weakness.
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Computes a weak entity tag from the contents of a body.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        // 64-bit FNV-1a, which is stable across processes and releases.
+        let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+        Self::weak(&format!("{:x}-{:016x}", bytes.len(), hash))
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.weak {
+            true => write!(f, "W/\"{}\"", self.tag),
+            false => write!(f, "\"{}\"", self.tag),
+        }
+    }
+}
+
+impl IntoParts for ETag {
+    fn into_parts(self, parts: Parts) -> Parts {
+        let mut parts = parts.clone();
+
+        parts.headers.remove("etag");
+        parts.headers.insert("ETag", &self.to_string());
+
+        parts
+    }
+}
+
+/// Returns whether an `If-Match` or `If-None-Match` header value matches the current entity tag,
+/// where `*` matches any existing representation.
+fn matches(value: &str, etag: Option<&ETag>, exists: bool, strong: bool) -> bool {
+    if value.trim() == "*" {
+        return exists;
+    }
+
+    let Some(etag) = etag else {
+        return false;
+    };
+
+    value
+        .split(',')
+        .filter_map(ETag::parse)
+        .any(|tag| match strong {
+            true => tag.strong_eq(etag),
+            false => tag.weak_eq(etag),
+        })
+}
+
+/// Returns the seconds since the Unix epoch, HTTP dates only have a precision of seconds.
+fn secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Evaluates the conditional headers of a request against the current validators of the target
+/// resource, in the order defined by RFC 9110.
+///
+/// Returns `None` if the request should be processed normally, `Some(StatusCode::NotModified)` if
+/// the client's cached representation of a GET or HEAD request is still valid and
+/// `Some(StatusCode::PreconditionFailed)` if a precondition failed. The target resource is
+/// considered to exist if it has either validator.
+pub fn evaluate(
+    req: &Request,
+    etag: Option<&ETag>,
+    last_modified: Option<SystemTime>,
+) -> Option<StatusCode> {
+    let headers = req.headers();
+    let safe = matches!(req.method(), Method::Get | Method::Head);
+    let exists = etag.is_some() || last_modified.is_some();
+
+    if let Some(if_match) = headers.get("if-match") {
+        if !matches(&if_match, etag, exists, true) {
+            return Some(StatusCode::PreconditionFailed);
+        }
+    } else if let Some(since) = headers
+        .get("if-unmodified-since")
+        .and_then(|v| date::parse(&v))
+    {
+        if last_modified.is_some_and(|modified| secs(modified) > secs(since)) {
+            return Some(StatusCode::PreconditionFailed);
+        }
+    }
+
+    if let Some(if_none_match) = headers.get("if-none-match") {
+        if matches(&if_none_match, etag, exists, false) {
+            return Some(match safe {
+                true => StatusCode::NotModified,
+                false => StatusCode::PreconditionFailed,
+            });
+        }
+    } else if let Some(since) = headers
+        .get("if-modified-since")
+        .and_then(|v| date::parse(&v))
+    {
+        if safe && last_modified.is_some_and(|modified| secs(modified) <= secs(since)) {
+            return Some(StatusCode::NotModified);
+        }
+    }
+
+    None
+}
+
+/// Headers that are kept when a response is turned into a 304 Not Modified response.
+const NOT_MODIFIED_HEADERS: [&str; 7] = [
+    "cache-control",
+    "content-location",
+    "date",
+    "etag",
+    "expires",
+    "last-modified",
+    "vary",
+];
+
+/// Evaluates the conditional headers of a GET or HEAD request against the validators of the
+/// response, adding a weak entity tag to successful responses with a buffered body that have
+/// none.
+pub fn apply(req: &Request, mut res: Response) -> Response {
+    if !matches!(req.method(), Method::Get | Method::Head) || *res.status() != StatusCode::OK {
+        return res;
+    }
+
+    if res.headers().get("etag").is_none() {
+        if let Some(Body::Bytes(bytes)) = res.body() {
+            let etag = ETag::from_bytes(bytes);
+            res.headers_mut().insert("ETag", &etag.to_string());
+        }
+    }
+
+    let etag = res.headers().get("etag").and_then(|v| ETag::parse(&v));
+    let last_modified = res
+        .headers()
+        .get("last-modified")
+        .and_then(|v| date::parse(&v));
+
+    match evaluate(req, etag.as_ref(), last_modified) {
+        Some(StatusCode::NotModified) => {
+            let mut not_modified = Response::default();
+            *not_modified.status_mut() = StatusCode::NotModified;
+
+            for name in NOT_MODIFIED_HEADERS {
+                for value in res.headers().get_all(name).unwrap_or_default() {
+                    not_modified.headers_mut().insert(name, &value);
+                }
+            }
+
+            not_modified
+        }
+        Some(status) => status.into_response(),
+        None => res,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn request(method: Method, headers: &[(&str, &str)]) -> Request {
+        headers
+            .iter()
+            .fold(Request::builder().method(method), |builder, (k, v)| {
+                builder.header(k, v)
+            })
+            .build()
+    }
+
+    fn modified() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    }
+
+    fn cached(headers: &[(&str, &str)]) -> Response {
+        let mut res = Response::new("hello world!");
+        res.headers_mut().insert("ETag", "\"v1\"");
+        res.headers_mut()
+            .insert("Last-Modified", &date::format(modified()));
+        res.headers_mut().insert("Cache-Control", "max-age=60");
+        res.headers_mut().insert("Vary", "Accept");
+        res.headers_mut()
+            .insert("Content-Type", "text/plain; charset=utf-8");
+
+        apply(&request(Method::Get, headers), res)
+    }
+
+    #[test]
+    fn answers_matching_if_none_match_with_304() {
+        let res = cached(&[("If-None-Match", "\"v0\", W/\"v1\"")]);
+
+        assert_eq!(*res.status(), StatusCode::NotModified);
+        assert!(res.body().is_none());
+        assert_eq!(res.headers().get("etag").as_deref(), Some("\"v1\""));
+        assert_eq!(
+            res.headers().get("cache-control").as_deref(),
+            Some("max-age=60")
+        );
+        assert_eq!(res.headers().get("vary").as_deref(), Some("Accept"));
+        assert!(res.headers().get("last-modified").is_some());
+        assert!(res.headers().get("content-type").is_none());
+
+        assert_eq!(
+            *cached(&[("If-None-Match", "*")]).status(),
+            StatusCode::NotModified
+        );
+        assert_eq!(
+            *cached(&[("If-None-Match", "\"v2\"")]).status(),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn answers_unmodified_resources_with_304() {
+        let since = date::format(modified());
+        let res = cached(&[("If-Modified-Since", &since)]);
+
+        assert_eq!(*res.status(), StatusCode::NotModified);
+        assert!(res.headers().get("content-type").is_none());
+
+        let before = date::format(modified() - Duration::from_secs(1));
+        assert_eq!(
+            *cached(&[("If-Modified-Since", &before)]).status(),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn prefers_if_none_match_over_if_modified_since() {
+        let since = date::format(modified());
+
+        let res = cached(&[("If-None-Match", "\"v2\""), ("If-Modified-Since", &since)]);
+        assert_eq!(*res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn adds_weak_etags_to_buffered_responses() {
+        let res = apply(&request(Method::Get, &[]), Response::new("hello world!"));
+        let etag = res.headers().get("etag").unwrap();
+
+        assert!(etag.starts_with("W/\""));
+
+        let res = apply(
+            &request(Method::Get, &[("If-None-Match", &etag)]),
+            Response::new("hello world!"),
+        );
+        assert_eq!(*res.status(), StatusCode::NotModified);
+    }
+
+    #[test]
+    fn fails_unmatched_preconditions_of_unsafe_requests() {
+        let etag = ETag::strong("v1");
+
+        let req = request(Method::Put, &[("If-Match", "\"v0\"")]);
+        assert_eq!(
+            req.check_preconditions(Some(&etag), None),
+            Err(StatusCode::PreconditionFailed)
+        );
+
+        let req = request(Method::Put, &[("If-Match", "W/\"v1\"")]);
+        assert_eq!(
+            req.check_preconditions(Some(&etag), None),
+            Err(StatusCode::PreconditionFailed)
+        );
+
+        let req = request(Method::Put, &[("If-Match", "\"v1\"")]);
+        assert_eq!(req.check_preconditions(Some(&etag), None), Ok(()));
+
+        let req = request(Method::Put, &[("If-Match", "*")]);
+        assert_eq!(
+            req.check_preconditions(None, None),
+            Err(StatusCode::PreconditionFailed)
+        );
+
+        let req = request(Method::Put, &[("If-None-Match", "*")]);
+        assert_eq!(
+            req.check_preconditions(Some(&etag), None),
+            Err(StatusCode::PreconditionFailed)
+        );
+        assert_eq!(req.check_preconditions(None, None), Ok(()));
+    }
+
+    #[test]
+    fn fails_if_unmodified_since_for_modified_resources() {
+        let before = date::format(modified() - Duration::from_secs(1));
+        let req = request(Method::Put, &[("If-Unmodified-Since", &before)]);
+        assert_eq!(
+            req.check_preconditions(None, Some(modified())),
+            Err(StatusCode::PreconditionFailed)
+        );
+
+        let since = date::format(modified());
+        let req = request(Method::Put, &[("If-Unmodified-Since", &since)]);
+        assert_eq!(req.check_preconditions(None, Some(modified())), Ok(()));
+
+        // If-Match takes precedence over If-Unmodified-Since
+        let req = request(
+            Method::Put,
+            &[("If-Match", "\"v1\""), ("If-Unmodified-Since", &before)],
+        );
+        assert_eq!(
+            req.check_preconditions(Some(&ETag::strong("v1")), Some(modified())),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn requires_preconditions_for_unsafe_requests() {
+        let required = |req: Request| {
+            crate::http::middleware::require_preconditions(
+                crate::Context::new(crate::Config::for_tests("")),
+                req,
+                Box::new(|_| Response::default()),
+            )
+            .into_response()
+        };
+
+        assert_eq!(
+            *required(request(Method::Put, &[])).status(),
+            StatusCode::PreconditionRequired
+        );
+        assert_eq!(
+            *required(request(Method::Delete, &[])).status(),
+            StatusCode::PreconditionRequired
+        );
+        assert_eq!(
+            *required(request(Method::Put, &[("If-Match", "\"v1\"")])).status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            *required(request(Method::Get, &[])).status(),
+            StatusCode::OK
+        );
+    }
+}
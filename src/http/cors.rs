@@ -0,0 +1,317 @@
+use crate::config::CorsConfig;
+
+use super::{
+    header::{vary, HeaderMap},
+    request::Request,
+    response::Response,
+    Method, StatusCode,
+};
+
+/// Methods allowed in cross-origin requests if none are configured.
+const DEFAULT_METHODS: [&str; 6] = ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"];
+
+/// Returns whether the origin matches one of the allowed origin patterns.
+///
+/// Patterns are either `*`, an exact origin or an origin with a wildcard subdomain such as
+/// `https://*.example.com`, which matches subdomains at any depth but not the domain itself.
+fn is_allowed(origin: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| {
+        if pattern == "*" || pattern.eq_ignore_ascii_case(origin) {
+            return true;
+        }
+
+        let Some((scheme, domain)) = pattern.split_once("://*.") else {
+            return false;
+        };
+
+        let origin = origin.to_lowercase();
+        let Some(host) = origin.strip_prefix(&format!("{}://", scheme.to_lowercase())) else {
+            return false;
+        };
+        let Some(subdomain) = host.strip_suffix(&format!(".{}", domain.to_lowercase())) else {
+            return false;
+        };
+
+        !subdomain.is_empty()
+            && subdomain.split('.').all(|label| {
+                !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            })
+    })
+}
+
+/// Returns whether any origin is allowed with the wildcard.
+fn allows_any(config: &CorsConfig) -> bool {
+    config
+        .allowed_origins
+        .as_ref()
+        .is_some_and(|origins| origins.iter().any(|o| o == "*"))
+}
+
+/// Adds the headers shared by preflight and actual responses.
+fn allow_origin(headers: &mut HeaderMap, origin: &str, config: &CorsConfig) {
+    let credentials = config.allow_credentials.unwrap_or_default();
+    let any = allows_any(config);
+
+    // Browsers reject the wildcard for requests with credentials. Reflecting the origin instead
+    // would allow any site to make requests with credentials, so credentials are not allowed with
+    // the wildcard, which is also rejected when the config is loaded.
+    if any {
+        headers.insert("Access-Control-Allow-Origin", "*");
+
+        return;
+    }
+
+    headers.insert("Access-Control-Allow-Origin", origin);
+    vary(headers, &["Origin"]);
+
+    if credentials {
+        headers.insert("Access-Control-Allow-Credentials", "true");
+    }
+}
+
+/// Returns the origin of a cross-origin request if it is allowed by the config.
+fn allowed_origin(req: &Request, config: &CorsConfig) -> Option<String> {
+    let origin = req.headers().get("origin")?;
+
+    is_allowed(
+        &origin,
+        config.allowed_origins.as_deref().unwrap_or_default(),
+    )
+    .then_some(origin)
+}
+
+/// Answers a preflight request from an allowed origin.
+///
+/// Returns `None` if the request is not a preflight request or its origin is not allowed, in
+/// which case it should be handled like any other request.
+pub fn preflight(req: &Request, config: &CorsConfig) -> Option<Response> {
+    if req.method() != Method::Options
+        || req.headers().get("access-control-request-method").is_none()
+    {
+        return None;
+    }
+
+    let origin = allowed_origin(req, config)?;
+
+    let mut res = Response::default();
+    *res.status_mut() = StatusCode::NoContent;
+
+    let headers = res.headers_mut();
+    allow_origin(headers, &origin, config);
+
+    let methods = match &config.allowed_methods {
+        Some(methods) => methods.join(", "),
+        None => DEFAULT_METHODS.join(", "),
+    };
+    headers.insert("Access-Control-Allow-Methods", &methods);
+
+    let allowed_headers = match &config.allowed_headers {
+        Some(allowed) => Some(allowed.join(", ")),
+        None => req.headers().get("access-control-request-headers"),
+    };
+    if let Some(allowed_headers) = allowed_headers.filter(|h| !h.is_empty()) {
+        headers.insert("Access-Control-Allow-Headers", &allowed_headers);
+    }
+
+    if let Some(max_age) = config.max_age {
+        headers.insert("Access-Control-Max-Age", &max_age.to_string());
+    }
+
+    vary(
+        headers,
+        &[
+            "Access-Control-Request-Method",
+            "Access-Control-Request-Headers",
+        ],
+    );
+
+    Some(res)
+}
+
+/// Adds CORS headers to the response of an actual cross-origin request from an allowed origin.
+///
+/// Unless any origin is allowed, every response varies on the `Origin` header, including those to
+/// requests from disallowed origins or without an origin, so shared caches do not serve a response
+/// without CORS headers to an allowed origin or the other way around.
+pub fn apply(req: &Request, mut res: Response, config: &CorsConfig) -> Response {
+    if !allows_any(config) {
+        vary(res.headers_mut(), &["Origin"]);
+    }
+
+    let Some(origin) = allowed_origin(req, config) else {
+        return res;
+    };
+
+    let headers = res.headers_mut();
+    allow_origin(headers, &origin, config);
+
+    if let Some(exposed) = config.exposed_headers.as_ref().filter(|h| !h.is_empty()) {
+        headers.insert("Access-Control-Expose-Headers", &exposed.join(", "));
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(origins: &[&str], credentials: bool) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: Some(origins.iter().map(|o| o.to_string()).collect()),
+            allow_credentials: Some(credentials),
+            ..Default::default()
+        }
+    }
+
+    fn request(origin: &str) -> Request {
+        Request::builder()
+            .method(Method::Get)
+            .header("Origin", origin)
+            .build()
+    }
+
+    fn preflight_request(origin: &str) -> Request {
+        Request::builder()
+            .method(Method::Options)
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "PUT")
+            .header("Access-Control-Request-Headers", "X-Custom")
+            .build()
+    }
+
+    #[test]
+    fn matches_origin_patterns() {
+        let patterns = [
+            "https://acme.com".to_string(),
+            "https://*.example.com".to_string(),
+        ];
+
+        assert!(is_allowed("https://acme.com", &patterns));
+        assert!(is_allowed("HTTPS://ACME.com", &patterns));
+        assert!(is_allowed("https://app.example.com", &patterns));
+        assert!(is_allowed("https://a.b.example.com", &patterns));
+        assert!(!is_allowed("https://example.com", &patterns));
+        assert!(!is_allowed("http://app.example.com", &patterns));
+        assert!(!is_allowed("https://evil-example.com", &patterns));
+        assert!(!is_allowed("https://app.example.com.evil.com", &patterns));
+        assert!(!is_allowed("https://acme.com.evil.com", &patterns));
+    }
+
+    #[test]
+    fn reflects_allowed_origins_with_credentials() {
+        let res = apply(
+            &request("https://acme.com"),
+            Response::default(),
+            &config(&["https://acme.com"], true),
+        );
+
+        assert_eq!(
+            res.headers().get("access-control-allow-origin").as_deref(),
+            Some("https://acme.com")
+        );
+        assert_eq!(
+            res.headers()
+                .get("access-control-allow-credentials")
+                .as_deref(),
+            Some("true")
+        );
+        assert_eq!(res.headers().get("vary").as_deref(), Some("Origin"));
+    }
+
+    #[test]
+    fn does_not_reflect_the_origin_for_the_wildcard_with_credentials() {
+        let res = apply(
+            &request("https://evil.example"),
+            Response::default(),
+            &config(&["*"], true),
+        );
+
+        assert_eq!(
+            res.headers().get("access-control-allow-origin").as_deref(),
+            Some("*")
+        );
+        assert!(res
+            .headers()
+            .get("access-control-allow-credentials")
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_the_wildcard_with_credentials_when_the_config_loads() {
+        let config = crate::Config::for_tests(
+            "[cors]\nallowed_origins = [\"*\"]\nallow_credentials = true\n",
+        );
+
+        assert!(matches!(
+            config.validate(),
+            Err(crate::config::InvalidConfigError::WildcardOriginWithCredentials)
+        ));
+    }
+
+    #[test]
+    fn ignores_disallowed_origins() {
+        let config = config(&["https://acme.com"], false);
+        let res = apply(
+            &request("https://evil.example"),
+            Response::default(),
+            &config,
+        );
+
+        assert!(res.headers().get("access-control-allow-origin").is_none());
+        assert_eq!(res.headers().get_all("vary").unwrap(), ["Origin"]);
+        assert!(preflight(&preflight_request("https://evil.example"), &config).is_none());
+
+        let res = apply(
+            &Request::builder().method(Method::Get).build(),
+            Response::default(),
+            &config,
+        );
+        assert_eq!(res.headers().get_all("vary").unwrap(), ["Origin"]);
+    }
+
+    #[test]
+    fn does_not_vary_on_the_origin_if_any_origin_is_allowed() {
+        let res = apply(
+            &request("https://acme.com"),
+            Response::default(),
+            &config(&["*"], false),
+        );
+
+        assert!(res.headers().get("vary").is_none());
+    }
+
+    #[test]
+    fn answers_preflight_requests() {
+        let res = preflight(
+            &preflight_request("https://acme.com"),
+            &config(&["https://acme.com"], false),
+        )
+        .unwrap();
+
+        assert_eq!(*res.status(), StatusCode::NoContent);
+        assert_eq!(
+            res.headers().get("access-control-allow-methods").as_deref(),
+            Some("GET, HEAD, POST, PUT, PATCH, DELETE")
+        );
+        assert_eq!(
+            res.headers().get("access-control-allow-headers").as_deref(),
+            Some("X-Custom")
+        );
+        assert_eq!(
+            res.headers().get_all("vary").unwrap(),
+            [
+                "Origin",
+                "Access-Control-Request-Method",
+                "Access-Control-Request-Headers"
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_requests_that_are_not_preflight_requests() {
+        let config = config(&["https://acme.com"], false);
+
+        assert!(preflight(&request("https://acme.com"), &config).is_none());
+    }
+}
@@ -0,0 +1,25 @@
+use std::time::SystemTime;
+
+use jiff::{civil::DateTime, tz::TimeZone, Timestamp};
+
+/// The format of dates in HTTP headers, as defined in RFC 9110.
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Formats a point in time as an HTTP date.
+pub fn format(time: SystemTime) -> String {
+    Timestamp::try_from(time)
+        .unwrap_or(Timestamp::UNIX_EPOCH)
+        .strftime(HTTP_DATE_FORMAT)
+        .to_string()
+}
+
+/// Parses an HTTP date into a point in time.
+pub fn parse(value: &str) -> Option<SystemTime> {
+    let timestamp = DateTime::strptime(HTTP_DATE_FORMAT, value.trim())
+        .ok()?
+        .to_zoned(TimeZone::UTC)
+        .ok()?
+        .timestamp();
+
+    Some(SystemTime::from(timestamp))
+}
@@ -0,0 +1,561 @@
+use std::io::{self, Cursor, Read};
+
+use super::{
+    request::Request,
+    response::{Body, Response},
+    StatusCode,
+};
+
+/// The default minimum size in bytes of a response body before it is compressed.
+pub const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// Represents a content coding supported by snx.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    /// Supported encodings in order of preference when a client accepts multiple equally.
+    const PREFERENCE: [Encoding; 3] = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    /// Gets the name of the encoding as used in the `Content-Encoding` header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    /// Parses an encoding from its name as used in the `Content-Encoding` header.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "br" => Some(Encoding::Brotli),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Wraps a reader so that it produces the encoded contents of the given reader.
+    pub fn encode(&self, reader: impl Read + Send + 'static) -> Box<dyn Read + Send> {
+        match self {
+            Encoding::Brotli => Box::new(brotli::CompressorReader::new(reader, 4096, 5, 22)),
+            Encoding::Gzip => Box::new(flate2::read::GzEncoder::new(
+                reader,
+                flate2::Compression::default(),
+            )),
+            Encoding::Deflate => Box::new(flate2::read::ZlibEncoder::new(
+                reader,
+                flate2::Compression::default(),
+            )),
+        }
+    }
+
+    /// Wraps a reader so that it produces the decoded contents of the given reader.
+    pub fn decode(&self, reader: impl Read + Send + 'static) -> Box<dyn Read + Send> {
+        match self {
+            Encoding::Brotli => Box::new(brotli::Decompressor::new(reader, 4096)),
+            Encoding::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Encoding::Deflate => Box::new(flate2::read::ZlibDecoder::new(reader)),
+        }
+    }
+}
+
+/// Negotiates the best supported encoding from the value of an `Accept-Encoding` header, taking
+/// quality values into account.
+///
+/// Returns `None` if the client prefers the identity encoding or accepts none of the supported
+/// encodings.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut wildcard = None;
+    let mut identity = None;
+    let mut qualities = [None; Encoding::PREFERENCE.len()];
+
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let name = params.next().unwrap_or_default().trim().to_lowercase();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok().filter(|q| q.is_finite()))
+            .unwrap_or(1.0)
+            .clamp(0.0, 1.0);
+
+        match name.as_str() {
+            "*" => wildcard = Some(quality),
+            "identity" => identity = Some(quality),
+            name => {
+                if let Some(i) = Encoding::from_name(name)
+                    .and_then(|encoding| Encoding::PREFERENCE.iter().position(|e| *e == encoding))
+                {
+                    qualities[i] = Some(quality);
+                }
+            }
+        }
+    }
+
+    let (encoding, quality) = Encoding::PREFERENCE
+        .iter()
+        .zip(qualities)
+        .filter_map(|(encoding, quality)| Some((*encoding, quality.or(wildcard)?)))
+        .fold(
+            None,
+            |best: Option<(Encoding, f32)>, (encoding, quality)| match best {
+                Some((_, q)) if q >= quality => best,
+                _ => Some((encoding, quality)),
+            },
+        )?;
+
+    (quality > 0.0 && quality >= identity.unwrap_or(0.0)).then_some(encoding)
+}
+
+/// Returns whether responses with the given content type are worth compressing.
+///
+/// Most media and archive formats are already compressed and would only grow in size.
+fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    if essence == "image/svg+xml" {
+        return true;
+    }
+
+    !(essence.starts_with("image/")
+        || essence.starts_with("video/")
+        || essence.starts_with("audio/")
+        || essence.starts_with("font/woff")
+        || essence.starts_with("multipart/")
+        || matches!(
+            essence.as_str(),
+            "application/zip"
+                | "application/gzip"
+                | "application/x-gzip"
+                | "application/x-bzip2"
+                | "application/x-xz"
+                | "application/zstd"
+                | "application/x-7z-compressed"
+                | "application/x-rar-compressed"
+                | "application/vnd.rar"
+                | "application/pdf"
+                | "application/wasm"
+                | "application/octet-stream"
+        ))
+}
+
+/// Compresses the body of the given response using the encoding preferred by the client.
+///
+/// Responses that are small, already encoded, partial or of an already compressed content type
+/// are left untouched. Streamed bodies are compressed while they are written.
+pub fn compress(req: &Request, mut res: Response, min_size: u64) -> Response {
+    let Some(body) = res.body() else {
+        return res;
+    };
+
+    let eligible = !matches!(
+        res.status(),
+        StatusCode::NoContent | StatusCode::NotModified | StatusCode::PartialContent
+    ) && res.headers().get("content-encoding").is_none()
+        && res.headers().get("content-range").is_none()
+        && res
+            .headers()
+            .get("content-type")
+            .is_some_and(|content_type| is_compressible(&content_type))
+        && body.len().is_none_or(|len| len >= min_size);
+
+    if !eligible {
+        return res;
+    }
+
+    super::header::vary(res.headers_mut(), &["Accept-Encoding"]);
+
+    let Some(encoding) = req
+        .headers()
+        .get("accept-encoding")
+        .and_then(|v| negotiate(&v))
+    else {
+        return res;
+    };
+
+    let body = res.body_mut().take().unwrap_or_default();
+    *res.body_mut() = Some(match body {
+        Body::Bytes(bytes) => {
+            let mut compressed = Vec::new();
+            encoding
+                .encode(Cursor::new(bytes))
+                .read_to_end(&mut compressed)
+                .expect("compressing bytes in memory cannot fail");
+
+            Body::Bytes(compressed)
+        }
+        stream => match stream.into_reader() {
+            Ok(reader) => Body::from_reader(encoding.encode(reader), None),
+            Err(_) => return res,
+        },
+    });
+
+    res.headers_mut()
+        .insert("Content-Encoding", encoding.as_str());
+    res.headers_mut().remove("accept-ranges");
+    if let Some(etag) = res.headers_mut().remove("etag") {
+        for etag in etag {
+            match etag.starts_with("W/") {
+                true => res.headers_mut().insert("ETag", &etag),
+                false => res.headers_mut().insert("ETag", &format!("W/{etag}")),
+            }
+        }
+    }
+
+    res
+}
+
+/// Represents an error that occurred while decoding a request body.
+#[derive(thiserror::Error, Debug)]
+pub enum DecodeError {
+    #[error("content encoding `{0}` is not supported")]
+    Unsupported(String),
+    #[error("decoded body exceeds the maximum body size")]
+    TooLarge,
+    #[error("body could not be decoded: {0}")]
+    Invalid(#[from] io::Error),
+}
+
+/// Decodes the body of the given request according to its `Content-Encoding` header.
+///
+/// Encodings are removed in the reverse order in which they were applied. The maximum size is
+/// enforced on the decoded body while decoding, so compressed bodies cannot expand unbounded.
+pub fn decompress(req: &mut Request, max_size: usize) -> Result<(), DecodeError> {
+    let Some(header) = req.headers_mut().remove("content-encoding") else {
+        return Ok(());
+    };
+
+    let mut encodings = Vec::new();
+    for name in header.iter().flat_map(|v| v.split(',')).map(str::trim) {
+        if name.is_empty() || name.eq_ignore_ascii_case("identity") {
+            continue;
+        }
+
+        encodings
+            .push(Encoding::from_name(name).ok_or(DecodeError::Unsupported(name.to_string()))?);
+    }
+
+    if encodings.is_empty() {
+        return Ok(());
+    }
+
+    let mut reader: Box<dyn Read + Send> = Box::new(Cursor::new(std::mem::take(req.bytes_mut())));
+    for encoding in encodings.iter().rev() {
+        reader = encoding.decode(reader);
+    }
+
+    let mut decoded = Vec::new();
+    reader.take(max_size as u64 + 1).read_to_end(&mut decoded)?;
+
+    if decoded.len() > max_size {
+        return Err(DecodeError::TooLarge);
+    }
+
+    req.headers_mut().remove("content-length");
+    req.headers_mut()
+        .insert("Content-Length", &decoded.len().to_string());
+    *req.bytes_mut() = decoded;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{response::IntoResponse, Method};
+
+    use super::*;
+
+    fn compressed(accept_encoding: &str, res: Response) -> Response {
+        let req = Request::builder()
+            .method(Method::Get)
+            .header("Accept-Encoding", accept_encoding)
+            .build();
+
+        compress(&req, res, 16)
+    }
+
+    fn text(body: Body) -> Response {
+        let mut res = Response::new(body);
+        res.headers_mut()
+            .insert("Content-Type", "text/plain; charset=utf-8");
+
+        res
+    }
+
+    fn decoded(encoding: Encoding, res: Response) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        encoding
+            .decode(Cursor::new(
+                res.body().clone().unwrap().into_bytes().unwrap(),
+            ))
+            .read_to_end(&mut decoded)
+            .unwrap();
+
+        decoded
+    }
+
+    const TEXT: &str = "hello world! hello world! hello world!";
+
+    #[test]
+    fn negotiates_encodings_by_quality() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(
+            negotiate("deflate;q=0.8, gzip;q=0.2"),
+            Some(Encoding::Deflate)
+        );
+        assert_eq!(negotiate("x-gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("zstd"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn negotiates_wildcards() {
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("*, br;q=0"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*;q=0"), None);
+    }
+
+    #[test]
+    fn respects_refused_encodings() {
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate("gzip;q=0, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip, identity;q=0"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0.5, identity"), None);
+    }
+
+    #[test]
+    fn ignores_invalid_and_out_of_range_qualities() {
+        assert_eq!(negotiate("gzip;q=NaN"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=inf, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=-1"), None);
+        assert_eq!(negotiate("gzip;q=5, br"), Some(Encoding::Brotli));
+    }
+
+    #[test]
+    fn compresses_buffered_bodies() {
+        let res = compressed("gzip", text(Body::from(TEXT)));
+
+        assert_eq!(
+            res.headers().get("content-encoding").as_deref(),
+            Some("gzip")
+        );
+        assert_eq!(
+            res.headers().get("vary").as_deref(),
+            Some("Accept-Encoding")
+        );
+        assert_eq!(decoded(Encoding::Gzip, res), TEXT.as_bytes());
+    }
+
+    #[test]
+    fn compresses_streamed_bodies() {
+        let body = Body::from_reader(Cursor::new(TEXT.as_bytes().to_vec()), None);
+        let res = compressed("br", text(body));
+
+        assert_eq!(res.headers().get("content-encoding").as_deref(), Some("br"));
+        assert_eq!(res.body().as_ref().and_then(Body::len), None);
+        assert_eq!(decoded(Encoding::Brotli, res), TEXT.as_bytes());
+    }
+
+    #[test]
+    fn skips_responses_that_are_not_worth_compressing() {
+        let small = compressed("gzip", text(Body::from("hello")));
+        assert!(small.headers().get("content-encoding").is_none());
+        assert!(small.headers().get("vary").is_none());
+
+        let mut partial = text(Body::from(TEXT));
+        *partial.status_mut() = StatusCode::PartialContent;
+        partial
+            .headers_mut()
+            .insert("Content-Range", "bytes 0-37/100");
+        assert!(compressed("gzip", partial)
+            .headers()
+            .get("content-encoding")
+            .is_none());
+
+        let mut image = Response::new(Body::from(TEXT));
+        image.headers_mut().insert("Content-Type", "image/png");
+        assert!(compressed("gzip", image)
+            .headers()
+            .get("content-encoding")
+            .is_none());
+
+        let mut encoded = text(Body::from(TEXT));
+        encoded.headers_mut().insert("Content-Encoding", "br");
+        let encoded = compressed("gzip", encoded);
+        assert_eq!(
+            encoded.headers().get_all("content-encoding"),
+            Some(vec!["br".to_string()])
+        );
+    }
+
+    #[test]
+    fn varies_uncompressed_responses_on_accept_encoding() {
+        let res = compressed("identity", text(Body::from(TEXT)));
+
+        assert!(res.headers().get("content-encoding").is_none());
+        assert_eq!(
+            res.headers().get("vary").as_deref(),
+            Some("Accept-Encoding")
+        );
+    }
+
+    #[test]
+    fn merges_existing_vary_headers() {
+        let mut res = text(Body::from(TEXT));
+        res.headers_mut().insert("Vary", "Origin");
+        let res = compressed("gzip", res);
+
+        assert_eq!(
+            res.headers().get_all("vary"),
+            Some(vec!["Origin".to_string(), "Accept-Encoding".to_string()])
+        );
+
+        let mut res = text(Body::from(TEXT));
+        res.headers_mut().insert("Vary", "Origin, accept-encoding");
+        let res = compressed("gzip", res);
+
+        assert_eq!(
+            res.headers().get_all("vary"),
+            Some(vec!["Origin, accept-encoding".to_string()])
+        );
+    }
+
+    #[test]
+    fn weakens_strong_etags_of_compressed_responses() {
+        let mut res = text(Body::from(TEXT));
+        res.headers_mut().insert("ETag", "\"v1\"");
+        res.headers_mut().insert("Accept-Ranges", "bytes");
+        let res = compressed("gzip", res);
+
+        assert_eq!(res.headers().get("etag").as_deref(), Some("W/\"v1\""));
+        assert!(res.headers().get("accept-ranges").is_none());
+
+        let mut res = text(Body::from(TEXT));
+        res.headers_mut().insert("ETag", "W/\"v1\"");
+        let res = compressed("gzip", res);
+
+        assert_eq!(res.headers().get("etag").as_deref(), Some("W/\"v1\""));
+    }
+
+    fn encoded(encoding: Encoding, bytes: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encoding
+            .encode(Cursor::new(bytes.to_vec()))
+            .read_to_end(&mut encoded)
+            .unwrap();
+
+        encoded
+    }
+
+    fn encoded_request(content_encoding: &str, body: Vec<u8>) -> Request {
+        Request::builder()
+            .method(Method::Post)
+            .header("Content-Encoding", content_encoding)
+            .header("Content-Length", &body.len().to_string())
+            .body(body)
+            .build()
+    }
+
+    fn decompressed(req: Request, max_body_size: usize) -> Response {
+        let ctx = crate::Context::new(crate::Config::for_tests(&format!(
+            "max_body_size = {max_body_size}\n"
+        )));
+
+        crate::http::middleware::decompress_requests(
+            ctx,
+            req,
+            Box::new(|req| Response::new(req.bytes().clone())),
+        )
+        .into_response()
+    }
+
+    #[test]
+    fn decompresses_request_bodies() {
+        let mut req = encoded_request("gzip", encoded(Encoding::Gzip, TEXT.as_bytes()));
+        decompress(&mut req, 1024).unwrap();
+
+        assert_eq!(req.bytes(), TEXT.as_bytes());
+        assert!(req.headers().get("content-encoding").is_none());
+        assert_eq!(
+            req.headers().get_all("content-length"),
+            Some(vec![TEXT.len().to_string()])
+        );
+    }
+
+    #[test]
+    fn removes_stacked_encodings_in_reverse_order() {
+        let body = encoded(
+            Encoding::Brotli,
+            &encoded(Encoding::Deflate, TEXT.as_bytes()),
+        );
+        let mut req = encoded_request("deflate, identity, br", body);
+        decompress(&mut req, 1024).unwrap();
+
+        assert_eq!(req.bytes(), TEXT.as_bytes());
+    }
+
+    #[test]
+    fn limits_the_size_of_decompressed_bodies() {
+        let body = encoded(Encoding::Gzip, &vec![0; 1024 * 1024]);
+        assert!(body.len() < 64 * 1024);
+
+        let mut req = encoded_request("gzip", body.clone());
+        assert!(matches!(
+            decompress(&mut req, 64 * 1024),
+            Err(DecodeError::TooLarge)
+        ));
+
+        let res = decompressed(encoded_request("gzip", body), 64 * 1024);
+        assert_eq!(*res.status(), StatusCode::ContentTooLarge);
+    }
+
+    #[test]
+    fn rejects_unsupported_encodings_with_415() {
+        let mut req = encoded_request("zstd", TEXT.as_bytes().to_vec());
+        assert!(matches!(
+            decompress(&mut req, 1024),
+            Err(DecodeError::Unsupported(name)) if name == "zstd"
+        ));
+
+        let res = decompressed(encoded_request("zstd", TEXT.as_bytes().to_vec()), 1024);
+        assert_eq!(*res.status(), StatusCode::UnsupportedMediaType);
+        assert_eq!(
+            res.headers().get("accept-encoding").as_deref(),
+            Some("gzip, deflate, br")
+        );
+    }
+
+    #[test]
+    fn rejects_corrupt_bodies_with_400() {
+        let mut req = encoded_request("gzip", TEXT.as_bytes().to_vec());
+        assert!(matches!(
+            decompress(&mut req, 1024),
+            Err(DecodeError::Invalid(_))
+        ));
+
+        let res = decompressed(encoded_request("gzip", TEXT.as_bytes().to_vec()), 1024);
+        assert_eq!(*res.status(), StatusCode::BadRequest);
+    }
+
+    #[test]
+    fn passes_through_identity_encoded_bodies() {
+        let res = decompressed(encoded_request("identity", TEXT.as_bytes().to_vec()), 1024);
+
+        assert_eq!(*res.status(), StatusCode::OK);
+        assert_eq!(
+            res.body().clone().unwrap().into_bytes().unwrap(),
+            TEXT.as_bytes()
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    any::Any,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use crate::{
+    config::Environment,
+    html::{escape, Html},
+    panic_hook::{take_last_panic, PanicDetails},
+    Context,
+};
+
+use super::{
+    request::Request,
+    response::{IntoResponse, Response},
+    router::RoutePattern,
+    StatusCode,
+};
+
+/// Turns a panic of a handler into a response.
+///
+/// Shows the debug error page in development, otherwise uses the error handler of the
+/// application or an empty 500 response.
+pub fn respond(ctx: &Context, req: &Request, payload: Box<dyn Any + Send>) -> Response {
+    let details = take_last_panic().unwrap_or_else(|| PanicDetails {
+        message: payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or("Box<dyn Any>".to_string()),
+        location: "unknown".to_string(),
+        backtrace: "the panic hook is not installed, so no backtrace was captured".to_string(),
+    });
+
+    if ctx.config.environment.unwrap_or_default() == Environment::Development {
+        let mut res = debug_page(req, &details).into_response();
+        *res.status_mut() = StatusCode::InternalServerError;
+
+        return res;
+    }
+
+    let Some(handler) = &ctx.error_handler else {
+        return StatusCode::InternalServerError.into_response();
+    };
+
+    match catch_unwind(AssertUnwindSafe(|| {
+        handler.call(ctx.clone(), req.clone()).into_response()
+    })) {
+        Ok(mut res) => {
+            if *res.status() == StatusCode::OK {
+                *res.status_mut() = StatusCode::InternalServerError;
+            }
+
+            res
+        }
+        Err(_) => StatusCode::InternalServerError.into_response(),
+    }
+}
+
+/// Renders the debug error page with the details of the panic and the request.
+fn debug_page(req: &Request, details: &PanicDetails) -> Html {
+    let mut headers = req
+        .headers()
+        .iter()
+        .flat_map(|(name, values)| {
+            values
+                .iter()
+                .map(move |value| (name.clone(), value.clone()))
+        })
+        .collect::<Vec<_>>();
+    headers.sort();
+
+    let mut params = req
+        .params
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect::<Vec<_>>();
+    params.sort();
+
+    #[cfg(feature = "sessions")]
+    let session = match &req.session {
+        Some(session) => {
+            let mut data = session
+                .data
+                .iter()
+                .map(|(key, value)| (key.clone(), value.to_string()))
+                .collect::<Vec<_>>();
+            data.sort();
+
+            table(&data)
+        }
+        None => "<p>No session.</p>".to_string(),
+    };
+    #[cfg(not(feature = "sessions"))]
+    let session = "<p>Sessions are disabled.</p>".to_string();
+
+    let route = req
+        .extensions()
+        .get::<RoutePattern>()
+        .map(|pattern| format!("{} {}", req.method(), pattern.as_str()))
+        .unwrap_or("-".to_string());
+
+    let nonce = req
+        .csp_nonce()
+        .map(|nonce| format!(" nonce=\"{}\"", escape(&nonce)))
+        .unwrap_or_default();
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{message}</title>
+<style{nonce}>
+body {{ font-family: system-ui, sans-serif; margin: 2rem; color: #1f2328; }}
+h1 {{ color: #cf222e; font-size: 1.5rem; }}
+h2 {{ font-size: 1.1rem; margin-top: 2rem; }}
+pre {{ background: #f6f8fa; padding: 1rem; overflow-x: auto; font-size: 0.8rem; }}
+table {{ border-collapse: collapse; }}
+td {{ border-bottom: 1px solid #d0d7de; padding: 0.25rem 1rem 0.25rem 0; vertical-align: top; font-family: monospace; }}
+</style>
+</head>
+<body>
+<h1>{message}</h1>
+<p>Panicked at <code>{location}</code> while handling <code>{method} {path}</code>.</p>
+<p>This page is shown because the environment is development. Never enable it in production.</p>
+<h2>Route</h2>
+<p><code>{route}</code></p>
+<h2>Parameters</h2>
+{params}
+<h2>Headers</h2>
+{headers}
+<h2>Session</h2>
+{session}
+<h2>Backtrace</h2>
+<pre>{backtrace}</pre>
+</body>
+</html>
+"#,
+        message = escape(&details.message),
+        location = escape(&details.location),
+        method = req.method(),
+        path = escape(&req.path()),
+        route = escape(&route),
+        params = table(&params),
+        headers = table(&headers),
+        backtrace = escape(&details.backtrace),
+    ))
+}
+
+/// Renders key-value pairs as a table.
+fn table(rows: &[(String, String)]) -> String {
+    if rows.is_empty() {
+        return "<p>None.</p>".to_string();
+    }
+
+    let rows = rows
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "<tr><td>{}</td><td>{}</td></tr>",
+                escape(key),
+                escape(value)
+            )
+        })
+        .collect::<String>();
+
+    format!("<table>{rows}</table>")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{http::security, Config, Method};
+
+    fn request() -> Request {
+        Request::builder()
+            .method(Method::Post)
+            .path("/posts/<1>")
+            .header("User-Agent", "<script>alert(1)</script>")
+            .build()
+    }
+
+    fn body(res: &Response) -> String {
+        String::from_utf8(res.body().clone().unwrap().into_bytes().unwrap()).unwrap()
+    }
+
+    fn panicked(ctx: &Context, req: &Request) -> Response {
+        respond(ctx, req, Box::new("could not parse <input> & \"value\""))
+    }
+
+    #[test]
+    fn shows_escaped_panic_details_in_development() {
+        let mut ctx = Context::new(Config::for_tests(""));
+        ctx.config.environment = Some(Environment::Development);
+
+        let res = panicked(&ctx, &request());
+        let body = body(&res);
+
+        assert_eq!(*res.status(), StatusCode::InternalServerError);
+        assert!(body.contains("<h1>could not parse &lt;input&gt; &amp; &quot;value&quot;</h1>"));
+        assert!(body.contains("<code>POST /posts/&lt;1&gt;</code>"));
+        assert!(body.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!body.contains("<script>"));
+    }
+
+    #[test]
+    fn allows_the_debug_page_styles_with_the_csp_nonce() {
+        let mut ctx = Context::new(Config::for_tests(""));
+        ctx.config.environment = Some(Environment::Development);
+        let config = ctx.config.security_headers.clone().unwrap_or_default();
+        let nonce = security::nonce(&config).unwrap();
+
+        let mut req = request();
+        req.extensions_mut().insert(nonce.clone());
+        let res = security::apply(panicked(&ctx, &req), &config, Some(&nonce));
+
+        assert!(body(&res).contains(&format!("<style nonce=\"{}\">", nonce.as_str())));
+        assert!(res
+            .headers()
+            .get("content-security-policy")
+            .unwrap()
+            .contains(&format!("style-src 'self' 'nonce-{}'", nonce.as_str())));
+    }
+
+    #[test]
+    fn hides_panic_details_in_production() {
+        let ctx = Context::new(Config::for_tests(""));
+
+        let res = panicked(&ctx, &request());
+
+        assert_eq!(*res.status(), StatusCode::InternalServerError);
+        assert!(res.body().is_none());
+    }
+
+    #[test]
+    fn uses_the_error_handler_in_production() {
+        let mut ctx = Context::new(Config::for_tests(""));
+        ctx.error_handler = Some(Arc::new(Box::new(|_, _| "Something went wrong.")));
+
+        let res = panicked(&ctx, &request());
+
+        assert_eq!(*res.status(), StatusCode::InternalServerError);
+        assert_eq!(body(&res), "Something went wrong.");
+
+        ctx.error_handler = Some(Arc::new(Box::new(|_, _| StatusCode::ServiceUnavailable)));
+        assert_eq!(
+            *panicked(&ctx, &request()).status(),
+            StatusCode::ServiceUnavailable
+        );
+    }
+
+    #[test]
+    fn answers_with_500_if_the_error_handler_panics() {
+        let mut ctx = Context::new(Config::for_tests(""));
+        ctx.error_handler = Some(Arc::new(Box::new(|_, _| -> &str { panic!("again") })));
+
+        let res = panicked(&ctx, &request());
+
+        assert_eq!(*res.status(), StatusCode::InternalServerError);
+        assert!(res.body().is_none());
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+};
+
+/// A map of values keyed by their type, used by middleware to pass data to other middleware and
+/// handlers.
+///
+/// ```
+/// use snx::request::Extensions;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct User {
+///     id: u64,
+/// }
+///
+/// let mut extensions = Extensions::new();
+/// extensions.insert(User { id: 1 });
+///
+/// assert_eq!(extensions.get::<User>(), Some(&User { id: 1 }));
+/// ```
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    /// Creates an empty map of extensions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a value, replacing the previous value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.map.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Gets a reference to the value of the given type.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Removes the value of the given type and returns whether it existed.
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> bool {
+        self.map.remove(&TypeId::of::<T>()).is_some()
+    }
+
+    /// Returns whether a value of the given type exists.
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
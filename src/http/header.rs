@@ -21,7 +21,7 @@ impl HeaderMap {
     /// headers.insert("Content-Type", "application/json");
     /// ```
     pub fn insert(&mut self, name: &str, value: &str) {
-        match self.0.get_mut(name) {
+        match self.0.get_mut(&name.to_lowercase()) {
             Some(values) => values.push(value.to_string()),
             None => {
                 self.0.insert(name.to_lowercase(), vec![value.to_string()]);
@@ -57,6 +57,20 @@ impl HeaderMap {
         self.0.get(&name.to_lowercase())?.first()
     }
 
+    /// Removes a header, returning its values if it existed.
+    ///
+    /// ```
+    /// use snx::HeaderMap;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert("Content-Type", "application/json");
+    ///
+    /// let content_type = headers.remove("Content-Type").unwrap();
+    /// ```
+    pub fn remove(&mut self, name: &str) -> Option<Vec<String>> {
+        self.0.remove(&name.to_lowercase())
+    }
+
     /// Gets all values of a header.
     ///
     /// ```
@@ -90,3 +104,22 @@ impl Deref for HeaderMap {
         &self.0
     }
 }
+
+/// Returns whether a list of header values contains the given item, ignoring case.
+fn contains(values: &[String], item: &str) -> bool {
+    values
+        .iter()
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case(item))
+}
+
+/// Adds the given headers to the `Vary` header of a response if they are not listed yet.
+pub(crate) fn vary(headers: &mut HeaderMap, names: &[&str]) {
+    let vary = headers.get_all("vary").unwrap_or_default();
+
+    for name in names {
+        if !contains(&vary, name) {
+            headers.insert("Vary", name);
+        }
+    }
+}
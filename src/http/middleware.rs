@@ -1,15 +1,19 @@
 use std::{
-    sync::Arc,
-    time::{Duration, SystemTime},
+    sync::{Arc, PoisonError},
+    time::{Instant, SystemTime},
 };
 
+#[cfg(feature = "sessions")]
 use jiff::Zoned;
 
-use crate::Context;
+use crate::{metrics, Context, HeaderMap, Method, StatusCode};
 
 use super::{
-    request::Request,
+    access_log, conditional, cors, range,
+    request::{Request, RequestId},
     response::{IntoResponse, Response},
+    router::RoutePattern,
+    security,
 };
 
 pub type MiddlewareHandler = Arc<
@@ -21,35 +25,504 @@ pub type MiddlewareHandler = Arc<
 >;
 
 /// Built-in middleware to trace requests.
+///
+/// Handles each request in an `http` span with the method, matched route pattern and host of the
+/// request, and records the status, latency and amount of bytes of the response on it once the
+/// request is handled. Log lines of handlers and panics nest under the span.
+///
+/// Afterwards an access log line is written in the format configured in the `access_log` section,
+/// either to the tracing subscriber or to the configured file.
 pub fn trace_requests(
+    ctx: Context,
+    req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    let time = SystemTime::now();
+    let now = Instant::now();
+    let host = req.headers().get("host");
+    let route = req
+        .extensions()
+        .get::<RoutePattern>()
+        .map(|pattern| pattern.as_str().to_string());
+
+    let span = tracing::info_span!(
+        "http",
+        method = %req.method(),
+        route = route.as_deref().unwrap_or("-"),
+        host = host.as_deref().unwrap_or("-"),
+        status = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+        bytes = tracing::field::Empty,
+    );
+
+    let res = span.in_scope(|| next(req.clone()));
+
+    let entry = access_log::Entry {
+        time,
+        host,
+        remote_addr: req.peer_addr().map(|addr| addr.to_string()),
+        remote_ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+        method: req.method().to_string(),
+        path: req.path(),
+        route,
+        status: u16::from(res.status().clone()),
+        bytes: res.body().as_ref().and_then(|body| body.len()).unwrap_or(0),
+        referer: req.headers().get("referer"),
+        user_agent: req.headers().get("user-agent"),
+        request_id: req
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.as_str().to_string()),
+        latency: now.elapsed(),
+    };
+
+    span.record("status", entry.status);
+    span.record("latency_ms", entry.latency.as_millis() as u64);
+    span.record("bytes", entry.bytes);
+
+    let line = entry.format(&ctx.config.access_log.unwrap_or_default());
+
+    match &ctx.access_log {
+        Some(file) => {
+            // a panic while writing a line cannot corrupt the file, so it can still be used
+            let mut file = file.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Err(err) = file.write_line(&line) {
+                span.in_scope(|| tracing::error!("failed to write access log: {err}"));
+            }
+        }
+        None => span.in_scope(|| tracing::info!("{line}")),
+    }
+
+    Box::new(res)
+}
+
+/// Built-in middleware to record HTTP metrics.
+///
+/// Records the amount of requests in flight, the amount of handled requests by method, matched
+/// route pattern and status class, and a histogram of their latency by method and route pattern
+/// to the metrics registry of the context.
+pub fn record_metrics(
+    ctx: Context,
+    req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    let in_flight = ctx.metrics.gauge(
+        "snx_http_requests_in_flight",
+        "Number of HTTP requests being handled.",
+        &[],
+    );
+
+    let now = Instant::now();
+    let res = {
+        let _in_flight = InFlight::start(in_flight);
+        next(req.clone())
+    };
+
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<RoutePattern>()
+        .map(RoutePattern::as_str)
+        .unwrap_or("-");
+    let status = format!("{}xx", u16::from(res.status().clone()) / 100);
+
+    ctx.metrics
+        .counter(
+            "snx_http_requests_total",
+            "Total number of handled HTTP requests.",
+            &[("method", &method), ("route", route), ("status", &status)],
+        )
+        .inc();
+    ctx.metrics
+        .histogram(
+            "snx_http_request_duration_seconds",
+            "Latency of handled HTTP requests in seconds.",
+            &[("method", &method), ("route", route)],
+            metrics::DEFAULT_BUCKETS,
+        )
+        .observe(now.elapsed().as_secs_f64());
+
+    Box::new(res)
+}
+
+/// Counts a request as in flight until it is dropped, so requests are no longer counted once they
+/// are handled, even if a handler panics.
+struct InFlight(metrics::Gauge);
+
+impl InFlight {
+    fn start(gauge: metrics::Gauge) -> Self {
+        gauge.inc();
+
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Built-in middleware to render error responses as problem details.
+///
+/// Turns error responses without a body, e.g. the 404 and 405 responses of the router, and
+/// responses created from a [crate::Error] into `application/problem+json` responses as defined
+/// in RFC 9457, if the client prefers JSON according to its `Accept` header. Apply it to a route
+/// group to only render the errors of those routes as problem details, or register it as global
+/// middleware before [compress_responses] to include the errors of the router.
+#[cfg(feature = "json")]
+pub fn problem_details(
     _: Context,
     req: Request,
     next: Box<dyn Fn(Request) -> Response>,
 ) -> Box<dyn IntoResponse> {
-    let now = SystemTime::now();
+    let res = next(req.clone());
+
+    match req.prefers_json() {
+        true => Box::new(crate::problem::apply(&req, res)),
+        false => Box::new(res),
+    }
+}
+
+/// Built-in middleware to identify requests.
+///
+/// Uses the identifier in the `X-Request-Id` header of the request if incoming identifiers are
+/// trusted, otherwise generates one. The identifier is added to the extensions of the request as
+/// a [RequestId], echoed in the response and attached to a tracing span, so every log line of the
+/// request carries it. Register it after [trace_requests] to include it in its log lines.
+pub fn request_id(
+    ctx: Context,
+    mut req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    use crate::config::RequestIdFormat;
+
+    let config = ctx.config.request_id.unwrap_or_default();
+    let header = config.header.as_deref().unwrap_or("X-Request-Id");
+
+    let id = req
+        .headers()
+        .get(header)
+        .filter(|id| config.trust_incoming.unwrap_or_default() && RequestId::is_valid(id))
+        .map(|id| RequestId::new(&id))
+        .unwrap_or_else(|| match config.format.unwrap_or_default() {
+            RequestIdFormat::UuidV7 => RequestId::uuid_v7(),
+            RequestIdFormat::Ulid => RequestId::ulid(),
+        });
+    req.extensions_mut().insert(id.clone());
+
+    let span = tracing::info_span!("request", request_id = %id);
+    let mut res = span.in_scope(|| next(req));
+
+    res.headers_mut().remove(header);
+    res.headers_mut().insert(header, id.as_str());
+
+    Box::new(res)
+}
 
+/// Built-in middleware to answer range requests.
+///
+/// Serves single and multiple byte ranges of responses with a buffered or seekable body, such as
+/// [crate::File] responses, and honors `If-Range` validators.
+pub fn serve_ranges(
+    _: Context,
+    req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
     let res = next(req.clone());
 
-    let elapsed = now.elapsed().unwrap();
+    Box::new(range::apply(&req, res))
+}
 
-    tracing::info!(
-        "{} {} \"{} {}\" {} {}B {}ms",
-        req.headers().get("host").unwrap_or("-".to_string()),
-        req.peer_addr().map(|p| p.to_string()).unwrap_or_default(),
-        req.method(),
-        req.path(),
-        res.status(),
-        res.body().clone().unwrap_or_default().len(),
-        elapsed.as_millis(),
+/// Built-in middleware to answer conditional requests.
+///
+/// Adds a weak `ETag` to successful GET and HEAD responses with a buffered body, unless the
+/// handler already supplied one, and answers `If-None-Match` and `If-Modified-Since` with a 304 Not
+/// Modified and failed `If-Match` and `If-Unmodified-Since` preconditions with a 412 Precondition
+/// Failed. Handlers modifying resources should use [Request::check_preconditions] instead.
+pub fn conditional_requests(
+    _: Context,
+    req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    let res = next(req.clone());
+
+    Box::new(conditional::apply(&req, res))
+}
+
+/// Built-in middleware to require conditional requests.
+///
+/// Answers PUT and DELETE requests without an `If-Match` or `If-Unmodified-Since` header with a
+/// 428 Precondition Required, preventing clients from overwriting changes they have not seen.
+pub fn require_preconditions(
+    _: Context,
+    req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    let headers = req.headers();
+
+    if matches!(req.method(), Method::Put | Method::Delete)
+        && headers.get("if-match").is_none()
+        && headers.get("if-unmodified-since").is_none()
+    {
+        return Box::new(StatusCode::PreconditionRequired);
+    }
+
+    Box::new(next(req))
+}
+
+/// Built-in middleware to handle cross-origin requests.
+///
+/// Adds CORS headers to responses for origins allowed by the `[cors]` section of the config and
+/// answers their preflight requests before they reach the router, so routes do not need to accept
+/// `OPTIONS` requests. Register it as global middleware.
+pub fn cors(
+    ctx: Context,
+    req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    let config = ctx.config.cors.unwrap_or_default();
+
+    if let Some(res) = cors::preflight(&req, &config) {
+        return Box::new(res);
+    }
+
+    let res = next(req.clone());
+
+    Box::new(cors::apply(&req, res, &config))
+}
+
+/// Built-in middleware to limit the rate of requests.
+///
+/// Counts requests against the rules in the `[rate_limit]` section of the config that match their
+/// path and method, per IP address, per authenticated user or globally. Requests over a limit are
+/// answered with a 429 Too Many Requests and a `Retry-After` header, and all limited responses
+/// include `RateLimit-*` headers describing the most restrictive limit. The IP address of requests
+/// from `trusted_proxies` is taken from their `X-Forwarded-For` header, see [Request::client_ip].
+pub fn limit_requests(
+    ctx: Context,
+    req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    use crate::{
+        config::{parse_duration, RateLimitKey},
+        rate_limit::{self, Decision},
+    };
+
+    let (Some(store), Some(config)) = (ctx.rate_limit_store, ctx.config.rate_limit) else {
+        return Box::new(next(req));
+    };
+
+    let path = req.path();
+    let method = req.method().to_string();
+    let ip = req
+        .client_ip(
+            ctx.config
+                .server
+                .trusted_proxies
+                .as_deref()
+                .unwrap_or_default(),
+        )
+        .map(|ip| ip.to_string())
+        .unwrap_or_default();
+
+    let mut strictest: Option<(Decision, u64)> = None;
+    for rule in &config.rules {
+        if !super::matches_path(&path, &rule.paths)
+            || rule
+                .methods
+                .as_ref()
+                .is_some_and(|methods| !methods.iter().any(|m| m.eq_ignore_ascii_case(&method)))
+        {
+            continue;
+        }
+
+        // Rules are checked when the config is loaded, so this only skips rules of configs that
+        // were not validated.
+        let Some(period) = parse_duration(&rule.period)
+            .ok()
+            .filter(|period| !period.is_zero() && rule.limit > 0)
+        else {
+            tracing::error!(
+                "invalid limit `{}` or period `{}` of rate limit `{}`",
+                rule.limit,
+                rule.period,
+                rule.name
+            );
+            continue;
+        };
+
+        let key = match rule.key.unwrap_or_default() {
+            RateLimitKey::Ip => format!("{}:ip:{ip}", rule.name),
+            RateLimitKey::User => {
+                #[cfg(feature = "sessions")]
+                let user = req.session.as_ref().and_then(|session| {
+                    session
+                        .get::<serde_json::Value>(config.user_key.as_deref().unwrap_or("user_id"))
+                        .ok()
+                        .flatten()
+                });
+                #[cfg(not(feature = "sessions"))]
+                let user: Option<String> = None;
+
+                match user {
+                    Some(user) => format!("{}:user:{user}", rule.name),
+                    None => format!("{}:ip:{ip}", rule.name),
+                }
+            }
+            RateLimitKey::Global => format!("{}:global", rule.name),
+        };
+
+        let decision = rate_limit::hit(
+            &*store,
+            &key,
+            rule.algorithm.unwrap_or_default(),
+            rule.limit,
+            period,
+            SystemTime::now(),
+        );
+
+        let decision = match decision {
+            Ok(decision) => decision,
+            Err(e) => {
+                tracing::error!(
+                    "could not count request for rate limit `{}`: {e}",
+                    rule.name
+                );
+                continue;
+            }
+        };
+
+        if strictest.is_none_or(|(strictest, _)| decision.remaining < strictest.remaining) {
+            strictest = Some((decision, period.as_secs()));
+        }
+
+        if !decision.allowed {
+            break;
+        }
+    }
+
+    let Some((decision, window)) = strictest else {
+        return Box::new(next(req));
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "RateLimit-Policy",
+        &format!("{};w={window}", decision.limit),
     );
+    headers.insert("RateLimit-Limit", &decision.limit.to_string());
+    headers.insert("RateLimit-Remaining", &decision.remaining.to_string());
+    headers.insert("RateLimit-Reset", &decision.reset.to_string());
 
-    Box::new(res)
+    if !decision.allowed {
+        headers.insert("Retry-After", &decision.retry_after.to_string());
+
+        return Box::new((StatusCode::TooManyRequests, headers));
+    }
+
+    Box::new((headers, next(req)))
+}
+
+/// Built-in middleware to add security headers to responses.
+///
+/// Adds `Strict-Transport-Security`, `X-Content-Type-Options`, `X-Frame-Options`,
+/// `Referrer-Policy`, `Permissions-Policy` and `Content-Security-Policy` headers with sensible
+/// defaults that can be changed in the `[security_headers]` section of the config. A nonce is
+/// generated for the content security policy of each request, which can be read using
+/// [Request::csp_nonce].
+pub fn security_headers(
+    ctx: Context,
+    mut req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    let config = ctx.config.security_headers.unwrap_or_default();
+
+    let nonce = security::nonce(&config);
+    if let Some(nonce) = &nonce {
+        req.extensions_mut().insert(nonce.clone());
+    }
+
+    let res = next(req);
+
+    Box::new(security::apply(res, &config, nonce.as_ref()))
+}
+
+/// Built-in middleware to compress responses.
+///
+/// Negotiates gzip, deflate or brotli based on the `Accept-Encoding` header of the request and
+/// skips responses that are small or of an already compressed content type.
+#[cfg(feature = "compression")]
+pub fn compress_responses(
+    ctx: Context,
+    req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    let res = next(req.clone());
+
+    let min_size = ctx
+        .config
+        .compression
+        .unwrap_or_default()
+        .min_size
+        .unwrap_or(super::encoding::DEFAULT_MIN_SIZE);
+
+    Box::new(super::encoding::compress(&req, res, min_size))
+}
+
+/// Built-in middleware to decompress request bodies.
+///
+/// Decodes gzip, deflate and brotli encoded request bodies based on the `Content-Encoding` header
+/// of the request. Requests with an unsupported encoding are answered with a 415 Unsupported Media
+/// Type, and requests whose decoded body exceeds the maximum body size with a 413 Content Too
+/// Large.
+#[cfg(feature = "compression")]
+pub fn decompress_requests(
+    ctx: Context,
+    mut req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    use super::encoding::DecodeError;
+
+    let max_body_size = ctx
+        .config
+        .server
+        .max_body_size
+        .unwrap_or(crate::server::DEFAULT_MAX_BODY_SIZE);
+
+    match super::encoding::decompress(&mut req, max_body_size) {
+        Ok(()) => Box::new(next(req)),
+        Err(e @ DecodeError::Unsupported(_)) => {
+            tracing::warn!("could not decompress request body: {e}");
+
+            Box::new((
+                StatusCode::UnsupportedMediaType,
+                HeaderMap::from(("Accept-Encoding", "gzip, deflate, br")),
+                (),
+            ))
+        }
+        Err(e @ DecodeError::TooLarge) => {
+            tracing::warn!("could not decompress request body: {e}");
+
+            Box::new(StatusCode::ContentTooLarge)
+        }
+        Err(e @ DecodeError::Invalid(_)) => {
+            tracing::warn!("could not decompress request body: {e}");
+
+            Box::new(StatusCode::BadRequest)
+        }
+    }
 }
 
 /// Built-in cookie-based middleware to initialize sessions.
 ///
 /// Retrieves the current session if it exists otherwise starts a new session and adds the session
 /// to the request.
+///
+/// The cookie of a new session is named and scoped according to the `[session]` configuration. By
+/// default it is sent for all paths, hidden from JavaScript, only sent over HTTPS outside of
+/// development, restricted to same-site requests and top-level navigations, and expires along
+/// with the session.
 #[cfg(feature = "sessions")]
 pub fn initialize_session(
     ctx: Context,
@@ -57,58 +530,577 @@ pub fn initialize_session(
     next: Box<dyn Fn(Request) -> Response>,
 ) -> Box<dyn IntoResponse> {
     if let Some(session_store) = ctx.session_store {
-        if let Some(cookies) = req.cookies().unwrap() {
-            if let Some(cookie) = cookies.get(
-                &ctx.config
-                    .session
-                    .clone()
-                    .unwrap_or_default()
-                    .cookie_key
-                    .unwrap_or("snx-session".to_string()),
-            ) {
-                if let Ok(id) = cookie.value().parse::<u128>() {
-                    let mut guard = session_store.try_lock().unwrap();
-                    if let Ok(Some(session)) = guard.load(id) {
-                        if session.expires_at > Zoned::now() {
-                            drop(guard);
-                            req.session = Some(session);
-                            return Box::new(next(req));
-                        }
-
-                        guard.delete(session.id).unwrap();
+        let config = ctx.config.session.clone().unwrap_or_default();
+        let cookie_key = config
+            .cookie_key
+            .clone()
+            .unwrap_or("snx-session".to_string());
+
+        // Malformed cookies are treated like a missing session, so the visitor gets a new one.
+        let id = req.cookies().ok().flatten().and_then(|cookies| {
+            cookies
+                .get(&cookie_key)
+                .and_then(|cookie| cookie.value().parse::<u128>().ok())
+        });
+
+        if let Some(id) = id {
+            let Ok(mut guard) = session_store.lock() else {
+                tracing::error!("could not load session: session store lock is poisoned");
+
+                return Box::new(StatusCode::InternalServerError);
+            };
+            if let Ok(Some(mut session)) = guard.load(id) {
+                if session.expires_at > Zoned::now() {
+                    drop(guard);
+                    if let Err(e) = session.age_flash() {
+                        tracing::error!("could not age flashed values of session: {e}");
+                        session.discard_flash();
                     }
+                    req.session = Some(session);
+                    return Box::new(next(req));
+                }
+
+                if let Err(e) = guard.delete(session.id) {
+                    tracing::error!("could not delete expired session: {e}");
                 }
             }
         }
 
-        let duration = crate::config::parse_duration(
-            &ctx.config
-                .session
-                .unwrap_or_default()
-                .expires_after
-                .unwrap_or("7d".to_string()),
-        )
-        .unwrap();
-        let session = crate::session::Session::new(
-            Zoned::now().checked_add(duration).unwrap(),
-            session_store.clone(),
-        );
+        // The duration is checked when the config is loaded, so this only fails for configs that
+        // were not validated.
+        let Some((duration, expires_at)) = config
+            .expires_after()
+            .ok()
+            .and_then(|duration| Some((duration, Zoned::now().checked_add(duration).ok()?)))
+        else {
+            tracing::error!("could not create session: invalid `expires_after`");
+
+            return Box::new(StatusCode::InternalServerError);
+        };
+        let session = crate::session::Session::new(expires_at, session_store.clone());
 
-        session_store
-            .try_lock()
-            .unwrap()
-            .create(session.clone())
-            .unwrap();
+        let created = session_store
+            .lock()
+            .map_err(|_| crate::session::Error::Poisoned)
+            .and_then(|mut store| store.create(session.clone()));
+        if let Err(e) = created {
+            tracing::error!("could not create session: {e}");
+
+            return Box::new(StatusCode::InternalServerError);
+        }
         req.session = Some(session.clone());
 
+        let same_site = config.same_site.unwrap_or_default();
+        let secure = same_site == crate::config::SameSite::None
+            || config.secure.unwrap_or(
+                ctx.config.environment.unwrap_or_default()
+                    != crate::config::Environment::Development,
+            );
+
+        let mut cookie = biscotti::ResponseCookie::new(cookie_key, session.id.to_string())
+            .set_path(config.path.unwrap_or("/".to_string()))
+            .set_http_only(config.http_only.unwrap_or(true))
+            .set_secure(secure)
+            .set_same_site(match same_site {
+                crate::config::SameSite::Strict => biscotti::SameSite::Strict,
+                crate::config::SameSite::Lax => biscotti::SameSite::Lax,
+                crate::config::SameSite::None => biscotti::SameSite::None,
+            })
+            .set_max_age(jiff::SignedDuration::try_from(duration).ok());
+        if let Some(domain) = config.domain {
+            cookie = cookie.set_domain(domain);
+        }
+
         let mut cookies = biscotti::ResponseCookies::new();
-        cookies.insert(biscotti::ResponseCookie::new(
-            "snx-session",
-            session.id.to_string(),
-        ));
+        cookies.insert(cookie);
+
+        return Box::new((cookies, next(req)));
+    }
+
+    Box::new(next(req))
+}
+
+/// Built-in middleware to protect against cross-site request forgery.
+///
+/// Issues a token per session and verifies it on requests with unsafe methods, which must submit
+/// it in the `X-CSRF-Token` header or the `_token` form field. Requests with a missing or
+/// mismatching token are answered with a 403 Forbidden, or a 419 Page Expired if configured. Must
+/// be registered before [initialize_session], unless the token is stored in a double-submit
+/// cookie.
+#[cfg(feature = "sessions")]
+pub fn verify_csrf_token(
+    ctx: Context,
+    mut req: Request,
+    next: Box<dyn Fn(Request) -> Response>,
+) -> Box<dyn IntoResponse> {
+    use crate::csrf;
+
+    let config = ctx.config.csrf.unwrap_or_default();
+    let double_submit = config.double_submit_cookie.unwrap_or_default();
+
+    let token = if double_submit {
+        req.cookies()
+            .ok()
+            .flatten()
+            .and_then(|cookies| cookies.get(csrf::COOKIE).map(|c| c.value().to_string()))
+            .filter(|token| csrf::is_valid(token))
+    } else {
+        match req.session.as_ref() {
+            Some(session) => session.get::<String>(csrf::SESSION_KEY).ok().flatten(),
+            None => {
+                tracing::warn!("csrf tokens are stored in the session, but no session was found");
+                None
+            }
+        }
+    };
+
+    let safe = matches!(
+        req.method(),
+        Method::Get | Method::Head | Method::Options | Method::Trace
+    );
+    if !safe && !super::matches_path(&req.path(), config.except.as_deref().unwrap_or_default()) {
+        let verified = match (&token, csrf::submitted(&req)) {
+            (Some(token), Some(submitted)) => csrf::verify(token, &submitted),
+            _ => false,
+        };
+
+        if !verified {
+            return Box::new(match config.status {
+                Some(419) => StatusCode::PageExpired,
+                _ => StatusCode::Forbidden,
+            });
+        }
+    }
+
+    let issued = token.is_none();
+    let token = token.unwrap_or_else(csrf::generate);
+    if issued && !double_submit {
+        if let Some(session) = req.session.as_mut() {
+            if let Err(e) = session.insert(csrf::SESSION_KEY, &token) {
+                tracing::error!("failed to store csrf token in session: {e}");
+
+                return Box::new(StatusCode::InternalServerError);
+            }
+        }
+    }
+    req.extensions_mut().insert(csrf::CsrfToken(token.clone()));
+
+    if double_submit && issued {
+        let mut cookies = biscotti::ResponseCookies::new();
+        cookies.insert(
+            biscotti::ResponseCookie::new(csrf::COOKIE, token)
+                .set_path("/")
+                .set_same_site(biscotti::SameSite::Lax),
+        );
 
         return Box::new((cookies, next(req)));
     }
 
     Box::new(next(req))
 }
+
+/// Defines a middleware that adds its name to the `X-Middleware` header of the response, so
+/// tests can assert in which order middleware ran.
+#[cfg(test)]
+macro_rules! tagging_middleware {
+    ($name:ident) => {
+        fn $name(
+            _: $crate::Context,
+            req: $crate::request::Request,
+            next: Box<dyn Fn($crate::request::Request) -> $crate::response::Response>,
+        ) -> Box<dyn $crate::response::IntoResponse> {
+            let mut res = next(req);
+            res.headers_mut().insert("X-Middleware", stringify!($name));
+
+            Box::new(res)
+        }
+    };
+}
+
+#[cfg(test)]
+pub(crate) use tagging_middleware;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(ctx: &Context, peer: &str, forwarded_for: &str) -> Response {
+        let req = Request::builder()
+            .path("/login")
+            .peer_addr(Some(peer.parse().unwrap()))
+            .header("X-Forwarded-For", forwarded_for)
+            .build();
+
+        limit_requests(ctx.clone(), req, Box::new(|_| Response::default())).into_response()
+    }
+
+    #[test]
+    fn limits_requests_per_client_behind_trusted_proxies() {
+        let mut ctx = Context::new(crate::Config::for_tests(
+            "trusted_proxies = [\"10.0.0.1\"]\n\
+             [rate_limit]\n\
+             [[rate_limit.rules]]\n\
+             name = \"login\"\n\
+             paths = [\"/login\"]\n\
+             limit = 1\n\
+             period = \"1m\"\n",
+        ));
+        ctx.rate_limit_store = Some(Arc::new(crate::rate_limit::MemoryRateLimitStore::default()));
+
+        let res = limited(&ctx, "10.0.0.1:1234", "203.0.113.7");
+        assert_eq!(*res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("ratelimit-remaining").as_deref(),
+            Some("0")
+        );
+
+        let res = limited(&ctx, "10.0.0.1:1234", "203.0.113.7");
+        assert_eq!(*res.status(), StatusCode::TooManyRequests);
+        assert_eq!(res.headers().get("retry-after").as_deref(), Some("60"));
+
+        // another client behind the same proxy has its own limit
+        let res = limited(&ctx, "10.0.0.1:1234", "203.0.113.8");
+        assert_eq!(*res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn does_not_limit_requests_without_matching_rules() {
+        let mut ctx = Context::new(crate::Config::for_tests(
+            "[rate_limit]\n[[rate_limit.rules]]\nname = \"api\"\npaths = [\"/api/*\"]\nlimit = 1\nperiod = \"1m\"\n",
+        ));
+        ctx.rate_limit_store = Some(Arc::new(crate::rate_limit::MemoryRateLimitStore::default()));
+
+        for _ in 0..3 {
+            let res = limited(&ctx, "203.0.113.7:1234", "");
+            assert_eq!(*res.status(), StatusCode::OK);
+            assert!(res.headers().get("ratelimit-limit").is_none());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_rate_limit_rules_when_the_config_loads() {
+        let rule = |limit: u32, period: &str| {
+            crate::Config::for_tests(&format!(
+                "[rate_limit]\n[[rate_limit.rules]]\nname = \"api\"\npaths = [\"/api/*\"]\nlimit = {limit}\nperiod = \"{period}\"\n",
+            ))
+            .validate()
+        };
+
+        for (limit, period) in [(0, "1m"), (1, "0s"), (1, "1"), (1, "1w")] {
+            assert!(matches!(
+                rule(limit, period),
+                Err(crate::config::InvalidConfigError::RateLimitRule(name)) if name == "api"
+            ));
+        }
+        assert!(rule(1, "1m").is_ok());
+    }
+
+    #[test]
+    fn stops_counting_requests_in_flight_if_handling_panics() {
+        let ctx = Context::new(crate::Config::for_tests(""));
+
+        let handled = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            record_metrics(
+                ctx.clone(),
+                Request::builder().build(),
+                Box::new(|_| panic!("boom")),
+            )
+        }));
+
+        assert!(handled.is_err());
+        assert_eq!(
+            ctx.metrics
+                .gauge(
+                    "snx_http_requests_in_flight",
+                    "Number of HTTP requests being handled.",
+                    &[],
+                )
+                .get(),
+            0
+        );
+    }
+
+    #[cfg(feature = "sessions")]
+    mod sessions {
+        use std::sync::Mutex;
+
+        use super::*;
+        use crate::{
+            session::{self, MemorySessionStore, Session, SessionStore},
+            Config,
+        };
+
+        /// A session store which cannot save sessions.
+        struct FailingStore;
+
+        impl SessionStore for FailingStore {
+            fn create(&mut self, _: Session) -> Result<(), session::Error> {
+                Ok(())
+            }
+
+            fn load(&mut self, _: u128) -> Result<Option<Session>, session::Error> {
+                Ok(None)
+            }
+
+            fn save(&mut self, _: &Session) -> Result<(), session::Error> {
+                Err(serde_json::from_str::<()>("").unwrap_err().into())
+            }
+
+            fn delete(&mut self, _: u128) -> Result<(), session::Error> {
+                Ok(())
+            }
+        }
+
+        fn request(method: Method, store: impl SessionStore + Send + Sync + 'static) -> Request {
+            let (session, _) = session::test_session(store);
+
+            let mut req = Request::builder().method(method).build();
+            req.session = Some(session);
+
+            req
+        }
+
+        fn verify(req: Request) -> Response {
+            verify_with("", req)
+        }
+
+        fn verify_with(config: &str, req: Request) -> Response {
+            verify_csrf_token(
+                Context::new(Config::for_tests(config)),
+                req,
+                Box::new(|req| Response::new(crate::csrf::token(&req).unwrap_or_default())),
+            )
+            .into_response()
+        }
+
+        #[test]
+        fn issues_a_token_for_safe_requests() {
+            let res = verify(request(Method::Get, MemorySessionStore::default()));
+
+            assert_eq!(*res.status(), StatusCode::OK);
+        }
+
+        #[test]
+        fn rejects_unsafe_requests_without_token() {
+            let res = verify(request(Method::Post, MemorySessionStore::default()));
+
+            assert_eq!(*res.status(), StatusCode::Forbidden);
+        }
+
+        #[test]
+        fn accepts_unsafe_requests_with_the_session_token() {
+            let mut req = request(Method::Post, MemorySessionStore::default());
+            let token = crate::csrf::generate();
+            req.session
+                .as_mut()
+                .unwrap()
+                .insert(crate::csrf::SESSION_KEY, &token)
+                .unwrap();
+            req.headers_mut().insert(crate::csrf::HEADER, &token);
+
+            assert_eq!(*verify(req).status(), StatusCode::OK);
+        }
+
+        #[test]
+        fn answers_with_the_configured_status() {
+            let res = verify_with(
+                "[csrf]\nstatus = 419\n",
+                request(Method::Post, MemorySessionStore::default()),
+            );
+            assert_eq!(*res.status(), StatusCode::PageExpired);
+
+            let config = Config::for_tests("[csrf]\nstatus = 400\n");
+            assert!(matches!(
+                config.validate(),
+                Err(crate::config::InvalidConfigError::CsrfStatus(400))
+            ));
+            assert!(Config::for_tests("[csrf]\nstatus = 419\n")
+                .validate()
+                .is_ok());
+        }
+
+        #[test]
+        fn does_not_verify_excepted_paths() {
+            let config = "[csrf]\nexcept = [\"/webhooks/*\", \"/ping\"]\n";
+            let excepted = |path: &str| {
+                let mut req = Request::builder().method(Method::Post).path(path).build();
+                req.session = request(Method::Post, MemorySessionStore::default()).session;
+
+                verify_with(config, req)
+            };
+
+            assert_eq!(*excepted("/webhooks/stripe").status(), StatusCode::OK);
+            assert_eq!(*excepted("/ping").status(), StatusCode::OK);
+            assert_eq!(*excepted("/ping/pong").status(), StatusCode::Forbidden);
+            assert_eq!(*excepted("/posts").status(), StatusCode::Forbidden);
+        }
+
+        #[test]
+        fn issues_a_double_submit_cookie() {
+            let config = "[csrf]\ndouble_submit_cookie = true\n";
+            let res = verify_with(config, Request::builder().method(Method::Get).build());
+            let token =
+                String::from_utf8(res.body().clone().unwrap().into_bytes().unwrap()).unwrap();
+
+            assert_eq!(*res.status(), StatusCode::OK);
+            assert!(crate::csrf::is_valid(&token));
+            let cookie = res.headers().get("set-cookie").unwrap();
+            assert!(cookie.starts_with(&format!("{}={token}", crate::csrf::COOKIE)));
+            assert!(!cookie.to_lowercase().contains("httponly"));
+
+            // an existing cookie is reused instead of issuing a new one
+            let req = Request::builder()
+                .method(Method::Get)
+                .header("Cookie", &format!("{}={token}", crate::csrf::COOKIE))
+                .build();
+            let res = verify_with(config, req);
+            assert!(res.headers().get("set-cookie").is_none());
+        }
+
+        #[test]
+        fn verifies_double_submit_cookies() {
+            let config = "[csrf]\ndouble_submit_cookie = true\n";
+            let token = crate::csrf::generate();
+            let submit = |cookie: &str, header: &str| {
+                let req = Request::builder()
+                    .method(Method::Post)
+                    .header("Cookie", &format!("{}={cookie}", crate::csrf::COOKIE))
+                    .header(crate::csrf::HEADER, header)
+                    .build();
+
+                verify_with(config, req)
+            };
+
+            assert_eq!(*submit(&token, &token).status(), StatusCode::OK);
+            assert_eq!(
+                *submit(&token, &crate::csrf::generate()).status(),
+                StatusCode::Forbidden
+            );
+            assert_eq!(*submit("forged", "forged").status(), StatusCode::Forbidden);
+        }
+
+        #[test]
+        fn fails_if_the_token_cannot_be_stored() {
+            let res = verify(request(Method::Get, FailingStore));
+
+            assert_eq!(*res.status(), StatusCode::InternalServerError);
+        }
+
+        #[test]
+        fn continues_without_flashed_values_that_cannot_be_aged() {
+            let (mut session, store) = session::test_session(MemorySessionStore::default());
+            session.insert("_flash", "not flashed values").unwrap();
+            session.insert("user_id", 1).unwrap();
+
+            let mut ctx = Context::new(Config::for_tests(""));
+            ctx.session_store = Some(store);
+            let req = Request::builder()
+                .header("Cookie", &format!("snx-session={}", session.id))
+                .build();
+
+            let res = initialize_session(
+                ctx,
+                req,
+                Box::new(|req| {
+                    let session = req.session.as_ref().unwrap();
+                    assert!(!session.data.contains_key("_flash"));
+                    assert_eq!(session.get::<u32>("user_id").unwrap(), Some(1));
+
+                    Response::default()
+                }),
+            )
+            .into_response();
+
+            assert_eq!(*res.status(), StatusCode::OK);
+        }
+
+        /// Initializes the session of a request with the given cookie header, returning the id of
+        /// the session the handler received.
+        fn initialize(
+            store: Arc<Mutex<Box<dyn SessionStore + Send + Sync>>>,
+            cookie: &str,
+        ) -> u128 {
+            let mut ctx = Context::new(Config::for_tests(""));
+            ctx.session_store = Some(store);
+            let req = Request::builder().header("Cookie", cookie).build();
+
+            let res = initialize_session(
+                ctx,
+                req,
+                Box::new(|req| Response::new(req.session.unwrap().id.to_string())),
+            )
+            .into_response();
+
+            assert_eq!(*res.status(), StatusCode::OK);
+            assert!(res.headers().get("set-cookie").is_some());
+
+            String::from_utf8(res.body().clone().unwrap().into_bytes().unwrap())
+                .unwrap()
+                .parse()
+                .unwrap()
+        }
+
+        #[test]
+        fn rejects_invalid_session_durations_when_the_config_loads() {
+            for expires_after in ["7", "7w", "-1d", "999999999999999999d"] {
+                let config =
+                    Config::for_tests(&format!("[session]\nexpires_after = \"{expires_after}\"\n"));
+
+                assert!(matches!(
+                    config.validate(),
+                    Err(crate::config::InvalidConfigError::SessionExpiresAfter(value))
+                        if value == expires_after
+                ));
+            }
+
+            assert!(Config::for_tests("[session]\nexpires_after = \"30m\"\n")
+                .validate()
+                .is_ok());
+        }
+
+        #[test]
+        fn starts_a_new_session_for_malformed_cookies() {
+            let (session, store) = session::test_session(MemorySessionStore::default());
+            let req = Request::builder().header("Cookie", "snx-session").build();
+            assert!(req.cookies().is_err());
+
+            assert_ne!(initialize(store.clone(), "snx-session"), session.id);
+            assert_ne!(initialize(store, "snx-session=abc"), session.id);
+        }
+
+        #[test]
+        fn starts_a_new_session_if_an_expired_one_cannot_be_deleted() {
+            /// A session store which cannot delete sessions.
+            #[derive(Default)]
+            struct UndeletableStore(MemorySessionStore);
+
+            impl SessionStore for UndeletableStore {
+                fn create(&mut self, session: Session) -> Result<(), session::Error> {
+                    self.0.create(session)
+                }
+
+                fn load(&mut self, id: u128) -> Result<Option<Session>, session::Error> {
+                    self.0.load(id)
+                }
+
+                fn save(&mut self, session: &Session) -> Result<(), session::Error> {
+                    self.0.save(session)
+                }
+
+                fn delete(&mut self, _: u128) -> Result<(), session::Error> {
+                    Err(serde_json::from_str::<()>("").unwrap_err().into())
+                }
+            }
+
+            let (mut session, store) = session::test_session(UndeletableStore::default());
+            session.expires_at = Zoned::now()
+                .checked_sub(jiff::Span::new().hours(1))
+                .unwrap();
+            store.lock().unwrap().save(&session).unwrap();
+
+            let id = initialize(store, &format!("snx-session={}", session.id));
+
+            assert_ne!(id, session.id);
+        }
+    }
+}
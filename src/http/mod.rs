@@ -1,11 +1,99 @@
 use std::{fmt, str};
 
+pub mod accept;
+pub(crate) mod access_log;
+pub(crate) mod conditional;
+pub(crate) mod cors;
+pub(crate) mod date;
+#[cfg(feature = "compression")]
+pub(crate) mod encoding;
+pub(crate) mod error_page;
+mod extensions;
 pub mod handler;
 pub mod header;
 pub mod middleware;
+pub(crate) mod range;
 pub mod request;
+mod request_id;
 pub mod response;
 pub mod router;
+pub(crate) mod security;
+mod static_dir;
+
+/// Escapes a string for use inside a JSON string literal.
+pub(crate) fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Encodes bytes as lowercase hexadecimal.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Creates an empty directory inside the temporary directory for a test, unique for this process,
+/// which is removed with its contents once the returned guard is dropped.
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> TempDir {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = std::env::temp_dir().join(format!(
+        "snx-{}-{}-{name}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    TempDir(dir)
+}
+
+/// A temporary directory for a test, see [temp_dir].
+#[cfg(test)]
+pub(crate) struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Returns whether a path matches one of the given patterns, where a trailing `*` matches any path
+/// with the preceding prefix. The query string and fragment of the path are ignored.
+pub(crate) fn matches_path(path: &str, patterns: &[String]) -> bool {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == pattern,
+        })
+}
 
 /// Represents an HTTP request method.
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
@@ -340,6 +428,11 @@ define_status_codes! {
     ///
     /// The server refuses the attempt to brew coffee with a teapot.
     (418, ImATeapot, "I'm a teapot"),
+    /// 419 Page Expired
+    ///
+    /// Unofficial status code used by some frameworks to indicate that a CSRF token is missing or
+    /// has expired, commonly because the session of the form has expired.
+    (419, PageExpired, "Page Expired"),
     /// 421 Misdirected Request
     ///
     /// The request was directed at a server that is not able to produce a response. This can be
@@ -457,6 +550,7 @@ impl fmt::Display for StatusCode {
     }
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for StatusCode {
     fn default() -> Self {
         Self::OK
@@ -0,0 +1,426 @@
+use std::{
+    collections::{hash_map::RandomState, VecDeque},
+    hash::{BuildHasher, Hasher},
+    io::{self, Cursor, Read, Seek, SeekFrom},
+};
+
+use super::{
+    date,
+    request::Request,
+    response::{Body, Response, SeekRead},
+    Method, StatusCode,
+};
+
+/// The maximum amount of ranges that will be served for a single request after overlapping and
+/// adjacent ranges are merged, requests for more ranges are answered with the full representation.
+const MAX_RANGES: usize = 16;
+
+/// Answers a range request using the body of the given response.
+///
+/// Responses with a buffered or seekable body are advertised with `Accept-Ranges`. If the request
+/// contains a satisfiable `Range` header, the response is turned into a `206 Partial Content`
+/// response containing the requested ranges, or into a `416 Range Not Satisfiable` response if
+/// none of the ranges can be satisfied.
+pub fn apply(req: &Request, mut res: Response) -> Response {
+    let Some(len) = rangeable_len(&res) else {
+        return res;
+    };
+
+    if res.headers().get("accept-ranges").is_none() {
+        res.headers_mut().insert("Accept-Ranges", "bytes");
+    }
+
+    if req.method() != Method::Get || *res.status() != StatusCode::OK {
+        return res;
+    }
+
+    let Some(header) = req.headers().get("range") else {
+        return res;
+    };
+
+    if !if_range_matches(req, &res) {
+        return res;
+    }
+
+    let ranges = match parse(&header, len) {
+        Some(Ok(ranges)) => ranges,
+        Some(Err(Unsatisfiable)) => {
+            *res.status_mut() = StatusCode::RangeNotSatisfiable;
+            *res.body_mut() = None;
+            res.headers_mut()
+                .insert("Content-Range", &format!("bytes */{len}"));
+
+            return res;
+        }
+        None => return res,
+    };
+
+    let body = res.body_mut().take().unwrap_or_default();
+    *res.status_mut() = StatusCode::PartialContent;
+
+    if let [(start, end)] = ranges[..] {
+        res.headers_mut()
+            .insert("Content-Range", &format!("bytes {start}-{end}/{len}"));
+        *res.body_mut() = slice(body, start, end);
+
+        return res;
+    }
+
+    let boundary = format!("{:016x}", RandomState::new().build_hasher().finish());
+    let content_type = res.headers_mut().remove("content-type");
+
+    let mut parts = VecDeque::new();
+    let mut total = 0;
+    for (i, (start, end)) in ranges.iter().enumerate() {
+        let mut head = String::new();
+        if i > 0 {
+            head.push_str("\r\n");
+        }
+        head.push_str(&format!("--{boundary}\r\n"));
+        if let Some(content_type) = content_type.as_ref().and_then(|v| v.first()) {
+            head.push_str(&format!("Content-Type: {content_type}\r\n"));
+        }
+        head.push_str(&format!("Content-Range: bytes {start}-{end}/{len}\r\n\r\n"));
+
+        total += head.len() as u64 + (end - start + 1);
+        parts.push_back(Part::Bytes(Cursor::new(head.into_bytes())));
+        parts.push_back(Part::Range {
+            start: *start,
+            len: end - start + 1,
+            pos: 0,
+        });
+    }
+    let tail = format!("\r\n--{boundary}--\r\n");
+    total += tail.len() as u64;
+    parts.push_back(Part::Bytes(Cursor::new(tail.into_bytes())));
+
+    res.headers_mut().insert(
+        "Content-Type",
+        &format!("multipart/byteranges; boundary={boundary}"),
+    );
+    *res.body_mut() = match body {
+        Body::Bytes(bytes) => {
+            let mut reader = MultipartReader {
+                reader: Box::new(Cursor::new(bytes)),
+                parts,
+            };
+            let mut buffer = Vec::with_capacity(total as usize);
+            reader
+                .read_to_end(&mut buffer)
+                .expect("reading from memory cannot fail");
+
+            Some(Body::Bytes(buffer))
+        }
+        stream => stream
+            .take_seekable()
+            .map(|reader| Body::from_reader(MultipartReader { reader, parts }, Some(total))),
+    };
+
+    res
+}
+
+/// Gets the length of the body of a response if ranges of it can be served.
+fn rangeable_len(res: &Response) -> Option<u64> {
+    res.body()
+        .as_ref()
+        .filter(|body| body.is_seekable())
+        .and_then(Body::len)
+}
+
+/// Returns whether the validator in the `If-Range` header of the request matches the response.
+///
+/// Returns `true` if the request has no `If-Range` header.
+fn if_range_matches(req: &Request, res: &Response) -> bool {
+    let Some(if_range) = req.headers().get("if-range") else {
+        return true;
+    };
+    let if_range = if_range.trim();
+
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return match res.headers().get("etag") {
+            Some(etag) => !etag.starts_with("W/") && etag == if_range,
+            None => false,
+        };
+    }
+
+    match (
+        date::parse(if_range),
+        res.headers()
+            .get("last-modified")
+            .and_then(|v| date::parse(&v)),
+    ) {
+        (Some(since), Some(modified)) => since == modified,
+        _ => false,
+    }
+}
+
+/// Indicates that none of the requested ranges can be satisfied.
+#[derive(Debug)]
+pub struct Unsatisfiable;
+
+/// Parses a `Range` header into a list of inclusive start and end offsets.
+///
+/// Returns `None` if the header is invalid or should otherwise be ignored.
+pub fn parse(value: &str, len: u64) -> Option<Result<Vec<(u64, u64)>, Unsatisfiable>> {
+    let specs = value.trim().strip_prefix("bytes=")?;
+    let mut ranges = Vec::new();
+
+    for spec in specs.split(',') {
+        let (start, end) = spec.trim().split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+
+        let range = match (start.is_empty(), end.is_empty()) {
+            (false, false) => {
+                let (start, end) = (start.parse::<u64>().ok()?, end.parse::<u64>().ok()?);
+                if start > end {
+                    return None;
+                }
+
+                (start, end)
+            }
+            (false, true) => (start.parse::<u64>().ok()?, u64::MAX),
+            (true, false) => {
+                let suffix = end.parse::<u64>().ok()?;
+                if suffix == 0 {
+                    continue;
+                }
+
+                (len.saturating_sub(suffix), u64::MAX)
+            }
+            (true, true) => return None,
+        };
+
+        if range.0 < len {
+            ranges.push((range.0, range.1.min(len - 1)));
+        }
+    }
+
+    if ranges.is_empty() {
+        return Some(Err(Unsatisfiable));
+    }
+
+    let ranges = coalesce(ranges);
+    if ranges.len() > MAX_RANGES {
+        return None;
+    }
+
+    Some(Ok(ranges))
+}
+
+/// Sorts ranges and merges the ones that overlap or are adjacent, so that no byte is served more
+/// than once.
+fn coalesce(mut ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    ranges.sort_unstable();
+
+    let mut coalesced: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match coalesced.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => coalesced.push((start, end)),
+        }
+    }
+
+    coalesced
+}
+
+/// Slices a body to the given inclusive range.
+fn slice(body: Body, start: u64, end: u64) -> Option<Body> {
+    let len = end - start + 1;
+
+    match body {
+        Body::Bytes(bytes) => Some(Body::Bytes(bytes[start as usize..=end as usize].to_vec())),
+        stream => {
+            let mut reader = stream.take_seekable()?;
+            reader.seek(SeekFrom::Start(start)).ok()?;
+
+            Some(Body::from_reader(reader.take(len), Some(len)))
+        }
+    }
+}
+
+enum Part {
+    Bytes(Cursor<Vec<u8>>),
+    Range { start: u64, len: u64, pos: u64 },
+}
+
+/// Reads the parts of a `multipart/byteranges` body, seeking through the underlying reader.
+struct MultipartReader {
+    reader: Box<dyn SeekRead>,
+    parts: VecDeque<Part>,
+}
+
+impl Read for MultipartReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(part) = self.parts.front_mut() {
+            let read = match part {
+                Part::Bytes(cursor) => cursor.read(buf)?,
+                Part::Range { start, len, pos } => {
+                    let remaining = (*len - *pos).min(buf.len() as u64) as usize;
+                    if remaining == 0 {
+                        0
+                    } else {
+                        self.reader.seek(SeekFrom::Start(*start + *pos))?;
+                        let read = self.reader.read(&mut buf[..remaining])?;
+                        if read == 0 {
+                            return Err(io::ErrorKind::UnexpectedEof.into());
+                        }
+                        *pos += read as u64;
+
+                        read
+                    }
+                }
+            };
+
+            if read > 0 {
+                return Ok(read);
+            }
+
+            self.parts.pop_front();
+        }
+
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranged(range: &str, body: Body) -> Response {
+        let req = Request::builder()
+            .method(Method::Get)
+            .header("Range", range)
+            .build();
+
+        apply(&req, Response::new(body))
+    }
+
+    fn body_bytes(res: Response) -> Vec<u8> {
+        res.body().clone().unwrap().into_bytes().unwrap()
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse("bytes=0-4", 10).unwrap().unwrap(), [(0, 4)]);
+        assert_eq!(parse("bytes=5-", 10).unwrap().unwrap(), [(5, 9)]);
+        assert_eq!(parse("bytes=-3", 10).unwrap().unwrap(), [(7, 9)]);
+        assert_eq!(parse("bytes=-30", 10).unwrap().unwrap(), [(0, 9)]);
+        assert_eq!(parse("bytes=8-20", 10).unwrap().unwrap(), [(8, 9)]);
+        assert_eq!(
+            parse("bytes= 0-1 , 4-5", 10).unwrap().unwrap(),
+            [(0, 1), (4, 5)]
+        );
+    }
+
+    #[test]
+    fn ignores_invalid_ranges() {
+        assert!(parse("items=0-4", 10).is_none());
+        assert!(parse("bytes=4-2", 10).is_none());
+        assert!(parse("bytes=-", 10).is_none());
+        assert!(parse("bytes=a-b", 10).is_none());
+        assert!(parse("bytes=0-99999999999999999999", 10).is_none());
+        let ranges = (0..=MAX_RANGES)
+            .map(|i| format!("{0}-{0}", i * 2))
+            .collect::<Vec<_>>();
+        assert!(parse(&format!("bytes={}", ranges.join(",")), 100).is_none());
+    }
+
+    #[test]
+    fn coalesces_overlapping_and_adjacent_ranges() {
+        assert_eq!(
+            parse("bytes=6-8,0-2,3-4,7-", 10).unwrap().unwrap(),
+            [(0, 4), (6, 9)]
+        );
+        assert_eq!(
+            parse(&format!("bytes={}", ["0-"; MAX_RANGES * 4].join(",")), 10)
+                .unwrap()
+                .unwrap(),
+            [(0, 9)]
+        );
+    }
+
+    #[test]
+    fn rejects_ranges_beyond_the_end() {
+        assert!(matches!(parse("bytes=10-", 10), Some(Err(Unsatisfiable))));
+        assert!(matches!(parse("bytes=-0", 10), Some(Err(Unsatisfiable))));
+    }
+
+    #[test]
+    fn serves_a_single_range_of_a_buffered_body() {
+        let res = ranged("bytes=6-", Body::from("hello world!"));
+
+        assert_eq!(*res.status(), StatusCode::PartialContent);
+        assert_eq!(
+            res.headers().get("content-range").as_deref(),
+            Some("bytes 6-11/12")
+        );
+        assert_eq!(body_bytes(res), b"world!");
+    }
+
+    #[test]
+    fn serves_a_single_range_of_a_seekable_body() {
+        let body = Body::from_seekable(Cursor::new(b"hello world!".to_vec()), 12);
+        let res = ranged("bytes=0-4", body);
+
+        assert_eq!(*res.status(), StatusCode::PartialContent);
+        assert_eq!(body_bytes(res), b"hello");
+    }
+
+    #[test]
+    fn serves_multiple_ranges_as_multipart() {
+        let body = Body::from_seekable(Cursor::new(b"hello world!".to_vec()), 12);
+        let res = ranged("bytes=0-4,6-10", body);
+        let content_type = res.headers().get("content-type").unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        let len = res.body().as_ref().and_then(Body::len);
+        let bytes = body_bytes(res);
+
+        assert_eq!(len, Some(bytes.len() as u64));
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            format!(
+                "--{boundary}\r\nContent-Range: bytes 0-4/12\r\n\r\nhello\r\n\
+                 --{boundary}\r\nContent-Range: bytes 6-10/12\r\n\r\nworld\r\n\
+                 --{boundary}--\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn answers_unsatisfiable_ranges_with_416() {
+        let res = ranged("bytes=20-", Body::from("hello world!"));
+
+        assert_eq!(*res.status(), StatusCode::RangeNotSatisfiable);
+        assert_eq!(
+            res.headers().get("content-range").as_deref(),
+            Some("bytes */12")
+        );
+        assert!(res.body().is_none());
+    }
+
+    #[test]
+    fn serves_the_full_body_if_the_if_range_validator_does_not_match() {
+        let req = Request::builder()
+            .method(Method::Get)
+            .header("Range", "bytes=0-4")
+            .header("If-Range", "\"v1\"")
+            .build();
+        let mut res = Response::new("hello world!");
+        res.headers_mut().insert("ETag", "\"v2\"");
+        let res = apply(&req, res);
+
+        assert_eq!(*res.status(), StatusCode::OK);
+        assert_eq!(body_bytes(res), b"hello world!");
+    }
+
+    #[test]
+    fn does_not_serve_ranges_of_unseekable_bodies() {
+        let body = Body::from_reader(Cursor::new(b"hello world!".to_vec()), Some(12));
+        let res = ranged("bytes=0-4", body);
+
+        assert_eq!(*res.status(), StatusCode::OK);
+        assert!(res.headers().get("accept-ranges").is_none());
+    }
+}
@@ -1,9 +1,25 @@
-use std::{collections::HashMap, net::SocketAddr, str};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    str,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
 
 #[cfg(feature = "cookies")]
 use biscotti::{errors::ParseError, Processor, ProcessorConfig, RequestCookies};
 
-use super::{header::HeaderMap, Method};
+pub use super::{extensions::Extensions, request_id::RequestId, security::CspNonce};
+
+use super::{
+    accept,
+    conditional::{self, ETag},
+    header::HeaderMap,
+    Method, StatusCode,
+};
 
 /// The maximum amount of headers that will be parsed.
 const HEADERS_COUNT: usize = 32;
@@ -17,6 +33,8 @@ pub struct Request {
     headers: HeaderMap,
     peer_addr: Option<SocketAddr>,
     pub params: HashMap<String, String>,
+    extensions: Extensions,
+    cancelled: Arc<AtomicBool>,
     #[cfg(feature = "sessions")]
     pub session: Option<crate::session::Session>,
 }
@@ -74,6 +92,18 @@ impl Request {
         self.headers.clone()
     }
 
+    /// Gets a mutable reference to the headers for this request.
+    ///
+    /// ```
+    /// use snx::request::Request;
+    ///
+    /// let mut request = Request::builder().path("/").build();
+    /// request.headers_mut().insert("X-Forwarded-For", "127.0.0.1");
+    /// ```
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
     /// Gets the cookies for this request.
     ///
     /// ```
@@ -83,7 +113,7 @@ impl Request {
     /// let cookies = request.cookies();
     /// ```
     #[cfg(feature = "cookies")]
-    pub fn cookies(&self) -> Result<Option<RequestCookies>, ParseError> {
+    pub fn cookies(&self) -> Result<Option<RequestCookies<'_>>, ParseError> {
         if let Some(value) = self.headers.get_ref("cookie") {
             let processor: Processor = ProcessorConfig::default().into();
             return Ok(Some(RequestCookies::parse_header(value, &processor)?));
@@ -111,6 +141,72 @@ impl Request {
         self.peer_addr
     }
 
+    /// Gets the address of the client that made this request.
+    ///
+    /// If the request was made by one of the trusted proxies, the address is taken from the
+    /// `X-Forwarded-For` header, skipping trusted proxies from right to left, since only the
+    /// addresses added by trusted proxies can be relied on.
+    ///
+    /// ```
+    /// use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    ///
+    /// use snx::request::Request;
+    ///
+    /// let proxy = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    /// let request = Request::builder()
+    ///     .peer_addr(Some(SocketAddr::new(proxy, 8080)))
+    ///     .header("X-Forwarded-For", "203.0.113.7")
+    ///     .build();
+    ///
+    /// assert_eq!(
+    ///     request.client_ip(&[proxy]),
+    ///     Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)))
+    /// );
+    /// ```
+    pub fn client_ip(&self, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+        let mut ip = self.peer_addr?.ip();
+        let forwarded = self.headers.get_all("x-forwarded-for").unwrap_or_default();
+        let mut forwarded = forwarded.iter().flat_map(|value| value.split(',')).rev();
+
+        while trusted_proxies.contains(&ip) {
+            let Some(next) = forwarded.next().and_then(|v| v.trim().parse().ok()) else {
+                break;
+            };
+            ip = next;
+        }
+
+        Some(ip)
+    }
+
+    /// Gets a reference to the extensions for this request, which contain values added by
+    /// middleware.
+    ///
+    /// ```
+    /// use snx::request::{Request, RequestId};
+    ///
+    /// let request = Request::builder().build();
+    /// let request_id = request.extensions().get::<RequestId>();
+    /// ```
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Gets a mutable reference to the extensions for this request.
+    ///
+    /// ```
+    /// use snx::request::Request;
+    ///
+    /// struct User {
+    ///     id: u64,
+    /// }
+    ///
+    /// let mut request = Request::builder().build();
+    /// request.extensions_mut().insert(User { id: 1 });
+    /// ```
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
     /// Gets a reference to the body as raw bytes.
     ///
     /// ```
@@ -149,6 +245,144 @@ impl Request {
         str::from_utf8(&self.body).map(|s| s.to_string())
     }
 
+    /// Gets a mutable reference to the body as raw bytes.
+    ///
+    /// ```
+    /// use snx::request::Request;
+    ///
+    /// let mut request = Request::builder().build();
+    /// *request.bytes_mut() = "hello world!".as_bytes().to_vec();
+    /// ```
+    pub fn bytes_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.body
+    }
+
+    /// Gets the nonce of the content security policy for this request, which allows inline scripts
+    /// with a matching `nonce` attribute to run.
+    ///
+    /// Returns `None` if the request was not handled by the security headers middleware or its
+    /// policy does not use a nonce.
+    ///
+    /// ```
+    /// use snx::{request::Request, template};
+    ///
+    /// let request = Request::builder().build();
+    ///
+    /// let html = template! {
+    ///     <script nonce=request.csp_nonce().unwrap_or_default()>
+    ///         "console.log('hello world!');"
+    ///     </script>
+    /// };
+    /// ```
+    pub fn csp_nonce(&self) -> Option<String> {
+        self.extensions
+            .get::<CspNonce>()
+            .map(|nonce| nonce.as_str().to_string())
+    }
+
+    /// Returns whether this request has been cancelled, e.g. because it exceeded the timeout of its
+    /// route and the client has already been answered.
+    ///
+    /// Long-running handlers can check this flag to stop doing work that is no longer needed.
+    ///
+    /// ```
+    /// use snx::request::Request;
+    ///
+    /// let request = Request::builder().build();
+    ///
+    /// assert!(!request.is_cancelled());
+    /// ```
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Cancels this request and all of its clones.
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the client accepts one of the given media types according to its `Accept`
+    /// header. A missing header accepts any media type.
+    ///
+    /// ```
+    /// use snx::request::Request;
+    ///
+    /// let request = Request::builder()
+    ///     .header("Accept", "text/html, application/*;q=0.5")
+    ///     .build();
+    ///
+    /// assert!(request.accepts(&["application/json"]));
+    /// assert!(!request.accepts(&["image/png"]));
+    /// ```
+    pub fn accepts(&self, media_types: &[&str]) -> bool {
+        self.preferred_type(media_types).is_some()
+    }
+
+    /// Picks the media type the client prefers out of the given media types according to its
+    /// `Accept` header, or `None` if it accepts none of them. Ties are broken by the order of the
+    /// given media types.
+    ///
+    /// ```
+    /// use snx::request::Request;
+    ///
+    /// let request = Request::builder()
+    ///     .header("Accept", "text/html;q=0.9, application/json")
+    ///     .build();
+    ///
+    /// assert_eq!(
+    ///     request.preferred_type(&["text/html", "application/json"]),
+    ///     Some("application/json")
+    /// );
+    /// ```
+    pub fn preferred_type<'a>(&self, media_types: &[&'a str]) -> Option<&'a str> {
+        accept::preferred(
+            self.headers.get_ref("accept").map(String::as_str),
+            media_types,
+        )
+    }
+
+    /// Returns whether the client prefers JSON over HTML according to its `Accept` header, which
+    /// must list a JSON media type explicitly. Used to pick the format of framework errors.
+    #[cfg(any(feature = "json", feature = "forms"))]
+    pub(crate) fn prefers_json(&self) -> bool {
+        self.headers.get_ref("accept").is_some()
+            && matches!(
+                self.preferred_type(&["text/html", "application/problem+json", "application/json"]),
+                Some("application/problem+json" | "application/json")
+            )
+    }
+
+    /// Evaluates the conditional headers of this request against the current validators of the
+    /// target resource, which should be done before modifying it to prevent lost updates.
+    ///
+    /// Returns `Err(StatusCode::PreconditionFailed)` if a precondition failed, or
+    /// `Err(StatusCode::NotModified)` if the client's cached representation of a GET or HEAD
+    /// request is still valid.
+    ///
+    /// ```
+    /// use snx::{request::Request, ETag, Method, StatusCode};
+    ///
+    /// let request = Request::builder()
+    ///     .method(Method::Put)
+    ///     .header("If-Match", "\"41\"")
+    ///     .build();
+    ///
+    /// assert_eq!(
+    ///     request.check_preconditions(Some(&ETag::strong("42")), None),
+    ///     Err(StatusCode::PreconditionFailed)
+    /// );
+    /// ```
+    pub fn check_preconditions(
+        &self,
+        etag: Option<&ETag>,
+        last_modified: Option<SystemTime>,
+    ) -> Result<(), StatusCode> {
+        match conditional::evaluate(self, etag, last_modified) {
+            Some(status) => Err(status),
+            None => Ok(()),
+        }
+    }
+
     /// Tries to deserialize the JSON body into the specified struct.
     #[cfg(feature = "json")]
     pub fn json<T: serde::de::DeserializeOwned>(
@@ -157,6 +391,112 @@ impl Request {
         serde_json::from_slice::<T>(&self.body).map_err(|e| e.into())
     }
 
+    /// Tries to deserialize the JSON body into the specified struct and validates it.
+    ///
+    /// Fails with `400 Bad Request` if the body is malformed, or with `422 Unprocessable Content`
+    /// and the errors per field as JSON if it is invalid.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use snx::{
+    ///     request::Request,
+    ///     validation::{Validate, ValidationErrors, Validator},
+    ///     Context, Error,
+    /// };
+    ///
+    /// #[derive(Deserialize)]
+    /// struct NewPost {
+    ///     title: String,
+    /// }
+    ///
+    /// impl Validate for NewPost {
+    ///     fn validate(&self) -> Result<(), ValidationErrors> {
+    ///         Validator::new().required("title", &self.title).finish()
+    ///     }
+    /// }
+    ///
+    /// fn store(_: Context, req: Request) -> Result<String, Error> {
+    ///     let post = req.validated_json::<NewPost>()?;
+    ///
+    ///     Ok(post.title)
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn validated_json<T: serde::de::DeserializeOwned + crate::validation::Validate>(
+        &self,
+    ) -> Result<T, crate::Error> {
+        let payload = self.json::<T>()?;
+        payload.validate()?;
+
+        Ok(payload)
+    }
+
+    /// Tries to deserialize the URL-encoded form body into the specified struct.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use snx::request::Request;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Search {
+    ///     query: String,
+    /// }
+    ///
+    /// let request = Request::builder()
+    ///     .body("query=hello+world%21".as_bytes().to_vec())
+    ///     .build();
+    ///
+    /// assert_eq!(request.form::<Search>().unwrap().query, "hello world!");
+    /// ```
+    #[cfg(feature = "forms")]
+    pub fn form<T: serde::de::DeserializeOwned>(
+        &self,
+    ) -> Result<T, crate::form::InvalidFormBodyError> {
+        serde_urlencoded::from_bytes::<T>(&self.body).map_err(|e| e.into())
+    }
+
+    /// Tries to deserialize the URL-encoded form body into the specified struct and validates it.
+    ///
+    /// Fails with `400 Bad Request` if the body is malformed. If it is invalid and the client
+    /// prefers JSON or has no session, fails with `422 Unprocessable Content` and the errors per
+    /// field as JSON. Otherwise the errors and the submitted input (except passwords and the CSRF
+    /// token) are flashed into the session and the client is redirected back to the form with
+    /// `303 See Other`, so it can be rendered again with [crate::validation::errors] and
+    /// [crate::validation::old].
+    #[cfg(feature = "forms")]
+    pub fn validated_form<T: serde::de::DeserializeOwned + crate::validation::Validate>(
+        &self,
+    ) -> Result<T, crate::Error> {
+        let payload = self.form::<T>()?;
+        let Err(errors) = payload.validate() else {
+            return Ok(payload);
+        };
+
+        let prefers_json = self.prefers_json();
+
+        #[cfg(feature = "sessions")]
+        if let (false, Some(mut session)) = (prefers_json, self.session.clone()) {
+            session.flash(crate::validation::ERRORS_FLASH_KEY, &errors)?;
+            session.flash(
+                crate::validation::OLD_INPUT_FLASH_KEY,
+                crate::validation::flashable_input(self),
+            )?;
+
+            let back = crate::redirect::local_referer(self).unwrap_or_else(|| self.path.clone());
+
+            return Err(
+                crate::Error::new(StatusCode::SeeOther, "The given data was invalid.")
+                    .with_validation_errors(errors)
+                    .header("Location", &back),
+            );
+        }
+
+        #[cfg(not(feature = "sessions"))]
+        let _ = prefers_json;
+
+        Err(errors.into())
+    }
+
     /// Tries to parse a request object from a buffer of bytes.
     ///
     /// ```
@@ -193,7 +533,12 @@ impl Request {
                     let length = length
                         .parse::<usize>()
                         .map_err(|_| ParseRequestError::ContentLengthInvalid)?;
-                    let range = &buffer[start_of_body..(start_of_body + length)];
+                    let end = start_of_body
+                        .checked_add(length)
+                        .ok_or(ParseRequestError::ContentLengthTooLarge)?;
+                    let range = buffer
+                        .get(start_of_body..end)
+                        .ok_or(ParseRequestError::Partial)?;
 
                     request = request.body(range.to_vec());
                 }
@@ -222,6 +567,31 @@ pub enum ParseRequestError {
     General(#[from] httparse::Error),
     #[error("value of content length header is an invalid unsigned integer")]
     ContentLengthInvalid,
+    #[error("value of content length header is too large")]
+    ContentLengthTooLarge,
+}
+
+/// Gets the length of the body announced in the `Content-Length` header of the request in the
+/// buffer, so it can be checked before the body is received. Returns `None` if the head of the
+/// request is incomplete or the header is missing.
+pub(crate) fn announced_body_length(buffer: &[u8]) -> Result<Option<usize>, ParseRequestError> {
+    let mut headers = [httparse::EMPTY_HEADER; HEADERS_COUNT];
+    let mut req = httparse::Request::new(&mut headers);
+
+    if req.parse(buffer)?.is_partial() {
+        return Ok(None);
+    }
+
+    req.headers
+        .iter()
+        .find(|header| header.name.eq_ignore_ascii_case("content-length"))
+        .map(|header| {
+            str::from_utf8(header.value)?
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| ParseRequestError::ContentLengthInvalid)
+        })
+        .transpose()
 }
 
 /// An HTTP request builder.
@@ -231,7 +601,6 @@ pub struct Builder {
     body: Vec<u8>,
     headers: HeaderMap,
     peer_addr: Option<SocketAddr>,
-    params: Option<HashMap<String, String>>,
 }
 
 impl Builder {
@@ -331,6 +700,8 @@ impl Builder {
             body: self.body.clone(),
             headers: self.headers.clone(),
             params: Default::default(),
+            extensions: Default::default(),
+            cancelled: Default::default(),
             #[cfg(feature = "sessions")]
             session: None,
         }
@@ -344,8 +715,184 @@ impl Default for Builder {
             path: "/".to_string(),
             body: vec![],
             headers: HeaderMap::new(),
-            params: Default::default(),
             peer_addr: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_complete_request() {
+        let request = Request::try_parse_from_bytes(
+            b"POST /posts HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello",
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(request.method(), Method::Post);
+        assert_eq!(request.path(), "/posts");
+        assert_eq!(request.bytes(), b"hello");
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_the_body() {
+        let result = Request::try_parse_from_bytes(
+            b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello",
+            None,
+        );
+
+        assert!(matches!(result, Err(ParseRequestError::Partial)));
+    }
+
+    #[test]
+    fn rejects_an_overflowing_content_length() {
+        let buffer = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", usize::MAX);
+        let result = Request::try_parse_from_bytes(buffer.as_bytes(), None);
+
+        assert!(matches!(
+            result,
+            Err(ParseRequestError::ContentLengthTooLarge)
+        ));
+    }
+
+    #[test]
+    fn rejects_an_invalid_content_length() {
+        let result =
+            Request::try_parse_from_bytes(b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n", None);
+
+        assert!(matches!(
+            result,
+            Err(ParseRequestError::ContentLengthInvalid)
+        ));
+    }
+
+    #[test]
+    fn announces_the_body_length_once_the_head_is_complete() {
+        assert_eq!(
+            announced_body_length(b"POST / HTTP/1.1\r\nContent-Len").unwrap(),
+            None
+        );
+        assert_eq!(
+            announced_body_length(b"POST / HTTP/1.1\r\ncontent-length: 1048576\r\n\r\n").unwrap(),
+            Some(1048576)
+        );
+        assert_eq!(
+            announced_body_length(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap(),
+            None
+        );
+        assert!(announced_body_length(
+            b"POST / HTTP/1.1\r\nContent-Length: 99999999999999999999999\r\n\r\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn client_ip_is_the_peer_address_without_trusted_proxies() {
+        let request = Request::builder()
+            .peer_addr(Some("203.0.113.7:1234".parse().unwrap()))
+            .header("X-Forwarded-For", "198.51.100.1")
+            .build();
+
+        assert_eq!(request.client_ip(&[]), "203.0.113.7".parse().ok());
+    }
+
+    #[test]
+    fn client_ip_skips_trusted_proxies_in_x_forwarded_for() {
+        let proxies = ["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        let request = Request::builder()
+            .peer_addr(Some("10.0.0.1:1234".parse().unwrap()))
+            .header("X-Forwarded-For", "198.51.100.1, 203.0.113.7")
+            .header("X-Forwarded-For", "10.0.0.2")
+            .build();
+
+        // the leftmost address was sent by the client and could be spoofed
+        assert_eq!(request.client_ip(&proxies), "203.0.113.7".parse().ok());
+    }
+
+    #[test]
+    fn client_ip_is_the_proxy_if_x_forwarded_for_is_invalid() {
+        let proxies = ["10.0.0.1".parse().unwrap()];
+        let request = Request::builder()
+            .peer_addr(Some("10.0.0.1:1234".parse().unwrap()))
+            .header("X-Forwarded-For", "unknown")
+            .build();
+
+        assert_eq!(request.client_ip(&proxies), "10.0.0.1".parse().ok());
+    }
+
+    #[cfg(all(feature = "forms", feature = "sessions"))]
+    mod validated_form {
+        use super::*;
+        use crate::{
+            response::IntoResponse,
+            session::{self, MemorySessionStore},
+            validation::{self, Validate, ValidationErrors, Validator},
+        };
+
+        #[derive(serde::Deserialize, Debug)]
+        struct Payload {
+            title: String,
+        }
+
+        impl Validate for Payload {
+            fn validate(&self) -> Result<(), ValidationErrors> {
+                Validator::new().required("title", &self.title).finish()
+            }
+        }
+
+        fn submit(builder: Builder) -> (Request, crate::response::Response) {
+            let (session, store) = session::test_session(MemorySessionStore::default());
+
+            let mut req = builder
+                .method(Method::Post)
+                .path("/posts")
+                .header("Host", "acme.com")
+                .body(b"title=&password=secret&_token=abc".to_vec())
+                .build();
+            req.session = Some(session);
+
+            let res = req.validated_form::<Payload>().unwrap_err().into_response();
+            let session = store.lock().unwrap().load(req.session.as_ref().unwrap().id);
+            req.session = session.unwrap();
+            req.session.as_mut().unwrap().age_flash().unwrap();
+
+            (req, res)
+        }
+
+        #[test]
+        fn redirects_back_with_errors_and_old_input() {
+            let (req, res) =
+                submit(Request::builder().header("Referer", "https://acme.com/posts/new?draft=1"));
+
+            assert_eq!(*res.status(), StatusCode::SeeOther);
+            assert_eq!(
+                res.headers().get("location").as_deref(),
+                Some("/posts/new?draft=1")
+            );
+            assert!(validation::errors(&req).has("title"));
+            assert_eq!(validation::old(&req, "title").as_deref(), Some(""));
+            assert_eq!(validation::old(&req, "password"), None);
+            assert_eq!(validation::old(&req, crate::csrf::FIELD), None);
+        }
+
+        #[test]
+        fn does_not_redirect_to_other_hosts() {
+            let (_, res) =
+                submit(Request::builder().header("Referer", "https://evil.example/phish"));
+
+            assert_eq!(res.headers().get("location").as_deref(), Some("/posts"));
+        }
+
+        #[test]
+        fn responds_with_json_if_preferred() {
+            let (req, res) = submit(Request::builder().header("Accept", "application/json"));
+
+            assert_eq!(*res.status(), StatusCode::UnprocessableContent);
+            assert!(res.headers().get("location").is_none());
+            assert!(validation::errors(&req).is_empty());
+        }
+    }
+}
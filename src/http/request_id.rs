@@ -0,0 +1,110 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rand::Rng;
+
+/// The identifier of a request, used to correlate log lines across services.
+///
+/// Added to the extensions of a request by the [crate::middleware::request_id] middleware.
+///
+/// ```
+/// use snx::request::{Request, RequestId};
+///
+/// let mut request = Request::builder().build();
+/// request.extensions_mut().insert(RequestId::new("7d0b3a52"));
+///
+/// let id = request.extensions().get::<RequestId>().unwrap();
+/// assert_eq!(id.as_str(), "7d0b3a52");
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Creates a request identifier from an existing value.
+    pub fn new(id: &str) -> Self {
+        Self(id.to_string())
+    }
+
+    /// Generates a UUIDv7, which is sortable by the time it was generated.
+    ///
+    /// ```
+    /// use snx::request::RequestId;
+    ///
+    /// let id = RequestId::uuid_v7();
+    ///
+    /// assert_eq!(id.as_str().len(), 36);
+    /// assert_eq!(&id.as_str()[14..15], "7");
+    /// ```
+    pub fn uuid_v7() -> Self {
+        let mut bytes = rand::rng().random::<[u8; 16]>();
+        bytes[..6].copy_from_slice(&timestamp().to_be_bytes()[2..]);
+        bytes[6] = (bytes[6] & 0x0f) | 0x70;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex = super::hex(&bytes);
+
+        Self(format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        ))
+    }
+
+    /// Generates a ULID, which is sortable by the time it was generated.
+    ///
+    /// ```
+    /// use snx::request::RequestId;
+    ///
+    /// let id = RequestId::ulid();
+    ///
+    /// assert_eq!(id.as_str().len(), 26);
+    /// ```
+    pub fn ulid() -> Self {
+        const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+        let random = rand::rng().random::<u128>() >> 48;
+        let value = ((timestamp() as u128) << 80) | random;
+
+        Self(
+            (0..26)
+                .rev()
+                .map(|i| ALPHABET[((value >> (i * 5)) & 0x1f) as usize] as char)
+                .collect(),
+        )
+    }
+
+    /// Gets the identifier as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns whether an incoming identifier is safe to use in headers and log lines.
+    pub(crate) fn is_valid(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= 128
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Gets the amount of milliseconds since the Unix epoch, truncated to 48 bits.
+fn timestamp() -> u64 {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    millis & 0xffff_ffff_ffff
+}
@@ -0,0 +1,290 @@
+use std::{
+    fmt,
+    io::{self, Read, Seek, Write},
+    sync::{Arc, Mutex},
+};
+
+/// Represents a reader which can also seek.
+pub trait SeekRead: Read + Seek + Send {}
+
+impl<T> SeekRead for T where T: Read + Seek + Send {}
+
+enum Reader {
+    Read(Box<dyn Read + Send>),
+    Seek(Box<dyn SeekRead>),
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Reader::Read(reader) => reader.read(buf),
+            Reader::Seek(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Represents the body of a response.
+///
+/// A body is either fully buffered in memory or streamed from a reader while the response is
+/// written to the client. Clones of a streamed body share the same reader, so a streamed body can
+/// only be consumed once.
+#[derive(Clone)]
+pub enum Body {
+    Bytes(Vec<u8>),
+    Stream(Stream),
+}
+
+/// Represents a body that is streamed from a reader.
+#[derive(Clone)]
+pub struct Stream {
+    reader: Arc<Mutex<Option<Reader>>>,
+    len: Option<u64>,
+}
+
+impl Body {
+    /// Creates a body that is streamed from the given reader.
+    ///
+    /// Bodies without a known length are sent using chunked transfer encoding.
+    ///
+    /// ```
+    /// use snx::response::Body;
+    ///
+    /// let body = Body::from_reader(std::io::empty(), Some(0));
+    /// ```
+    pub fn from_reader(reader: impl Read + Send + 'static, len: Option<u64>) -> Self {
+        Body::Stream(Stream {
+            reader: Arc::new(Mutex::new(Some(Reader::Read(Box::new(reader))))),
+            len,
+        })
+    }
+
+    /// Creates a body that is streamed from the given seekable reader, such as a file.
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    ///
+    /// use snx::response::Body;
+    ///
+    /// let body = Body::from_seekable(Cursor::new(b"hello world!".to_vec()), 12);
+    /// ```
+    pub fn from_seekable(reader: impl Read + Seek + Send + 'static, len: u64) -> Self {
+        Body::Stream(Stream {
+            reader: Arc::new(Mutex::new(Some(Reader::Seek(Box::new(reader))))),
+            len: Some(len),
+        })
+    }
+
+    /// Gets the length of the body in bytes if it is known.
+    ///
+    /// ```
+    /// use snx::response::Body;
+    ///
+    /// let body = Body::from("hello world!");
+    ///
+    /// assert_eq!(body.len(), Some(12));
+    /// ```
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::Stream(stream) => stream.len,
+        }
+    }
+
+    /// Returns whether the body is known to be empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// Returns whether arbitrary ranges of the body can be read, which is the case for buffered
+    /// bodies and bodies streamed from a seekable reader.
+    ///
+    /// ```
+    /// use snx::response::Body;
+    ///
+    /// let body = Body::from_reader(std::io::empty(), None);
+    ///
+    /// assert!(!body.is_seekable());
+    /// ```
+    pub fn is_seekable(&self) -> bool {
+        match self {
+            Body::Bytes(_) => true,
+            Body::Stream(stream) => matches!(*stream.reader.lock().unwrap(), Some(Reader::Seek(_))),
+        }
+    }
+
+    /// Gets a reference to the bytes of the body if it is buffered in memory.
+    ///
+    /// ```
+    /// use snx::response::Body;
+    ///
+    /// let body = Body::from("hello world!");
+    ///
+    /// assert_eq!(body.as_bytes(), Some("hello world!".as_bytes()));
+    /// ```
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes),
+            Body::Stream(_) => None,
+        }
+    }
+
+    /// Takes the seekable reader out of a streamed body.
+    ///
+    /// Returns `None` if the body is buffered, not seekable or was already consumed.
+    pub fn take_seekable(&self) -> Option<Box<dyn SeekRead>> {
+        let Body::Stream(stream) = self else {
+            return None;
+        };
+
+        let mut guard = stream.reader.lock().unwrap();
+        match guard.take() {
+            Some(Reader::Seek(reader)) => Some(reader),
+            other => {
+                *guard = other;
+                None
+            }
+        }
+    }
+
+    /// Reads the entire body into memory.
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    ///
+    /// use snx::response::Body;
+    ///
+    /// let body = Body::from_seekable(Cursor::new(b"hello world!".to_vec()), 12);
+    ///
+    /// assert_eq!(body.into_bytes().unwrap(), b"hello world!");
+    /// ```
+    pub fn into_bytes(self) -> io::Result<Vec<u8>> {
+        match self {
+            Body::Bytes(bytes) => Ok(bytes),
+            stream => {
+                let mut bytes = Vec::new();
+                stream.write_to(&mut bytes)?;
+
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Turns the body into a reader.
+    ///
+    /// Fails if the body is streamed and was already consumed.
+    ///
+    /// ```
+    /// use std::io::Read;
+    ///
+    /// use snx::response::Body;
+    ///
+    /// let mut contents = String::new();
+    /// Body::from("hello world!")
+    ///     .into_reader()
+    ///     .unwrap()
+    ///     .read_to_string(&mut contents)
+    ///     .unwrap();
+    /// ```
+    pub fn into_reader(self) -> io::Result<Box<dyn Read + Send>> {
+        match self {
+            Body::Bytes(bytes) => Ok(Box::new(io::Cursor::new(bytes))),
+            Body::Stream(stream) => match stream.reader.lock().unwrap().take() {
+                Some(reader) => Ok(Box::new(reader)),
+                None => Err(io::Error::other("streamed body was already consumed")),
+            },
+        }
+    }
+
+    /// Writes the body to the given writer as-is.
+    ///
+    /// Streamed bodies of a known length are written up to that length. Fails if their reader
+    /// ends before, e.g. because a file was truncated while it was served, as the declared
+    /// `Content-Length` cannot be met anymore.
+    pub fn write_to(self, w: &mut impl Write) -> io::Result<u64> {
+        let len = self.len();
+
+        let written = match self {
+            Body::Bytes(bytes) => {
+                w.write_all(&bytes)?;
+
+                return Ok(bytes.len() as u64);
+            }
+            stream => match len {
+                Some(len) => io::copy(&mut stream.into_reader()?.take(len), w)?,
+                None => io::copy(&mut stream.into_reader()?, w)?,
+            },
+        };
+
+        match len {
+            Some(len) if written < len => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("streamed body ended after {written} of {len} bytes"),
+            )),
+            _ => Ok(written),
+        }
+    }
+
+    /// Writes the body to the given writer using chunked transfer encoding.
+    pub fn write_chunked_to(self, w: &mut impl Write) -> io::Result<u64> {
+        let mut writer = ChunkedWriter(w);
+        let written = self.write_to(&mut writer)?;
+
+        w.write_all(b"0\r\n\r\n")?;
+
+        Ok(written)
+    }
+}
+
+/// Writes every buffer it receives as a single chunk.
+struct ChunkedWriter<'a, W: Write>(&'a mut W);
+
+impl<W: Write> Write for ChunkedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        write!(self.0, "{:x}\r\n", buf.len())?;
+        self.0.write_all(buf)?;
+        self.0.write_all(b"\r\n")?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            Body::Stream(stream) => f.debug_struct("Stream").field("len", &stream.len).finish(),
+        }
+    }
+}
+
+impl Default for Body {
+    fn default() -> Self {
+        Body::Bytes(vec![])
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(value: Vec<u8>) -> Self {
+        Body::Bytes(value)
+    }
+}
+
+impl From<&str> for Body {
+    fn from(value: &str) -> Self {
+        Body::Bytes(value.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Body {
+    fn from(value: String) -> Self {
+        Body::Bytes(value.into_bytes())
+    }
+}
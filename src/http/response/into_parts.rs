@@ -21,7 +21,12 @@ impl IntoParts for HeaderMap {
     fn into_parts(self, parts: Parts) -> Parts {
         let mut parts = parts.clone();
 
-        parts.headers = self;
+        for (key, values) in self.iter() {
+            parts.headers.remove(key);
+            for value in values {
+                parts.headers.insert(key, value);
+            }
+        }
 
         parts
     }
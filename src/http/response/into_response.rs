@@ -100,8 +100,9 @@ macro_rules! define_into_response_for_tuple {
         {
             fn into_response(self) -> Response {
                 let ($($t,)* res) = self;
+                let res = res.into_response();
 
-                let mut parts = Parts::default();
+                let mut parts = res.head.clone();
                 $(
                     parts = $t.clone().into_parts(parts.clone());
                 )*
@@ -157,3 +158,33 @@ where
         self.into_response_boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Html;
+
+    use super::*;
+
+    #[test]
+    fn header_parts_replace_headers_of_the_response() {
+        let res = (HeaderMap::from(("Content-Type", "text/csv")), "a,b").into_response();
+
+        assert_eq!(
+            res.headers().get_all("content-type"),
+            Some(vec!["text/csv".to_string()])
+        );
+
+        let res = (
+            StatusCode::Created,
+            HeaderMap::from(("Content-Type", "application/xhtml+xml")),
+            Html("<p>hello</p>".to_string()),
+        )
+            .into_response();
+
+        assert_eq!(*res.status(), StatusCode::Created);
+        assert_eq!(
+            res.headers().get_all("content-type"),
+            Some(vec!["application/xhtml+xml".to_string()])
+        );
+    }
+}
@@ -1,12 +1,18 @@
-mod into_parts;
+mod body;
+pub(crate) mod into_parts;
 mod into_response;
 
+use std::{
+    io::{self, Write},
+    time::SystemTime,
+};
+
 use crate::StatusCode;
 
+pub use body::{Body, SeekRead};
 pub use into_response::IntoResponse;
-use jiff::Zoned;
 
-use super::header::HeaderMap;
+use super::{date, extensions::Extensions, header::HeaderMap};
 
 #[derive(Debug, Clone, Default)]
 pub struct Parts {
@@ -18,7 +24,8 @@ pub struct Parts {
 #[derive(Debug, Clone, Default)]
 pub struct Response {
     head: Parts,
-    body: Option<Vec<u8>>,
+    body: Option<Body>,
+    extensions: Extensions,
 }
 
 impl Response {
@@ -29,9 +36,9 @@ impl Response {
     ///
     /// let res = Response::new("hello world!".as_bytes().to_vec());
     /// ```
-    pub fn new(body: Vec<u8>) -> Self {
+    pub fn new(body: impl Into<Body>) -> Self {
         Self {
-            body: Some(body),
+            body: Some(body.into()),
             ..Default::default()
         }
     }
@@ -84,29 +91,93 @@ impl Response {
         &mut self.head.headers
     }
 
-    /// Gets a reference to the body.
+    /// Gets a reference to the body, which is `None` for responses without a body, such as
+    /// `304 Not Modified` responses.
+    ///
+    /// The body is either buffered in memory or streamed, use [Body::as_bytes] to get the bytes of
+    /// a buffered body or [Body::into_bytes] to read a streamed body into memory.
     ///
     /// ```
     /// use snx::{response::Response, StatusCode};
     ///
     /// let res = Response::new("hello world!".as_bytes().to_vec());
-    /// let body = res.body();
+    /// let body = res.body().as_ref().and_then(|body| body.as_bytes());
+    ///
+    /// assert_eq!(body, Some("hello world!".as_bytes()));
     /// ```
-    pub fn body(&self) -> &Option<Vec<u8>> {
+    pub fn body(&self) -> &Option<Body> {
         &self.body
     }
 
+    /// Gets a mutable reference to the body.
+    ///
+    /// ```
+    /// use snx::response::Response;
+    ///
+    /// let mut res = Response::default();
+    /// *res.body_mut() = Some("hello world!".into());
+    /// ```
+    pub fn body_mut(&mut self) -> &mut Option<Body> {
+        &mut self.body
+    }
+
+    /// Gets a reference to the extensions for this response, which contain values added by
+    /// handlers and middleware.
+    ///
+    /// ```
+    /// use snx::response::Response;
+    ///
+    /// let res = Response::default();
+    /// let value = res.extensions().get::<u64>();
+    /// ```
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Gets a mutable reference to the extensions for this response.
+    ///
+    /// ```
+    /// use snx::response::Response;
+    ///
+    /// let mut res = Response::default();
+    /// res.extensions_mut().insert(1u64);
+    /// ```
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
     /// Serializes the response object to a raw HTTP response.
     ///
+    /// Streamed bodies are read into memory, use [Response::write_to] to stream them instead.
+    /// Fails if a streamed body cannot be read.
+    ///
     /// ```
     /// use snx::response::Response;
     ///
-    /// let bytes = Response::default().serialize_to_raw_http_response();
+    /// let bytes = Response::default().serialize_to_raw_http_response().unwrap();
     /// ```
-    pub fn serialize_to_raw_http_response(self) -> Vec<u8> {
+    pub fn serialize_to_raw_http_response(self) -> io::Result<Vec<u8>> {
         let mut serialized = Vec::new();
+        self.write_to(&mut serialized)?;
+
+        Ok(serialized)
+    }
 
-        serialized.extend_from_slice(
+    /// Writes the response to the given writer as a raw HTTP response, streaming the body.
+    ///
+    /// Bodies without a known length are written using chunked transfer encoding. If reading a
+    /// streamed body fails, the head has already been written, so the connection must be closed.
+    ///
+    /// ```
+    /// use snx::response::Response;
+    ///
+    /// let mut bytes = Vec::new();
+    /// Response::new("hello world!").write_to(&mut bytes).unwrap();
+    /// ```
+    pub fn write_to(self, w: &mut impl Write) -> io::Result<()> {
+        let mut head = Vec::new();
+
+        head.extend_from_slice(
             format!(
                 "HTTP/1.1 {} {}\r\n",
                 self.head.status,
@@ -117,24 +188,82 @@ impl Response {
 
         for (key, values) in self.head.headers.iter() {
             for value in values {
-                serialized.extend_from_slice(format!("{}: {}\r\n", key, value).as_bytes());
+                head.extend_from_slice(format!("{}: {}\r\n", key, value).as_bytes());
             }
         }
 
         if let Some(ref body) = self.body {
-            serialized.extend_from_slice(format!("Content-Length: {}\r\n", body.len()).as_bytes());
+            match body.len() {
+                Some(len) => {
+                    head.extend_from_slice(format!("Content-Length: {}\r\n", len).as_bytes())
+                }
+                None => head.extend_from_slice(b"Transfer-Encoding: chunked\r\n"),
+            }
         }
 
-        let date = Zoned::now()
-            .strftime("Date: %a, %d %b %Y %H:%M:%S GMT\r\n")
-            .to_string();
-        serialized.extend_from_slice(date.as_bytes());
+        head.extend_from_slice(format!("Date: {}\r\n", date::format(SystemTime::now())).as_bytes());
+
+        head.extend_from_slice(b"\r\n");
+        w.write_all(&head)?;
+
+        match self.body {
+            Some(body) if body.len().is_none() => body.write_chunked_to(w)?,
+            Some(body) => body.write_to(w)?,
+            None => 0,
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        serialized.extend_from_slice(b"\r\n");
-        if let Some(body) = self.body {
-            serialized.extend_from_slice(&body);
+    struct FailingReader;
+
+    impl io::Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk on fire"))
         }
+    }
+
+    #[test]
+    fn serializing_fails_if_the_streamed_body_cannot_be_read() {
+        let res = Response::new(Body::from_reader(FailingReader, Some(12)));
+
+        assert!(res.serialize_to_raw_http_response().is_err());
+    }
+
+    #[test]
+    fn writing_fails_if_the_streamed_body_is_shorter_than_its_length() {
+        let res = Response::new(Body::from_seekable(io::Cursor::new(b"hello".to_vec()), 12));
+        let mut raw = Vec::new();
+
+        let err = res.write_to(&mut raw).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(raw.ends_with(b"\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn streamed_bodies_are_written_up_to_their_length() {
+        let res = Response::new(Body::from_reader(
+            io::Cursor::new(b"hello world!".to_vec()),
+            Some(5),
+        ));
+        let raw = String::from_utf8(res.serialize_to_raw_http_response().unwrap()).unwrap();
+
+        assert!(raw.contains("Content-Length: 5\r\n"));
+        assert!(raw.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn streamed_bodies_without_length_are_chunked() {
+        let res = Response::new(Body::from_reader(io::Cursor::new(b"hello".to_vec()), None));
+        let raw = String::from_utf8(res.serialize_to_raw_http_response().unwrap()).unwrap();
 
-        serialized
+        assert!(raw.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(raw.ends_with("\r\n\r\n5\r\nhello\r\n0\r\n\r\n"));
     }
 }
@@ -1,17 +1,15 @@
-use std::{
-    collections::{hash_map::Entry, HashMap},
-    sync::Arc,
-};
+use std::{collections::HashMap, ops::Range, path::PathBuf, sync::Arc, time::Duration};
 
 use regex::Regex;
 
-use crate::Context;
+use crate::{Context, Redirect};
 
 use super::{
     handler::Handler,
     middleware::MiddlewareHandler,
     request::Request,
     response::{IntoResponse, Response},
+    static_dir::StaticDir,
     Method,
 };
 
@@ -21,11 +19,30 @@ pub struct Route {
     method: Method,
     path: String,
     host: String,
+    name: Option<String>,
     handler: Arc<Box<dyn Handler + Send + Sync>>,
     middleware: Vec<MiddlewareHandler>,
+    timeout: Option<Duration>,
 }
 
 impl Route {
+    /// Gets the method of the route.
+    ///
+    /// ```
+    /// use snx::{router::Router, Method};
+    ///
+    /// let router = Router::builder("localhost")
+    ///     .get("/", |_, _| "hello world!")
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let matched_route = router.at(&Method::Get, "localhost", "/").unwrap();
+    /// assert_eq!(matched_route.route.method(), &Method::Get);
+    /// ```
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
     /// Gets a reference to the path.
     ///
     /// ```
@@ -44,6 +61,24 @@ impl Route {
         self.path.clone()
     }
 
+    /// Gets the name of the route if it has one.
+    ///
+    /// ```
+    /// use snx::{router::Router, Method};
+    ///
+    /// let router = Router::builder("localhost")
+    ///     .get("/", |_, _| "hello world!")
+    ///     .name("home")
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let matched_route = router.at(&Method::Get, "localhost", "/").unwrap();
+    /// let name = matched_route.route.name();
+    /// ```
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Gets a reference to the handler.
     ///
     /// ```
@@ -78,6 +113,53 @@ impl Route {
     pub fn middleware(&self) -> &Vec<MiddlewareHandler> {
         &self.middleware
     }
+
+    /// Gets the timeout of the route if it has one.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use snx::{router::Router, Method};
+    ///
+    /// let router = Router::builder("localhost")
+    ///     .timeout(Duration::from_secs(5), |router| {
+    ///         router.get("/", |_, _| "hello world!")
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let matched_route = router.at(&Method::Get, "localhost", "/").unwrap();
+    /// assert_eq!(matched_route.route.timeout(), Some(Duration::from_secs(5)));
+    /// ```
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Gets the path pattern of the route to add to the extensions of matching requests.
+    pub(crate) fn pattern(&self) -> RoutePattern {
+        RoutePattern(self.path.clone())
+    }
+}
+
+/// The path pattern of the route that matched a request, e.g. `/posts/{id}`.
+///
+/// Added to the extensions of a request before it is handled, so it can be used to group requests
+/// by route instead of by their raw path.
+///
+/// ```
+/// use snx::{request::Request, router::RoutePattern};
+///
+/// let request = Request::builder().path("/posts/1").build();
+/// let pattern = request.extensions().get::<RoutePattern>();
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct RoutePattern(String);
+
+impl RoutePattern {
+    /// Gets the pattern as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 pub struct MatchedRoute<'a> {
@@ -87,6 +169,8 @@ pub struct MatchedRoute<'a> {
 
 /// Used to route a [Request] to the correct route.
 pub struct Router {
+    host: String,
+    routes: Vec<Route>,
     hosts: HashMap<String, (Regex, HashMap<Method, matchit::Router<Route>>)>,
 }
 
@@ -113,7 +197,12 @@ impl Router {
     ///
     /// assert_eq!(&matched_route.route.path(), "/")
     /// ```
-    pub fn at(&self, method: &Method, host: &str, path: &str) -> Result<MatchedRoute, RouterError> {
+    pub fn at(
+        &self,
+        method: &Method,
+        host: &str,
+        path: &str,
+    ) -> Result<MatchedRoute<'_>, RouterError> {
         for (host_key, (pattern, methods)) in &self.hosts {
             let compiled_host_regex = compile_host_pattern(host_key);
 
@@ -154,6 +243,30 @@ impl Router {
         Err(RouterError::NotFound)
     }
 
+    /// Gets the lookup table of named routes used to generate URLs.
+    ///
+    /// ```
+    /// use snx::router::Router;
+    ///
+    /// let router = Router::builder("localhost")
+    ///     .get("/posts/{id}", |_, _| "returns a single post")
+    ///     .name("posts.show")
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let url = router.named_routes().url_for("posts.show", &[("id", "1")]);
+    ///
+    /// assert_eq!(url.as_deref(), Some("/posts/1"));
+    /// ```
+    pub fn named_routes(&self) -> NamedRoutes {
+        NamedRoutes(Arc::new(
+            self.routes
+                .iter()
+                .filter_map(|route| Some((route.name.clone()?, route.path.clone())))
+                .collect(),
+        ))
+    }
+
     /// Returns whether or not a route exists in the method router for the given path.
     fn find_alternatives(
         &self,
@@ -170,23 +283,110 @@ impl Router {
     }
 }
 
+/// A lookup table of named routes used to generate URLs.
+#[derive(Clone, Default, Debug)]
+pub struct NamedRoutes(Arc<HashMap<String, String>>);
+
+impl NamedRoutes {
+    /// Generates the path of a named route, filling in its dynamic segments with the given
+    /// parameters. Returns `None` if the route does not exist or a parameter is missing.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let pattern = self.0.get(name)?;
+        let mut url = String::with_capacity(pattern.len());
+        let mut rest = pattern.as_str();
+
+        while let Some(start) = rest.find('{') {
+            let end = start + rest[start..].find('}')?;
+            let segment = &rest[start + 1..end];
+            let key = segment.trim_start_matches('*');
+            let (_, value) = params.iter().find(|(k, _)| *k == key)?;
+
+            url.push_str(&rest[..start]);
+            url.push_str(&percent_encode(value, segment.starts_with('*')));
+            rest = &rest[end + 1..];
+        }
+        url.push_str(rest);
+
+        Some(url)
+    }
+}
+
+/// Percent-encodes a parameter for use in a path. Slashes are kept for catch-all parameters, which
+/// span multiple segments.
+fn percent_encode(value: &str, keep_slashes: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slashes => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    encoded
+}
+
 #[derive(Debug)]
 pub enum RouterError {
     NotFound,
     MethodNotAllowed,
 }
 
+/// Represents an error that occurred while building a router.
+#[derive(thiserror::Error, Debug)]
+pub enum BuildError {
+    #[error("route `{method} {host}{path}` conflicts with previously registered route `{with}`")]
+    Conflict {
+        method: Method,
+        host: String,
+        path: String,
+        with: String,
+    },
+    #[error("route `{method} {host}{path}` is invalid: {reason}")]
+    InvalidPath {
+        method: Method,
+        host: String,
+        path: String,
+        reason: String,
+    },
+}
+
+impl BuildError {
+    fn from_insert_error(route: &Route, error: matchit::InsertError) -> Self {
+        match error {
+            matchit::InsertError::Conflict { with } => BuildError::Conflict {
+                method: route.method.clone(),
+                host: route.host.clone(),
+                path: route.path.clone(),
+                with,
+            },
+            e => BuildError::InvalidPath {
+                method: route.method.clone(),
+                host: route.host.clone(),
+                path: route.path.clone(),
+                reason: e.to_string(),
+            },
+        }
+    }
+}
+
 /// Defines a method for adding routes to the Builder with the given method.
 macro_rules! define_route_method {
     ($k:ident, $v:ident) => {
         /// Adds a route to the builder.
         pub fn $k(mut self, path: &'static str, handler: impl Handler + 'static) -> Self {
+            self.last = self.routes.len()..self.routes.len() + 1;
             self.routes.push(Route {
                 method: Method::$v,
                 path: path.to_string(),
                 host: self.host.clone(),
+                name: None,
                 handler: Arc::new(Box::new(handler) as Box<dyn Handler>),
                 middleware: Default::default(),
+                timeout: None,
             });
 
             self
@@ -200,7 +400,10 @@ pub struct Builder {
     host: String,
     prefix: Option<String>,
     middleware: Vec<MiddlewareHandler>,
+    timeout: Option<Duration>,
     routes: Vec<Route>,
+    /// Indices of the routes added by the most recent call, which [Builder::name] applies to.
+    last: Range<usize>,
     children: Vec<Builder>,
 }
 
@@ -231,6 +434,7 @@ impl Builder {
 
         builder = body(builder);
         self.children.push(builder);
+        self.last = 0..0;
 
         self
     }
@@ -254,6 +458,7 @@ impl Builder {
 
         builder = body(builder);
         self.children.push(builder);
+        self.last = 0..0;
 
         self
     }
@@ -307,10 +512,184 @@ impl Builder {
 
         builder = body(builder);
         self.children.push(builder);
+        self.last = 0..0;
+
+        self
+    }
+
+    /// Adds a new group with a timeout to the builder.
+    ///
+    /// Requests to routes in the group that are not answered within the timeout are answered with
+    /// a 503 Service Unavailable, or the status configured as `timeout_status` in the server
+    /// config. The timeout includes the route's middleware, but not the global middleware. The
+    /// handler keeps running in the background, but it can check [Request::is_cancelled] to stop
+    /// early. The timeout of the innermost group applies.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use snx::router::Router;
+    ///
+    /// let router = Router::builder("localhost")
+    ///     .timeout(Duration::from_secs(10), |router| {
+    ///         router.get("/reports", |_, _| "generates a slow report")
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn timeout(mut self, timeout: Duration, body: impl Fn(Builder) -> Builder) -> Self {
+        let mut builder = Self {
+            host: self.host.clone(),
+            timeout: Some(timeout),
+            ..Self::default()
+        };
+
+        builder = body(builder);
+        self.children.push(builder);
+        self.last = 0..0;
 
         self
     }
 
+    /// Mounts another builder or router under a prefix.
+    ///
+    /// Routes of the mounted builder keep their own middleware and are wrapped by the middleware
+    /// of this builder. Routes registered on the mounted builder's own host inherit the host of
+    /// this builder, routes in its host groups keep their host.
+    ///
+    /// ```
+    /// use snx::{router::{Builder, Router}, Method};
+    ///
+    /// fn admin_routes() -> Builder {
+    ///     Builder::default()
+    ///         .get("/", |_, _| "admin dashboard")
+    ///         .get("/users", |_, _| "list of users")
+    /// }
+    ///
+    /// let router = Router::builder("localhost")
+    ///     .get("/", |_, _| "hello world!")
+    ///     .mount("/admin", admin_routes())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let matched_route = router.at(&Method::Get, "localhost", "/admin/users").unwrap();
+    /// assert_eq!(&matched_route.route.path(), "/admin/users");
+    /// ```
+    pub fn mount(mut self, prefix: &'static str, other: impl Into<Builder>) -> Self {
+        let other = other.into();
+        let other_host = other.host.clone();
+
+        let mut routes = other.resolve(&mut vec![], &mut vec![], None);
+        for route in &mut routes {
+            if route.host.is_empty() || route.host == other_host {
+                route.host = self.host.clone();
+            }
+        }
+
+        self.children.push(Self {
+            host: self.host.clone(),
+            prefix: Some(prefix.to_string()),
+            routes,
+            ..Default::default()
+        });
+        self.last = 0..0;
+
+        self
+    }
+
+    /// Names the most recently added route so URLs can be generated for it. Both routes added by
+    /// [Builder::redirect] and [Builder::permanent_redirect] are named. Has no effect if a group
+    /// was added after the route.
+    ///
+    /// ```
+    /// use snx::router::Router;
+    ///
+    /// let router = Router::builder("localhost")
+    ///     .get("/posts/{id}", |_, _| "returns a single post")
+    ///     .name("posts.show")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn name(mut self, name: &str) -> Self {
+        for route in &mut self.routes[self.last.clone()] {
+            route.name = Some(name.to_string());
+        }
+
+        self
+    }
+
+    /// Adds a route that redirects to another location with a 302 Found status code.
+    ///
+    /// ```
+    /// use snx::router::Router;
+    ///
+    /// let router = Router::builder("localhost")
+    ///     .redirect("/old", "/new")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn redirect(self, path: &'static str, location: &'static str) -> Self {
+        let start = self.routes.len();
+        let mut builder = self
+            .get(path, move |_, _| Redirect::to(location))
+            .head(path, move |_, _| Redirect::to(location));
+        builder.last = start..builder.routes.len();
+
+        builder
+    }
+
+    /// Adds a route that redirects to another location with a 301 Moved Permanently status code.
+    ///
+    /// ```
+    /// use snx::router::Router;
+    ///
+    /// let router = Router::builder("localhost")
+    ///     .permanent_redirect("/old", "/new")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn permanent_redirect(self, path: &'static str, location: &'static str) -> Self {
+        let start = self.routes.len();
+        let mut builder = self
+            .get(path, move |_, _| Redirect::moved_permanently(location))
+            .head(path, move |_, _| Redirect::moved_permanently(location));
+        builder.last = start..builder.routes.len();
+
+        builder
+    }
+
+    /// Adds routes that serve the files in a directory on the filesystem under a prefix.
+    ///
+    /// Directories are served using their `index.html` file. Conditional and range requests are
+    /// supported and file contents are streamed to the client.
+    ///
+    /// ```
+    /// use snx::router::Router;
+    ///
+    /// let router = Router::builder("localhost")
+    ///     .static_dir("/assets", "./public")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn static_dir(self, prefix: &'static str, dir: impl Into<PathBuf>) -> Self {
+        let dir = StaticDir::new(dir);
+
+        self.prefix(prefix, move |builder| {
+            let serve = |dir: StaticDir| {
+                move |_, req: Request| {
+                    let path = req.params.get("path").cloned().unwrap_or_default();
+                    dir.serve(&req, &path)
+                }
+            };
+
+            builder
+                .get("/", serve(dir.clone()))
+                .head("/", serve(dir.clone()))
+                .get("/{*path}", serve(dir.clone()))
+                .head("/{*path}", serve(dir.clone()))
+        })
+    }
+
     define_route_method!(get, Get);
     define_route_method!(head, Head);
     define_route_method!(post, Post);
@@ -322,46 +701,55 @@ impl Builder {
     define_route_method!(patch, Patch);
 
     /// Builds a router.
-    pub fn build(self) -> Result<Router, matchit::InsertError> {
-        let mut hosts = HashMap::new();
-
-        for route in self.resolve(&mut vec![], &mut vec![]) {
-            if let Entry::Vacant(e) = hosts.entry(route.host.clone()) {
-                let mut router = matchit::Router::new();
-                router.insert(route.path.clone(), route.clone())?;
-
-                let mut methods = HashMap::new();
-                methods.insert(route.method, router);
-
-                let pattern = compile_host_pattern(&route.host);
-
-                e.insert((pattern, methods));
-            } else {
-                let (_, methods) = hosts.get_mut(&route.host).unwrap();
-
-                if let Some(router) = methods.get_mut(&route.method) {
-                    router.insert(route.path.clone(), route.clone())?;
-                } else {
-                    let mut router = matchit::Router::new();
-                    router.insert(route.path.clone(), route.clone())?;
-
-                    methods.insert(route.method, router);
-                }
-            }
+    ///
+    /// ```
+    /// use snx::router::{BuildError, Router};
+    ///
+    /// let result = Router::builder("localhost")
+    ///     .get("/posts", |_, _| "returns a list of posts")
+    ///     .prefix("/posts", |router| router.get("/", |_, _| "also returns a list of posts"))
+    ///     .build();
+    ///
+    /// assert!(matches!(result, Err(BuildError::Conflict { .. })));
+    /// ```
+    pub fn build(self) -> Result<Router, BuildError> {
+        let host = self.host.clone();
+        let routes = self.resolve(&mut vec![], &mut vec![], None);
+        let mut hosts: HashMap<String, (Regex, HashMap<Method, matchit::Router<Route>>)> =
+            HashMap::new();
+
+        for route in &routes {
+            let (_, methods) = hosts
+                .entry(route.host.clone())
+                .or_insert_with(|| (compile_host_pattern(&route.host), HashMap::new()));
+
+            methods
+                .entry(route.method.clone())
+                .or_default()
+                .insert(route.path.clone(), route.clone())
+                .map_err(|e| BuildError::from_insert_error(route, e))?;
         }
 
-        Ok(Router { hosts })
+        Ok(Router {
+            host,
+            routes,
+            hosts,
+        })
     }
 
-    /// Recursively adds (compounding) prefixes and middleware to all of this builders' children,
-    /// combines the children's routes into its own and returns them.
+    /// Recursively adds (compounding) prefixes, middleware and timeouts to all of this builders'
+    /// children, combines the children's routes into its own and returns them.
     fn resolve(
         mut self,
         prefixes: &mut Vec<String>,
         middleware: &mut Vec<MiddlewareHandler>,
+        timeout: Option<Duration>,
     ) -> Vec<Route> {
+        let timeout = self.timeout.or(timeout);
+        let (prefixes_len, middleware_len) = (prefixes.len(), middleware.len());
+
         if let Some(prefix) = self.prefix {
-            prefixes.push(prefix);
+            prefixes.push(prefix.trim_end_matches('/').to_string());
         }
         middleware.extend(self.middleware);
 
@@ -371,7 +759,8 @@ impl Builder {
             }
 
             route.middleware.extend(middleware.clone());
-            route.path = format!("{}{}", prefixes.join("/"), route.path);
+            route.timeout = route.timeout.or(timeout);
+            route.path = format!("{}{}", prefixes.concat(), route.path);
 
             if route.path.len() > 1 {
                 if let Some(path) = route.path.strip_suffix('/') {
@@ -379,18 +768,33 @@ impl Builder {
                 }
             }
 
-            route.host = self.host.clone();
+            if route.path.is_empty() {
+                route.path = "/".to_string();
+            }
         }
 
         for child in self.children {
             self.routes
-                .extend_from_slice(&child.resolve(prefixes, middleware));
+                .extend_from_slice(&child.resolve(prefixes, middleware, timeout));
         }
 
+        prefixes.truncate(prefixes_len);
+        middleware.truncate(middleware_len);
+
         self.routes
     }
 }
 
+impl From<Router> for Builder {
+    fn from(router: Router) -> Self {
+        Self {
+            host: router.host,
+            routes: router.routes,
+            ..Default::default()
+        }
+    }
+}
+
 /// Compiles a regular expression that captures dynamic components of a hostname.
 fn compile_host_pattern(pattern: &str) -> Regex {
     let mut regex_pattern = regex::escape(pattern);
@@ -402,3 +806,125 @@ fn compile_host_pattern(pattern: &str) -> Regex {
 
     Regex::new(&format!("^{}$", regex_pattern)).expect("Invalid regex")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{middleware::tagging_middleware, Config};
+
+    type Middleware =
+        fn(Context, Request, Box<dyn Fn(Request) -> Response>) -> Box<dyn IntoResponse>;
+
+    tagging_middleware!(a);
+    tagging_middleware!(b);
+    tagging_middleware!(c);
+    tagging_middleware!(mounted);
+
+    /// Gets the names of the middleware of a route in the order they are stored in.
+    fn middleware_names(router: &Router, path: &str) -> Vec<String> {
+        let ctx = Context::new(Config::for_tests(""));
+        let route = router.at(&Method::Get, "localhost", path).unwrap().route;
+
+        route
+            .middleware()
+            .iter()
+            .map(|middleware| {
+                middleware(
+                    ctx.clone(),
+                    Request::builder().build(),
+                    Box::new(|_| Response::default()),
+                )
+                .into_response()
+                .headers()
+                .get("x-middleware")
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn group_middleware_keep_the_order_they_are_stored_in() {
+        static OUTER: [Middleware; 2] = [a, b];
+        static INNER: [Middleware; 1] = [c];
+
+        let router = Router::builder("localhost")
+            .middleware(&OUTER, |router| {
+                router.middleware(&INNER, |router| router.get("/", |_, _| "hello"))
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(middleware_names(&router, "/"), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn mounted_routes_keep_their_middleware_before_the_group_middleware() {
+        static MOUNTED: [Middleware; 1] = [mounted];
+        static GROUP: [Middleware; 1] = [a];
+
+        let router = Router::builder("localhost")
+            .middleware(&GROUP, |router| {
+                let admin = Router::builder("localhost")
+                    .middleware(&MOUNTED, |router| router.get("/users", |_, _| "users"))
+                    .build()
+                    .unwrap();
+
+                router.mount("/admin", admin)
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(middleware_names(&router, "/admin/users"), ["mounted", "a"]);
+    }
+
+    #[test]
+    fn name_applies_to_the_route_being_built() {
+        let router = Router::builder("localhost")
+            .get("/posts", |_, _| "returns a list of posts")
+            .name("posts.index")
+            .redirect("/old", "/posts")
+            .name("old")
+            .get("/about", |_, _| "about")
+            .prefix("/admin", |router| router.get("/", |_, _| "admin"))
+            .name("ignored")
+            .build()
+            .unwrap();
+        let routes = router.named_routes();
+
+        assert_eq!(
+            routes.url_for("posts.index", &[]).as_deref(),
+            Some("/posts")
+        );
+        assert_eq!(routes.url_for("old", &[]).as_deref(), Some("/old"));
+        assert_eq!(routes.url_for("ignored", &[]), None);
+        assert!(router
+            .routes
+            .iter()
+            .all(|route| route.path != "/about" || route.name.is_none()));
+    }
+
+    #[test]
+    fn url_for_percent_encodes_parameters() {
+        let router = Router::builder("localhost")
+            .get("/posts/{slug}", |_, _| "returns a single post")
+            .name("posts.show")
+            .get("/files/{*path}", |_, _| "returns a file")
+            .name("files.show")
+            .build()
+            .unwrap();
+        let routes = router.named_routes();
+
+        assert_eq!(
+            routes
+                .url_for("posts.show", &[("slug", "a/b?c=d#e f")])
+                .as_deref(),
+            Some("/posts/a%2Fb%3Fc%3Dd%23e%20f")
+        );
+        assert_eq!(
+            routes
+                .url_for("files.show", &[("path", "docs/read me.txt")])
+                .as_deref(),
+            Some("/files/docs/read%20me.txt")
+        );
+    }
+}
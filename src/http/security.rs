@@ -0,0 +1,105 @@
+use rand::Rng;
+
+use crate::config::SecurityHeadersConfig;
+
+use super::response::Response;
+
+/// The nonce of the content security policy of a request, which allows inline scripts with a
+/// matching `nonce` attribute to run.
+///
+/// Added to the extensions of a request by the [crate::middleware::security_headers] middleware
+/// if the configured policy uses a nonce.
+///
+/// ```
+/// use snx::request::{CspNonce, Request};
+///
+/// let request = Request::builder().build();
+/// let nonce = request.extensions().get::<CspNonce>();
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    /// Gets the nonce as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The default content security policy, which only allows resources from the same origin and
+/// inline scripts and styles with the nonce of the request.
+const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; script-src 'self' 'nonce-{nonce}'; style-src 'self' 'nonce-{nonce}'; object-src 'none'; base-uri 'self'; frame-ancestors 'none'";
+
+/// Generates a nonce for the content security policy of a request, if the configured policy
+/// uses one.
+pub fn nonce(config: &SecurityHeadersConfig) -> Option<CspNonce> {
+    config
+        .content_security_policy
+        .as_deref()
+        .unwrap_or(DEFAULT_CONTENT_SECURITY_POLICY)
+        .contains("{nonce}")
+        .then(|| CspNonce(super::hex(&rand::rng().random::<[u8; 16]>())))
+}
+
+/// Adds the configured security headers to a response, leaving headers that were already set by
+/// the handler untouched.
+pub fn apply(
+    mut res: Response,
+    config: &SecurityHeadersConfig,
+    nonce: Option<&CspNonce>,
+) -> Response {
+    let content_security_policy = config
+        .content_security_policy
+        .as_deref()
+        .unwrap_or(DEFAULT_CONTENT_SECURITY_POLICY)
+        .replace("{nonce}", nonce.map(CspNonce::as_str).unwrap_or_default());
+
+    let content_security_policy_header = match config
+        .content_security_policy_report_only
+        .unwrap_or_default()
+    {
+        true => "Content-Security-Policy-Report-Only",
+        false => "Content-Security-Policy",
+    };
+
+    let headers = [
+        (
+            "Strict-Transport-Security",
+            config
+                .strict_transport_security
+                .as_deref()
+                .unwrap_or("max-age=31536000; includeSubDomains"),
+        ),
+        (
+            "X-Content-Type-Options",
+            config.content_type_options.as_deref().unwrap_or("nosniff"),
+        ),
+        (
+            "X-Frame-Options",
+            config.frame_options.as_deref().unwrap_or("DENY"),
+        ),
+        (
+            "Referrer-Policy",
+            config
+                .referrer_policy
+                .as_deref()
+                .unwrap_or("strict-origin-when-cross-origin"),
+        ),
+        (
+            "Permissions-Policy",
+            config
+                .permissions_policy
+                .as_deref()
+                .unwrap_or("camera=(), microphone=(), geolocation=()"),
+        ),
+        (content_security_policy_header, &content_security_policy),
+    ];
+
+    for (name, value) in headers {
+        if !value.is_empty() && res.headers().get(name).is_none() {
+            res.headers_mut().insert(name, value);
+        }
+    }
+
+    res
+}
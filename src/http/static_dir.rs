@@ -0,0 +1,324 @@
+use std::{
+    fs::{self, File},
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use super::{
+    conditional::{self, ETag},
+    date, range,
+    request::Request,
+    response::{Body, IntoResponse, Response},
+    Method, StatusCode,
+};
+
+/// Serves files from a directory on the filesystem.
+#[derive(Clone, Debug)]
+pub struct StaticDir {
+    root: PathBuf,
+    index_files: Vec<String>,
+}
+
+impl StaticDir {
+    /// Creates a new static directory that serves files from the given root.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            index_files: vec!["index.html".to_string()],
+        }
+    }
+
+    /// Serves the file at the given path relative to the root of the directory.
+    pub fn serve(&self, req: &Request, path: &str) -> Response {
+        let Some(path) = self.resolve(path) else {
+            return StatusCode::NotFound.into_response();
+        };
+
+        let (file, metadata) = match File::open(&path).and_then(|f| Ok((f.metadata()?, f))) {
+            Ok((metadata, file)) => (file, metadata),
+            Err(_) => return StatusCode::NotFound.into_response(),
+        };
+
+        let len = metadata.len();
+        let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+        let etag = ETag::strong(&format!(
+            "{:x}-{:x}",
+            modified
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+            len
+        ));
+
+        let mut res = Response::default();
+        res.headers_mut().insert(
+            "Content-Type",
+            mime_guess::from_path(&path)
+                .first_or_octet_stream()
+                .essence_str(),
+        );
+        res.headers_mut()
+            .insert("Last-Modified", &date::format(modified));
+        res.headers_mut().insert("ETag", &etag.to_string());
+
+        match conditional::evaluate(req, Some(&etag), Some(modified)) {
+            Some(StatusCode::NotModified) => {
+                *res.status_mut() = StatusCode::NotModified;
+
+                return res;
+            }
+            Some(status) => return status.into_response(),
+            None => {}
+        }
+
+        *res.body_mut() = Some(Body::from_seekable(file, len));
+        let mut res = range::apply(req, res);
+
+        if req.method() == Method::Head {
+            if let Some(len) = res.body_mut().take().and_then(|body| body.len()) {
+                res.headers_mut().insert("Content-Length", &len.to_string());
+            }
+        }
+
+        res
+    }
+
+    /// Resolves a request path to a file inside the root, guarding against path traversal.
+    ///
+    /// Returns `None` if the path is invalid, escapes the root, contains hidden files or
+    /// directories such as `.env` or `.git` or no file exists for it.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        let decoded = percent_decode(path)?;
+
+        let mut resolved = self.root.clone();
+        for segment in decoded.split('/') {
+            match Path::new(segment).components().next() {
+                None | Some(Component::CurDir) => continue,
+                Some(Component::Normal(_))
+                    if !segment.starts_with('.') && !segment.contains(['\\', '\0']) =>
+                {
+                    resolved.push(segment)
+                }
+                _ => return None,
+            }
+        }
+
+        if resolved.is_dir() {
+            resolved = self
+                .index_files
+                .iter()
+                .map(|index| resolved.join(index))
+                .find(|index| index.is_file())?;
+        }
+
+        let root = fs::canonicalize(&self.root).ok()?;
+        let resolved = fs::canonicalize(resolved).ok()?;
+
+        (resolved.starts_with(root) && resolved.is_file()).then_some(resolved)
+    }
+}
+
+/// Decodes percent-encoded characters in a path.
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{temp_dir, TempDir};
+
+    /// Creates a static directory inside a temporary directory, which is removed once the
+    /// returned guard is dropped.
+    fn static_dir() -> (TempDir, StaticDir) {
+        let tmp = temp_dir("static-dir");
+        let root = tmp.join("public");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("hello.txt"), "hello world!").unwrap();
+        fs::write(root.join("docs/index.html"), "<h1>docs</h1>").unwrap();
+
+        (tmp, StaticDir::new(root))
+    }
+
+    fn get(path: &str) -> Request {
+        Request::builder().method(Method::Get).path(path).build()
+    }
+
+    fn body_bytes(res: Response) -> Vec<u8> {
+        res.body().clone().unwrap().into_bytes().unwrap()
+    }
+
+    #[test]
+    fn serves_files() {
+        let (_tmp, dir) = static_dir();
+        let res = dir.serve(&get("/hello.txt"), "hello.txt");
+
+        assert_eq!(*res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("content-type").as_deref(),
+            Some("text/plain")
+        );
+        assert!(res.headers().get("etag").is_some());
+        assert!(res.headers().get("last-modified").is_some());
+        assert_eq!(body_bytes(res), b"hello world!");
+    }
+
+    #[test]
+    fn serves_index_files_of_directories() {
+        let (_tmp, dir) = static_dir();
+        let res = dir.serve(&get("/docs/"), "docs/");
+
+        assert_eq!(*res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("content-type").as_deref(),
+            Some("text/html")
+        );
+        assert_eq!(body_bytes(res), b"<h1>docs</h1>");
+    }
+
+    #[test]
+    fn rejects_paths_escaping_the_root() {
+        let (tmp, dir) = static_dir();
+        fs::write(tmp.join("secret.txt"), "secret").unwrap();
+
+        for path in [
+            "../secret.txt",
+            "docs/../../secret.txt",
+            "%2e%2e/secret.txt",
+            "docs%2F..%2F..%2Fsecret.txt",
+            "..\\secret.txt",
+            "docs\\..\\..\\secret.txt",
+            "hello.txt%00.html",
+            "hello.txt\0",
+            "%+1",
+            "%2",
+        ] {
+            assert!(dir.resolve(path).is_none(), "{path} was resolved");
+            assert_eq!(*dir.serve(&get("/"), path).status(), StatusCode::NotFound);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_pointing_outside_the_root() {
+        let (_tmp, dir) = static_dir();
+        let outside = temp_dir("outside");
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), dir.root.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(&*outside, dir.root.join("linked")).unwrap();
+
+        assert!(dir.resolve("link.txt").is_none());
+        assert!(dir.resolve("linked/secret.txt").is_none());
+    }
+
+    #[test]
+    fn rejects_hidden_files_and_directories() {
+        let (_tmp, dir) = static_dir();
+        fs::create_dir_all(dir.root.join(".git")).unwrap();
+        fs::write(dir.root.join(".env"), "SECRET=1").unwrap();
+        fs::write(dir.root.join(".git/config"), "[core]").unwrap();
+        fs::write(dir.root.join("docs/.htpasswd"), "admin:x").unwrap();
+
+        for path in [".env", ".git/config", "docs/.htpasswd", "%2eenv", "./.env"] {
+            assert!(dir.resolve(path).is_none(), "{path} was resolved");
+            assert_eq!(*dir.serve(&get("/"), path).status(), StatusCode::NotFound);
+        }
+        assert!(dir.resolve("./hello.txt").is_some());
+    }
+
+    #[test]
+    fn decodes_percent_encoded_characters() {
+        assert_eq!(percent_decode("a%20b%2Fc").as_deref(), Some("a b/c"));
+        assert!(percent_decode("%+1").is_none());
+        assert!(percent_decode("%-1").is_none());
+        assert!(percent_decode("%zz").is_none());
+        assert!(percent_decode("%ff").is_none());
+    }
+
+    #[test]
+    fn answers_matching_if_none_match_with_304() {
+        let (_tmp, dir) = static_dir();
+        let etag = dir
+            .serve(&get("/hello.txt"), "hello.txt")
+            .headers()
+            .get("etag")
+            .unwrap();
+
+        let req = Request::builder()
+            .method(Method::Get)
+            .header("If-None-Match", &etag)
+            .build();
+        let res = dir.serve(&req, "hello.txt");
+
+        assert_eq!(*res.status(), StatusCode::NotModified);
+        assert_eq!(res.headers().get("etag"), Some(etag));
+        assert!(res.body().is_none());
+    }
+
+    #[test]
+    fn answers_unmodified_files_with_304() {
+        let (_tmp, dir) = static_dir();
+        let last_modified = dir
+            .serve(&get("/hello.txt"), "hello.txt")
+            .headers()
+            .get("last-modified")
+            .unwrap();
+
+        let req = Request::builder()
+            .method(Method::Get)
+            .header("If-Modified-Since", &last_modified)
+            .build();
+
+        assert_eq!(
+            *dir.serve(&req, "hello.txt").status(),
+            StatusCode::NotModified
+        );
+    }
+
+    #[test]
+    fn serves_ranges() {
+        let req = Request::builder()
+            .method(Method::Get)
+            .header("Range", "bytes=6-")
+            .build();
+        let (_tmp, dir) = static_dir();
+        let res = dir.serve(&req, "hello.txt");
+
+        assert_eq!(*res.status(), StatusCode::PartialContent);
+        assert_eq!(
+            res.headers().get("content-range").as_deref(),
+            Some("bytes 6-11/12")
+        );
+        assert_eq!(body_bytes(res), b"world!");
+    }
+
+    #[test]
+    fn answers_head_requests_with_the_content_length() {
+        let req = Request::builder().method(Method::Head).build();
+        let (_tmp, dir) = static_dir();
+        let res = dir.serve(&req, "hello.txt");
+
+        assert_eq!(*res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("content-length").as_deref(), Some("12"));
+        assert!(res.body().is_none());
+    }
+}
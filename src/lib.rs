@@ -3,27 +3,52 @@
 mod app;
 mod config;
 mod context;
+#[cfg(feature = "sessions")]
+pub mod csrf;
 mod db;
+pub mod error;
+mod file;
+#[cfg(feature = "forms")]
+mod form;
+pub mod health;
 mod html;
 mod http;
+pub mod metrics;
+mod negotiated;
 mod panic_hook;
+pub mod rate_limit;
+mod redirect;
 mod server;
+pub mod validation;
 
 #[cfg(feature = "sessions")]
 mod session;
 
 #[cfg(feature = "json")]
 mod json;
+#[cfg(feature = "json")]
+mod problem;
 
 pub use app::{boot, App};
 pub use config::Config;
 pub use context::Context;
+pub use error::Error;
+pub use file::File;
+#[cfg(feature = "forms")]
+pub use form::InvalidFormBodyError;
 pub use html::Html;
-pub use http::{header::HeaderMap, middleware, request, response, router, Method, StatusCode};
-pub use server::Server;
+pub use http::{
+    accept, conditional::ETag, handler, header::HeaderMap, middleware, request, response, router,
+    Method, StatusCode,
+};
+pub use negotiated::Negotiated;
+pub use redirect::Redirect;
+pub use server::{Server, ShutdownHandle};
 
 #[cfg(feature = "json")]
 pub use json::{InvalidJsonBodyError, Json};
+#[cfg(feature = "json")]
+pub use problem::Problem;
 
 #[cfg(feature = "templating")]
 pub use sjabloon::template;
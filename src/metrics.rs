@@ -0,0 +1,525 @@
+//! Metrics in the Prometheus text exposition format.
+//!
+//! Every [Context] has a [Registry], to which the [crate::middleware::record_metrics] middleware
+//! records HTTP metrics and applications can register their own metrics. The registry is exposed
+//! by mounting the [handler].
+//!
+//! ```
+//! use snx::{metrics, router::Router};
+//!
+//! let router = Router::builder("http://localhost:3000")
+//!     .get("/metrics", metrics::handler)
+//!     .build();
+//! ```
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use crate::{request::Request, response::Response, Context};
+
+/// The default buckets of histograms in seconds, the same as those of the Prometheus clients.
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A metric that only goes up, e.g. the amount of handled requests.
+#[derive(Clone, Default, Debug)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    /// Increments the counter by one.
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    /// Increments the counter by the given amount.
+    pub fn inc_by(&self, amount: u64) {
+        self.0.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    /// Gets the current value of the counter.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A metric that can go up and down, e.g. the amount of requests in flight.
+#[derive(Clone, Default, Debug)]
+pub struct Gauge(Arc<AtomicI64>);
+
+impl Gauge {
+    /// Sets the gauge to the given value.
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    /// Increments the gauge by one.
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Decrements the gauge by one.
+    pub fn dec(&self) {
+        self.add(-1);
+    }
+
+    /// Adds the given amount to the gauge, which may be negative.
+    pub fn add(&self, amount: i64) {
+        self.0.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    /// Gets the current value of the gauge.
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A metric that samples observations into buckets, e.g. the latency of requests.
+#[derive(Clone, Debug)]
+pub struct Histogram(Arc<Mutex<HistogramData>>);
+
+#[derive(Debug)]
+struct HistogramData {
+    bounds: Vec<f64>,
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Self {
+        Self(Arc::new(Mutex::new(HistogramData {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        })))
+    }
+
+    /// Adds an observation to the histogram.
+    pub fn observe(&self, value: f64) {
+        let mut data = self.data();
+
+        if let Some(i) = data.bounds.iter().position(|bound| value <= *bound) {
+            data.counts[i] += 1;
+        }
+
+        data.sum += value;
+        data.count += 1;
+    }
+
+    /// Gets the amount of observations.
+    pub fn count(&self) -> u64 {
+        self.data().count
+    }
+
+    /// Locks the data of the histogram, which is never left half updated by a panic.
+    fn data(&self) -> MutexGuard<'_, HistogramData> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Series {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+type Labels = Vec<(String, String)>;
+
+#[derive(Debug)]
+struct Family {
+    help: String,
+    kind: Kind,
+    series: BTreeMap<Labels, Series>,
+}
+
+/// A collection of metrics, identified by their name and labels.
+///
+/// Getting a metric registers it the first time, afterwards the same metric is returned.
+///
+/// ```
+/// use snx::metrics::Registry;
+///
+/// let registry = Registry::default();
+///
+/// let signups = registry.counter("signups_total", "Total number of signups.", &[("plan", "free")]);
+/// signups.inc();
+///
+/// registry
+///     .counter("signups_total", "Total number of signups.", &[("plan", "free")])
+///     .inc();
+///
+/// assert_eq!(signups.get(), 2);
+/// assert!(registry
+///     .render()
+///     .contains("# TYPE signups_total counter\nsignups_total{plan=\"free\"} 2\n"));
+/// ```
+#[derive(Default, Debug)]
+pub struct Registry {
+    families: Mutex<BTreeMap<String, Family>>,
+}
+
+impl Registry {
+    /// Gets or registers a counter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a metric of another kind was registered with the same name.
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Counter {
+        match self.series(name, help, labels, Kind::Counter, || {
+            Series::Counter(Counter::default())
+        }) {
+            Series::Counter(counter) => counter,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Gets or registers a gauge.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a metric of another kind was registered with the same name.
+    pub fn gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+        match self.series(name, help, labels, Kind::Gauge, || {
+            Series::Gauge(Gauge::default())
+        }) {
+            Series::Gauge(gauge) => gauge,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Gets or registers a histogram with the given bucket upper bounds, which must be sorted.
+    /// The buckets are ignored if the histogram was already registered.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a metric of another kind was registered with the same name.
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        buckets: &[f64],
+    ) -> Histogram {
+        match self.series(name, help, labels, Kind::Histogram, || {
+            Series::Histogram(Histogram::new(buckets))
+        }) {
+            Series::Histogram(histogram) => histogram,
+            _ => unreachable!(),
+        }
+    }
+
+    fn series(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        kind: Kind,
+        new: impl FnOnce() -> Series,
+    ) -> Series {
+        let mut families = self.families();
+
+        let family = families.entry(name.to_string()).or_insert_with(|| Family {
+            help: help.to_string(),
+            kind,
+            series: BTreeMap::new(),
+        });
+
+        if family.kind != kind {
+            panic!(
+                "metric {name} is already registered as a {}, not a {kind}",
+                family.kind
+            );
+        }
+
+        let mut labels = labels
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Labels>();
+        labels.sort();
+
+        family.series.entry(labels).or_insert_with(new).clone()
+    }
+
+    /// Locks the metric families, which are still consistent if registering a metric of the wrong
+    /// kind panicked.
+    fn families(&self) -> MutexGuard<'_, BTreeMap<String, Family>> {
+        self.families.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let families = self.families();
+        let mut out = String::new();
+
+        for (name, family) in families.iter() {
+            let _ = writeln!(out, "# HELP {name} {}", escape(&family.help, false));
+            let _ = writeln!(out, "# TYPE {name} {}", family.kind);
+
+            for (labels, series) in &family.series {
+                match series {
+                    Series::Counter(counter) => {
+                        let _ = writeln!(out, "{name}{} {}", format_labels(labels), counter.get());
+                    }
+                    Series::Gauge(gauge) => {
+                        let _ = writeln!(out, "{name}{} {}", format_labels(labels), gauge.get());
+                    }
+                    Series::Histogram(histogram) => {
+                        let data = histogram.data();
+                        let mut cumulative = 0;
+
+                        for (bound, count) in data.bounds.iter().zip(&data.counts) {
+                            cumulative += count;
+                            let _ = writeln!(
+                                out,
+                                "{name}_bucket{} {cumulative}",
+                                format_labels(&with_le(labels, &bound.to_string()))
+                            );
+                        }
+
+                        let _ = writeln!(
+                            out,
+                            "{name}_bucket{} {}",
+                            format_labels(&with_le(labels, "+Inf")),
+                            data.count
+                        );
+
+                        let labels = format_labels(labels);
+                        let _ = writeln!(out, "{name}_sum{labels} {}", data.sum);
+                        let _ = writeln!(out, "{name}_count{labels} {}", data.count);
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// Adds the `le` label of a histogram bucket to the labels of a series.
+fn with_le(labels: &Labels, bound: &str) -> Labels {
+    let mut labels = labels.clone();
+    labels.push(("le".to_string(), bound.to_string()));
+    labels
+}
+
+/// Formats labels as `{key="value",...}`, or an empty string if there are none.
+fn format_labels(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let labels = labels
+        .iter()
+        .map(|(key, value)| format!("{key}=\"{}\"", escape(value, true)))
+        .collect::<Vec<_>>();
+
+    format!("{{{}}}", labels.join(","))
+}
+
+/// Escapes backslashes and newlines, and quotes in label values.
+fn escape(value: &str, quotes: bool) -> String {
+    let value = value.replace('\\', "\\\\").replace('\n', "\\n");
+
+    match quotes {
+        true => value.replace('"', "\\\""),
+        false => value,
+    }
+}
+
+/// Handler that exposes the metrics of the registry in the Prometheus text exposition format.
+///
+/// Samples the state of the database pool before rendering.
+pub fn handler(ctx: Context, _: Request) -> Response {
+    #[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+    {
+        let state = ctx.db.state();
+
+        ctx.metrics
+            .gauge(
+                "snx_db_pool_connections",
+                "Number of connections in the database pool.",
+                &[],
+            )
+            .set(state.connections.into());
+        ctx.metrics
+            .gauge(
+                "snx_db_pool_idle_connections",
+                "Number of idle connections in the database pool.",
+                &[],
+            )
+            .set(state.idle_connections.into());
+        ctx.metrics
+            .gauge(
+                "snx_db_pool_max_size",
+                "Maximum number of connections in the database pool.",
+                &[],
+            )
+            .set(ctx.db.max_size().into());
+    }
+
+    let mut res = Response::new(ctx.metrics.render());
+    res.headers_mut()
+        .insert("Content-Type", "text/plain; version=0.0.4; charset=utf-8");
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn renders_counters_and_gauges() {
+        let registry = Registry::default();
+        registry
+            .counter("jobs_total", "Total number of jobs.", &[("queue", "mail")])
+            .inc_by(3);
+        registry
+            .counter(
+                "jobs_total",
+                "Total number of jobs.",
+                &[("queue", "default")],
+            )
+            .inc();
+        registry.gauge("workers", "Number of workers.", &[]).set(-2);
+
+        assert_eq!(
+            registry.render(),
+            "# HELP jobs_total Total number of jobs.\n\
+             # TYPE jobs_total counter\n\
+             jobs_total{queue=\"default\"} 1\n\
+             jobs_total{queue=\"mail\"} 3\n\
+             # HELP workers Number of workers.\n\
+             # TYPE workers gauge\n\
+             workers -2\n"
+        );
+    }
+
+    #[test]
+    fn renders_cumulative_histogram_buckets() {
+        let registry = Registry::default();
+        let histogram = registry.histogram(
+            "latency_seconds",
+            "Latency in seconds.",
+            &[("route", "/")],
+            &[0.1, 0.5, 1.0],
+        );
+        for value in [0.05, 0.1, 0.3, 2.0] {
+            histogram.observe(value);
+        }
+
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(
+            registry.render(),
+            "# HELP latency_seconds Latency in seconds.\n\
+             # TYPE latency_seconds histogram\n\
+             latency_seconds_bucket{route=\"/\",le=\"0.1\"} 2\n\
+             latency_seconds_bucket{route=\"/\",le=\"0.5\"} 3\n\
+             latency_seconds_bucket{route=\"/\",le=\"1\"} 3\n\
+             latency_seconds_bucket{route=\"/\",le=\"+Inf\"} 4\n\
+             latency_seconds_sum{route=\"/\"} 2.45\n\
+             latency_seconds_count{route=\"/\"} 4\n"
+        );
+    }
+
+    #[test]
+    fn escapes_help_and_label_values() {
+        let registry = Registry::default();
+        registry
+            .counter(
+                "hits_total",
+                "Hits of \"paths\" \\ per\nline.",
+                &[("path", "/a\"b\\c\nd")],
+            )
+            .inc();
+
+        assert_eq!(
+            registry.render(),
+            "# HELP hits_total Hits of \"paths\" \\\\ per\\nline.\n\
+             # TYPE hits_total counter\n\
+             hits_total{path=\"/a\\\"b\\\\c\\nd\"} 1\n"
+        );
+    }
+
+    #[test]
+    fn sorts_labels_to_identify_series() {
+        let registry = Registry::default();
+        registry
+            .counter("hits_total", "Hits.", &[("b", "2"), ("a", "1")])
+            .inc();
+        registry
+            .counter("hits_total", "Hits.", &[("a", "1"), ("b", "2")])
+            .inc();
+
+        assert!(registry
+            .render()
+            .contains("hits_total{a=\"1\",b=\"2\"} 2\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = "metric jobs is already registered as a counter, not a gauge")]
+    fn panics_if_a_metric_is_registered_as_another_kind() {
+        let registry = Registry::default();
+        registry.counter("jobs", "Jobs.", &[]);
+        registry.gauge("jobs", "Jobs.", &[]);
+    }
+
+    #[test]
+    fn keeps_working_after_a_kind_mismatch() {
+        let registry = Registry::default();
+        registry.counter("jobs", "Jobs.", &[]).inc();
+
+        let mismatch = panic::catch_unwind(AssertUnwindSafe(|| {
+            registry.histogram("jobs", "Jobs.", &[], DEFAULT_BUCKETS)
+        }));
+
+        assert!(mismatch.is_err());
+        registry.counter("jobs", "Jobs.", &[]).inc();
+        assert!(registry.render().contains("jobs 2\n"));
+    }
+
+    #[test]
+    fn handler_renders_the_registry_of_the_context() {
+        let ctx = Context::new(Config::for_tests(""));
+        ctx.metrics
+            .gauge("workers", "Number of workers.", &[])
+            .set(4);
+
+        let res = handler(ctx, Request::builder().build());
+        let body = String::from_utf8(res.body().clone().unwrap().into_bytes().unwrap()).unwrap();
+
+        assert_eq!(
+            res.headers().get("content-type").as_deref(),
+            Some("text/plain; version=0.0.4; charset=utf-8")
+        );
+        assert!(body.contains("# TYPE workers gauge\nworkers 4\n"));
+    }
+}
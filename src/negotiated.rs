@@ -0,0 +1,127 @@
+use crate::{
+    request::Request,
+    response::{IntoResponse, Response},
+    Html, StatusCode,
+};
+
+type Representation = Box<dyn FnOnce() -> Response>;
+
+/// Represents a response with multiple representations, of which the one the client prefers
+/// according to its `Accept` header is sent.
+///
+/// Only the chosen representation is built. Responds with `406 Not Acceptable` if the client
+/// accepts none of them. Either way `Vary: Accept` is set, so caches keep the representations
+/// apart.
+///
+/// ```
+/// use snx::{request::Request, Context, Html, Json, Negotiated};
+///
+/// fn show(_: Context, req: Request) -> Negotiated {
+///     Negotiated::new(&req)
+///         .html(|| Html("<h1>hello world!</h1>".to_string()))
+///         .json(|| Json(["hello", "world!"]))
+///         .with("text/plain", || "hello world!")
+/// }
+/// ```
+pub struct Negotiated {
+    accept: Option<String>,
+    representations: Vec<(String, Representation)>,
+}
+
+impl Negotiated {
+    /// Creates a response without representations for the given request.
+    pub fn new(req: &Request) -> Self {
+        Self {
+            accept: req.headers().get("accept"),
+            representations: vec![],
+        }
+    }
+
+    /// Adds a representation of the given media type.
+    pub fn with<R: IntoResponse + 'static>(
+        mut self,
+        media_type: &str,
+        representation: impl FnOnce() -> R + 'static,
+    ) -> Self {
+        self.representations.push((
+            media_type.to_string(),
+            Box::new(move || representation().into_response()),
+        ));
+
+        self
+    }
+
+    /// Adds an HTML representation.
+    pub fn html(self, representation: impl FnOnce() -> Html + 'static) -> Self {
+        self.with("text/html", representation)
+    }
+
+    /// Adds a JSON representation.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + 'static>(
+        self,
+        representation: impl FnOnce() -> crate::Json<T> + 'static,
+    ) -> Self {
+        self.with("application/json", representation)
+    }
+}
+
+impl IntoResponse for Negotiated {
+    fn into_response(mut self) -> Response {
+        let preferred = {
+            let media_types = self
+                .representations
+                .iter()
+                .map(|(media_type, _)| media_type.as_str())
+                .collect::<Vec<_>>();
+
+            crate::accept::preferred(self.accept.as_deref(), &media_types)
+                .and_then(|preferred| media_types.iter().position(|m| *m == preferred))
+        };
+
+        let mut res = match preferred {
+            Some(i) => {
+                let (media_type, representation) = self.representations.swap_remove(i);
+                let mut res = representation();
+
+                if res.headers().get("content-type").is_none() {
+                    res.headers_mut().insert("Content-Type", &media_type);
+                }
+
+                res
+            }
+            None => StatusCode::NotAcceptable.into_response(),
+        };
+
+        crate::http::header::vary(res.headers_mut(), &["Accept"]);
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn does_not_duplicate_the_vary_header() {
+        let req = Request::builder().header("Accept", "text/plain").build();
+        let res = Negotiated::new(&req)
+            .with("text/plain", || {
+                let mut res = Response::new("hello world!");
+                res.headers_mut().insert("Vary", "accept, Origin");
+
+                res
+            })
+            .into_response();
+
+        assert_eq!(res.headers().get_all("vary").unwrap(), ["accept, Origin"]);
+
+        let res = Negotiated::new(&req)
+            .with("text/html", || "hello world!")
+            .into_response();
+
+        assert_eq!(*res.status(), StatusCode::NotAcceptable);
+        assert_eq!(res.headers().get_all("vary").unwrap(), ["Accept"]);
+    }
+}
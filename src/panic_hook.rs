@@ -1,4 +1,20 @@
-use std::{backtrace::Backtrace, panic::PanicHookInfo};
+use std::{backtrace::Backtrace, cell::RefCell, panic::PanicHookInfo};
+
+/// The details of a panic, kept so they can be shown on the debug error page.
+pub(crate) struct PanicDetails {
+    pub message: String,
+    pub location: String,
+    pub backtrace: String,
+}
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<PanicDetails>> = const { RefCell::new(None) };
+}
+
+/// Takes the details of the last panic on the current thread, if the panic hook is installed.
+pub(crate) fn take_last_panic() -> Option<PanicDetails> {
+    LAST_PANIC.with(|last| last.borrow_mut().take())
+}
 
 fn trace(info: &PanicHookInfo, backtrace: Backtrace, s: &str) {
     tracing::error!(
@@ -9,6 +25,14 @@ fn trace(info: &PanicHookInfo, backtrace: Backtrace, s: &str) {
         s,
     );
     tracing::error!("panic backtrace: {}", backtrace);
+
+    LAST_PANIC.with(|last| {
+        *last.borrow_mut() = Some(PanicDetails {
+            message: s.to_string(),
+            location: info.location().unwrap().to_string(),
+            backtrace: backtrace.to_string(),
+        })
+    });
 }
 
 /// Logs the panic location, reason and backtrace using tracing.
@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    error::ErrorDetails,
+    request::Request,
+    response::{IntoResponse, Response},
+    StatusCode,
+};
+
+/// Represents a problem details response, as defined in RFC 9457.
+///
+/// ```
+/// use snx::{Problem, StatusCode};
+///
+/// let problem = Problem::new(StatusCode::Forbidden)
+///     .type_uri("https://example.com/probs/out-of-credit")
+///     .title("You do not have enough credit.")
+///     .detail("Your current balance is 30, but that costs 50.")
+///     .instance("/account/12345/msgs/abc")
+///     .extension("balance", 30);
+/// ```
+#[derive(Serialize, Clone, Debug)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    type_uri: String,
+    title: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<String>,
+    #[serde(flatten)]
+    extensions: BTreeMap<String, serde_json::Value>,
+}
+
+impl Problem {
+    /// Creates a problem with the given status, using `about:blank` as its type and the reason
+    /// phrase of the status as its title.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            type_uri: "about:blank".to_string(),
+            title: status.canonical_reason().to_string(),
+            status: status.into(),
+            detail: None,
+            instance: None,
+            extensions: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the URI that identifies the type of the problem.
+    pub fn type_uri(mut self, type_uri: &str) -> Self {
+        self.type_uri = type_uri.to_string();
+
+        self
+    }
+
+    /// Sets the short summary of the type of the problem.
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+
+        self
+    }
+
+    /// Sets the explanation specific to this occurrence of the problem.
+    pub fn detail(mut self, detail: &str) -> Self {
+        self.detail = Some(detail.to_string());
+
+        self
+    }
+
+    /// Sets the URI that identifies this occurrence of the problem.
+    pub fn instance(mut self, instance: &str) -> Self {
+        self.instance = Some(instance.to_string());
+
+        self
+    }
+
+    /// Adds an extension member.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value can't be serialized into JSON.
+    pub fn extension(mut self, key: &str, value: impl Serialize) -> Self {
+        self.extensions.insert(
+            key.to_string(),
+            serde_json::to_value(value).expect("failed to serialize extension member into json"),
+        );
+
+        self
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let bytes = serde_json::to_vec(&self).expect("failed to serialize problem into json bytes");
+
+        let mut res = Response::new(bytes);
+        *res.status_mut() =
+            StatusCode::try_from(self.status).unwrap_or(StatusCode::InternalServerError);
+        res.headers_mut()
+            .insert("Content-Type", "application/problem+json");
+
+        res
+    }
+}
+
+/// Turns an error response into a problem details response, if it has no body or was created from
+/// an [crate::Error]. Headers of the response, e.g. `Allow` or `Retry-After`, are kept.
+pub(crate) fn apply(req: &Request, res: Response) -> Response {
+    let status = res.status().clone();
+    let is_empty = res.body().as_ref().is_none_or(|body| body.is_empty());
+
+    let details = res.extensions().get::<ErrorDetails>();
+
+    if u16::from(status.clone()) < 400 || (!is_empty && details.is_none()) {
+        return res;
+    }
+
+    let mut problem = Problem::new(status).instance(req.path().split('?').next().unwrap_or("/"));
+    if let Some(details) = details {
+        if details.message != problem.title {
+            problem = problem.detail(&details.message);
+        }
+        if let Some(errors) = &details.validation_errors {
+            problem = problem.extension("errors", errors);
+        }
+    }
+
+    let mut headers = res.headers().clone();
+    headers.remove("content-type");
+
+    let mut res = problem.into_response();
+    for (name, values) in headers.iter() {
+        for value in values {
+            res.headers_mut().insert(name, value);
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(res: Response) -> serde_json::Value {
+        let bytes = res.body().clone().unwrap().into_bytes().unwrap();
+
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn request() -> Request {
+        Request::builder()
+            .path("/posts/1?draft=1")
+            .header("Accept", "application/json")
+            .build()
+    }
+
+    #[test]
+    fn renders_empty_error_responses() {
+        let mut res = StatusCode::MethodNotAllowed.into_response();
+        res.headers_mut().insert("Allow", "GET, HEAD");
+        let res = apply(&request(), res);
+
+        assert_eq!(
+            res.headers().get("content-type").as_deref(),
+            Some("application/problem+json")
+        );
+        assert_eq!(res.headers().get("allow").as_deref(), Some("GET, HEAD"));
+        assert_eq!(
+            json(res),
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Method Not Allowed",
+                "status": 405,
+                "instance": "/posts/1",
+            })
+        );
+    }
+
+    #[test]
+    fn renders_errors_with_their_message_as_detail() {
+        let res = apply(
+            &request(),
+            crate::Error::new(StatusCode::Forbidden, "You cannot edit this post.").into_response(),
+        );
+
+        assert_eq!(*res.status(), StatusCode::Forbidden);
+        assert_eq!(json(res)["detail"], "You cannot edit this post.");
+    }
+
+    #[test]
+    fn renders_validation_errors_as_extension() {
+        let mut errors = crate::validation::ValidationErrors::new();
+        errors.add("title", "The title is required.");
+        let res = apply(&request(), crate::Error::from(errors).into_response());
+
+        assert_eq!(*res.status(), StatusCode::UnprocessableContent);
+        assert_eq!(
+            json(res)["errors"],
+            serde_json::json!({ "title": ["The title is required."] })
+        );
+    }
+
+    #[test]
+    fn keeps_successful_and_custom_error_responses() {
+        let res = apply(&request(), Response::new("hello world!"));
+        assert!(res.headers().get("content-type").is_none());
+
+        let mut res = Response::new("custom not found page");
+        *res.status_mut() = StatusCode::NotFound;
+        let res = apply(&request(), res);
+        assert_eq!(
+            res.body().as_ref().and_then(|body| body.as_bytes()),
+            Some("custom not found page".as_bytes())
+        );
+    }
+
+    #[test]
+    fn prefers_json_only_if_listed_explicitly() {
+        let accept = |value: &str| Request::builder().header("Accept", value).build();
+
+        assert!(accept("application/problem+json").prefers_json());
+        assert!(accept("text/html;q=0.5, application/json").prefers_json());
+        assert!(!accept("text/html, application/json;q=0.9").prefers_json());
+        assert!(!accept("*/*").prefers_json());
+        assert!(!Request::builder().build().prefers_json());
+    }
+}
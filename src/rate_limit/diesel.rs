@@ -0,0 +1,327 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use diesel::{
+    prelude::*,
+    r2d2::{ConnectionManager, Pool},
+    result::DatabaseErrorKind,
+};
+
+use crate::{
+    db::DatabaseConnection,
+    rate_limit::{Bucket, Error, RateLimitStore},
+};
+
+diesel::table! {
+    snx_rate_limits (key) {
+        key -> Text,
+        count -> Double,
+        previous -> Double,
+        updated_at -> BigInt,
+        expires_at -> BigInt,
+    }
+}
+
+/// The amount of updates after which expired buckets are removed.
+const PRUNE_INTERVAL: usize = 1024;
+
+/// A rate limit store that saves buckets to the database, so limits hold across several
+/// processes sharing the same database.
+///
+/// Expects the following table to exist:
+///
+/// ```sql
+/// CREATE TABLE snx_rate_limits (
+///     key VARCHAR(255) PRIMARY KEY NOT NULL,
+///     count DOUBLE PRECISION NOT NULL,
+///     previous DOUBLE PRECISION NOT NULL,
+///     updated_at BIGINT NOT NULL,
+///     expires_at BIGINT NOT NULL
+/// );
+/// ```
+pub struct DieselRateLimitStore {
+    pool: Pool<ConnectionManager<DatabaseConnection>>,
+    updates: AtomicUsize,
+}
+
+impl DieselRateLimitStore {
+    /// Creates a new store using the given connection pool, e.g. the pool of the [crate::Context].
+    pub fn new(pool: Pool<ConnectionManager<DatabaseConnection>>) -> Self {
+        Self {
+            pool,
+            updates: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Loads the current bucket for a key, locking its row until the end of the transaction.
+fn load(conn: &mut DatabaseConnection, key: &str) -> QueryResult<Option<(Bucket, i64)>> {
+    let query = snx_rate_limits::table
+        .filter(snx_rate_limits::key.eq(key))
+        .select((
+            snx_rate_limits::count,
+            snx_rate_limits::previous,
+            snx_rate_limits::updated_at,
+            snx_rate_limits::expires_at,
+        ));
+
+    // SQLite locks the whole database for immediate transactions instead.
+    #[cfg(not(feature = "sqlite"))]
+    let query = query.for_update();
+
+    Ok(query.first::<(f64, f64, i64, i64)>(conn).optional()?.map(
+        |(count, previous, updated_at, expires_at)| {
+            (
+                Bucket {
+                    count,
+                    previous,
+                    updated_at,
+                },
+                expires_at,
+            )
+        },
+    ))
+}
+
+/// Runs the given function in a transaction that prevents concurrent updates of the same bucket.
+fn transaction<T>(
+    conn: &mut DatabaseConnection,
+    f: impl FnOnce(&mut DatabaseConnection) -> QueryResult<T>,
+) -> QueryResult<T> {
+    #[cfg(feature = "sqlite")]
+    return conn.immediate_transaction(f);
+
+    #[cfg(not(feature = "sqlite"))]
+    return conn.transaction(f);
+}
+
+impl RateLimitStore for DieselRateLimitStore {
+    fn update(
+        &self,
+        key: &str,
+        expires_after: Duration,
+        f: &mut dyn FnMut(Option<Bucket>) -> Bucket,
+    ) -> Result<(), Error> {
+        let mut conn = self.pool.get()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        let expires_at = now + expires_after.as_millis() as i64;
+
+        let updates = self.updates.fetch_add(1, Ordering::Relaxed) + 1;
+        if updates.is_multiple_of(PRUNE_INTERVAL) {
+            diesel::delete(snx_rate_limits::table.filter(snx_rate_limits::expires_at.le(now)))
+                .execute(&mut conn)?;
+        }
+
+        let attempt = || {
+            transaction(&mut conn, |conn| {
+                let current = load(conn, key)?;
+                let exists = current.is_some();
+                let bucket = f(current
+                    .filter(|(_, expires_at)| *expires_at > now)
+                    .map(|(bucket, _)| bucket));
+
+                let values = (
+                    snx_rate_limits::count.eq(bucket.count),
+                    snx_rate_limits::previous.eq(bucket.previous),
+                    snx_rate_limits::updated_at.eq(bucket.updated_at),
+                    snx_rate_limits::expires_at.eq(expires_at),
+                );
+
+                match exists {
+                    true => diesel::update(snx_rate_limits::table.find(key))
+                        .set(values)
+                        .execute(conn),
+                    false => diesel::insert_into(snx_rate_limits::table)
+                        .values((snx_rate_limits::key.eq(key), values))
+                        .execute(conn),
+                }
+            })
+        };
+
+        retry_on_unique_violation(attempt)?;
+
+        Ok(())
+    }
+}
+
+/// Runs the given attempt again if it failed because another process inserted the bucket first,
+/// in which case the bucket can be updated now.
+fn retry_on_unique_violation<T>(mut attempt: impl FnMut() -> QueryResult<T>) -> QueryResult<T> {
+    match attempt() {
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            attempt()
+        }
+        result => result,
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use diesel::result::Error as DieselError;
+
+    use super::*;
+    use crate::rate_limit::{hit, RateLimitAlgorithm};
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn store() -> DieselRateLimitStore {
+        // every connection to an in-memory database has its own database
+        let pool = Pool::builder()
+            .max_size(1)
+            .build(ConnectionManager::new(":memory:"))
+            .unwrap();
+
+        diesel::sql_query(
+            "CREATE TABLE snx_rate_limits (
+                key VARCHAR(255) PRIMARY KEY NOT NULL,
+                count DOUBLE PRECISION NOT NULL,
+                previous DOUBLE PRECISION NOT NULL,
+                updated_at BIGINT NOT NULL,
+                expires_at BIGINT NOT NULL
+            )",
+        )
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+
+        DieselRateLimitStore::new(pool)
+    }
+
+    fn allowed(store: &DieselRateLimitStore, key: &str) -> bool {
+        hit(
+            store,
+            key,
+            RateLimitAlgorithm::TokenBucket,
+            2,
+            MINUTE,
+            SystemTime::now(),
+        )
+        .unwrap()
+        .allowed
+    }
+
+    fn keys(store: &DieselRateLimitStore) -> Vec<String> {
+        snx_rate_limits::table
+            .select(snx_rate_limits::key)
+            .order(snx_rate_limits::key)
+            .load(&mut store.pool.get().unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn limits_requests() {
+        let store = store();
+
+        assert!(allowed(&store, "a"));
+        assert!(allowed(&store, "a"));
+        assert!(!allowed(&store, "a"));
+        assert!(allowed(&store, "b"));
+        assert_eq!(keys(&store), ["a", "b"]);
+    }
+
+    #[test]
+    fn ignores_expired_buckets() {
+        let store = store();
+        diesel::insert_into(snx_rate_limits::table)
+            .values((
+                snx_rate_limits::key.eq("a"),
+                snx_rate_limits::count.eq(2.0),
+                snx_rate_limits::previous.eq(0.0),
+                snx_rate_limits::updated_at.eq(i64::MAX / 2),
+                snx_rate_limits::expires_at.eq(0),
+            ))
+            .execute(&mut store.pool.get().unwrap())
+            .unwrap();
+
+        assert!(allowed(&store, "a"));
+    }
+
+    #[test]
+    fn counts_concurrent_requests_exactly() {
+        let store = Arc::new(store());
+        let now = SystemTime::now();
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let store = store.clone();
+                thread::spawn(move || {
+                    (0..10)
+                        .filter(|_| {
+                            hit(
+                                &*store,
+                                "test",
+                                RateLimitAlgorithm::TokenBucket,
+                                25,
+                                MINUTE,
+                                now,
+                            )
+                            .unwrap()
+                            .allowed
+                        })
+                        .count()
+                })
+            })
+            .collect();
+
+        let allowed: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(allowed, 25);
+    }
+
+    #[test]
+    fn prunes_expired_buckets_periodically() {
+        let store = store();
+        store
+            .update("expired", Duration::ZERO, &mut |_| Bucket::default())
+            .unwrap();
+
+        for _ in 1..PRUNE_INTERVAL - 1 {
+            allowed(&store, "a");
+        }
+        assert_eq!(keys(&store), ["a", "expired"]);
+
+        allowed(&store, "a");
+        assert_eq!(keys(&store), ["a"]);
+    }
+
+    #[test]
+    fn retries_once_if_the_bucket_was_inserted_concurrently() {
+        let violation = || {
+            DieselError::DatabaseError(
+                DatabaseErrorKind::UniqueViolation,
+                Box::new("UNIQUE constraint failed: snx_rate_limits.key".to_string()),
+            )
+        };
+
+        let mut attempts = 0;
+        let result = retry_on_unique_violation(|| {
+            attempts += 1;
+            match attempts {
+                1 => Err(violation()),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(result, Ok(2));
+
+        let mut attempts = 0;
+        let result = retry_on_unique_violation(|| -> QueryResult<()> {
+            attempts += 1;
+            Err(violation())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 2);
+
+        let mut attempts = 0;
+        let result = retry_on_unique_violation(|| -> QueryResult<()> {
+            attempts += 1;
+            Err(DieselError::NotFound)
+        });
+        assert_eq!(result, Err(DieselError::NotFound));
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,53 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::rate_limit::{Bucket, Error, RateLimitStore};
+
+/// The amount of updates after which expired buckets are removed.
+const PRUNE_INTERVAL: usize = 1024;
+
+/// A rate limit store that keeps buckets in memory.
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    data: HashMap<String, (Bucket, Instant)>,
+    updates: usize,
+}
+
+impl RateLimitStore for MemoryRateLimitStore {
+    fn update(
+        &self,
+        key: &str,
+        expires_after: Duration,
+        f: &mut dyn FnMut(Option<Bucket>) -> Bucket,
+    ) -> Result<(), Error> {
+        let now = Instant::now();
+        // a panic while holding the lock cannot leave a bucket half updated, so the state can
+        // still be used
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        state.updates += 1;
+        if state.updates.is_multiple_of(PRUNE_INTERVAL) {
+            state.data.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+
+        let current = state
+            .data
+            .get(key)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(bucket, _)| *bucket);
+
+        state
+            .data
+            .insert(key.to_string(), (f(current), now + expires_after));
+
+        Ok(())
+    }
+}
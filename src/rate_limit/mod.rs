@@ -0,0 +1,349 @@
+//! Limits the rate of requests to protect against abuse.
+//!
+//! Requests are counted by the [crate::middleware::limit_requests] middleware using the rules in
+//! the `[rate_limit]` section of the config. Counts are kept in a [RateLimitStore], which is kept
+//! in memory by default. Use [DieselRateLimitStore] to share limits between several processes
+//! using the same database.
+
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+mod diesel;
+mod memory;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+pub use diesel::DieselRateLimitStore;
+pub use memory::MemoryRateLimitStore;
+
+pub use crate::config::{RateLimitAlgorithm, RateLimitKey};
+
+/// The state of a rate limit for a single key.
+///
+/// For a token bucket, `count` is the amount of tokens taken from the bucket at `updated_at`. For
+/// a sliding window, `count` is the amount of requests in the window starting at `updated_at` and
+/// `previous` the amount of requests in the window before it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Bucket {
+    pub count: f64,
+    pub previous: f64,
+    /// Milliseconds since the Unix epoch.
+    pub updated_at: i64,
+}
+
+/// A store for the buckets of rate limits, which is shared by all threads handling requests.
+///
+/// Stores synchronize access themselves, so slow stores such as databases do not block requests
+/// counted by other threads.
+pub trait RateLimitStore {
+    /// Atomically updates the bucket for the given key using the given function, which receives
+    /// the current bucket if it exists and returns the new bucket.
+    ///
+    /// Buckets may be removed once they have not been updated for the given duration.
+    fn update(
+        &self,
+        key: &str,
+        expires_after: Duration,
+        f: &mut dyn FnMut(Option<Bucket>) -> Bucket,
+    ) -> Result<(), Error>;
+}
+
+/// Represents an error that occurred while accessing a rate limit store.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+    #[error(transparent)]
+    Diesel(#[from] ::diesel::result::Error),
+    #[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+    #[error(transparent)]
+    Pool(#[from] ::diesel::r2d2::PoolError),
+}
+
+/// The outcome of counting a request against a rate limit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Decision {
+    /// Whether the request is allowed.
+    pub allowed: bool,
+    /// The maximum amount of requests per period.
+    pub limit: u32,
+    /// The amount of requests that are still allowed.
+    pub remaining: u32,
+    /// Seconds until the limit is fully reset.
+    pub reset: u64,
+    /// Seconds until a request will be allowed again, if it is not allowed now.
+    pub retry_after: u64,
+}
+
+/// Counts a request for the given key against a limit of requests per period.
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+///
+/// use snx::rate_limit::{self, MemoryRateLimitStore, RateLimitAlgorithm};
+///
+/// let store = MemoryRateLimitStore::default();
+/// let now = SystemTime::now();
+///
+/// for _ in 0..5 {
+///     let decision = rate_limit::hit(
+///         &store,
+///         "login:ip:127.0.0.1",
+///         RateLimitAlgorithm::TokenBucket,
+///         5,
+///         Duration::from_secs(60),
+///         now,
+///     )
+///     .unwrap();
+///
+///     assert!(decision.allowed);
+/// }
+///
+/// let decision = rate_limit::hit(
+///     &store,
+///     "login:ip:127.0.0.1",
+///     RateLimitAlgorithm::TokenBucket,
+///     5,
+///     Duration::from_secs(60),
+///     now,
+/// )
+/// .unwrap();
+///
+/// assert!(!decision.allowed);
+/// assert_eq!(decision.retry_after, 12);
+/// ```
+pub fn hit(
+    store: &dyn RateLimitStore,
+    key: &str,
+    algorithm: RateLimitAlgorithm,
+    limit: u32,
+    period: Duration,
+    now: SystemTime,
+) -> Result<Decision, Error> {
+    let now = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    let period = (period.as_millis() as i64).max(1);
+
+    let mut decision = None;
+    store.update(
+        key,
+        Duration::from_millis(period as u64 * 2),
+        &mut |bucket| {
+            let (bucket, outcome) = match algorithm {
+                RateLimitAlgorithm::TokenBucket => token_bucket(bucket, limit, period, now),
+                RateLimitAlgorithm::SlidingWindow => sliding_window(bucket, limit, period, now),
+            };
+            decision = Some(outcome);
+
+            bucket
+        },
+    )?;
+
+    Ok(decision.expect("rate limit stores must call the update function"))
+}
+
+/// Converts milliseconds to whole seconds, rounding up.
+fn secs(millis: f64) -> u64 {
+    (millis.max(0.0) / 1000.0).ceil() as u64
+}
+
+/// Takes a token from a bucket which refills at a steady rate of `limit` tokens per period.
+fn token_bucket(bucket: Option<Bucket>, limit: u32, period: i64, now: i64) -> (Bucket, Decision) {
+    let limit_f = limit as f64;
+    let rate = limit_f / period as f64;
+
+    let bucket = bucket.unwrap_or_default();
+    let elapsed = (now - bucket.updated_at).max(0) as f64;
+    let taken = (bucket.count - elapsed * rate).max(0.0);
+
+    let allowed = taken + 1.0 <= limit_f;
+    let taken = if allowed { taken + 1.0 } else { taken };
+
+    let decision = Decision {
+        allowed,
+        limit,
+        remaining: (limit_f - taken).floor().max(0.0) as u32,
+        reset: secs(taken / rate),
+        retry_after: if allowed {
+            0
+        } else {
+            secs((taken + 1.0 - limit_f) / rate).max(1)
+        },
+    };
+
+    (
+        Bucket {
+            count: taken,
+            previous: 0.0,
+            updated_at: now,
+        },
+        decision,
+    )
+}
+
+/// Counts a request in a sliding window of one period, approximated by weighing the count of the
+/// previous fixed window by how much of it overlaps with the sliding window.
+fn sliding_window(bucket: Option<Bucket>, limit: u32, period: i64, now: i64) -> (Bucket, Decision) {
+    let limit_f = limit as f64;
+    let window = now - now.rem_euclid(period);
+
+    let (mut current, previous) = match bucket {
+        Some(bucket) if bucket.updated_at == window => (bucket.count, bucket.previous),
+        Some(bucket) if bucket.updated_at == window - period => (0.0, bucket.count),
+        _ => (0.0, 0.0),
+    };
+
+    let elapsed = (now - window) as f64;
+    let weight = 1.0 - elapsed / period as f64;
+    let estimated = previous * weight + current;
+
+    let allowed = estimated + 1.0 <= limit_f;
+    if allowed {
+        current += 1.0;
+    }
+
+    let retry_after = match allowed {
+        true => 0,
+        // the current window alone exceeds the limit, so wait for the next window
+        false if current + 1.0 > limit_f || previous == 0.0 => secs(period as f64 - elapsed),
+        false => {
+            let target = 1.0 - (limit_f - current - 1.0) / previous;
+            secs(period as f64 * target - elapsed)
+        }
+    };
+
+    let decision = Decision {
+        allowed,
+        limit,
+        remaining: (limit_f - previous * weight - current).floor().max(0.0) as u32,
+        reset: secs(period as f64 - elapsed),
+        retry_after: retry_after.max(if allowed { 0 } else { 1 }),
+    };
+
+    (
+        Bucket {
+            count: current,
+            previous,
+            updated_at: window,
+        },
+        decision,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn allowed(store: &dyn RateLimitStore, algorithm: RateLimitAlgorithm, now: SystemTime) -> bool {
+        hit(store, "test", algorithm, 2, MINUTE, now)
+            .unwrap()
+            .allowed
+    }
+
+    #[test]
+    fn token_bucket_refills_at_a_steady_rate() {
+        let store = MemoryRateLimitStore::default();
+
+        assert!(allowed(&store, RateLimitAlgorithm::TokenBucket, at(600)));
+        assert!(allowed(&store, RateLimitAlgorithm::TokenBucket, at(600)));
+
+        let decision = hit(
+            &store,
+            "test",
+            RateLimitAlgorithm::TokenBucket,
+            2,
+            MINUTE,
+            at(600),
+        )
+        .unwrap();
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.retry_after, 30);
+
+        assert!(allowed(&store, RateLimitAlgorithm::TokenBucket, at(630)));
+        assert!(!allowed(&store, RateLimitAlgorithm::TokenBucket, at(630)));
+    }
+
+    #[test]
+    fn sliding_window_weighs_the_previous_window() {
+        let store = MemoryRateLimitStore::default();
+
+        assert!(allowed(&store, RateLimitAlgorithm::SlidingWindow, at(600)));
+        assert!(allowed(&store, RateLimitAlgorithm::SlidingWindow, at(600)));
+        assert!(!allowed(&store, RateLimitAlgorithm::SlidingWindow, at(659)));
+
+        // half of the previous window overlaps, which counts as one request
+        assert!(allowed(&store, RateLimitAlgorithm::SlidingWindow, at(690)));
+        assert!(!allowed(&store, RateLimitAlgorithm::SlidingWindow, at(690)));
+    }
+
+    #[test]
+    fn keys_are_limited_separately() {
+        let store = MemoryRateLimitStore::default();
+
+        for key in ["a", "a", "b", "b"] {
+            assert!(
+                hit(
+                    &store,
+                    key,
+                    RateLimitAlgorithm::TokenBucket,
+                    2,
+                    MINUTE,
+                    at(600)
+                )
+                .unwrap()
+                .allowed
+            );
+        }
+    }
+
+    #[test]
+    fn counts_concurrent_requests_exactly() {
+        let store = Arc::new(MemoryRateLimitStore::default());
+        let now = SystemTime::now();
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                thread::spawn(move || {
+                    (0..10)
+                        .filter(|_| {
+                            hit(
+                                &*store,
+                                "test",
+                                RateLimitAlgorithm::TokenBucket,
+                                50,
+                                MINUTE,
+                                now,
+                            )
+                            .unwrap()
+                            .allowed
+                        })
+                        .count()
+                })
+            })
+            .collect();
+
+        let allowed: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(allowed, 50);
+    }
+
+    #[test]
+    fn memory_store_recovers_from_a_panic_while_updating() {
+        let store = Arc::new(MemoryRateLimitStore::default());
+
+        let poisoner = store.clone();
+        let _ =
+            thread::spawn(move || poisoner.update("test", MINUTE, &mut |_| panic!("boom"))).join();
+
+        assert!(allowed(&*store, RateLimitAlgorithm::TokenBucket, at(600)));
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{
+    request::Request,
+    response::{IntoResponse, Response},
+    Context, StatusCode,
+};
+
+/// Represents a redirect response.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    status: StatusCode,
+    location: String,
+}
+
+impl Redirect {
+    /// Creates a redirect with a 302 Found status code.
+    ///
+    /// ```
+    /// use snx::Redirect;
+    ///
+    /// let redirect = Redirect::to("/login");
+    /// ```
+    pub fn to(uri: &str) -> Self {
+        Self::with_status(StatusCode::Found, uri)
+    }
+
+    /// Creates a redirect with a 303 See Other status code, which makes the client follow up with
+    /// a GET request. This is typically used after a form submission.
+    ///
+    /// ```
+    /// use snx::Redirect;
+    ///
+    /// let redirect = Redirect::see_other("/posts/1");
+    /// ```
+    pub fn see_other(uri: &str) -> Self {
+        Self::with_status(StatusCode::SeeOther, uri)
+    }
+
+    /// Creates a redirect with a 307 Temporary Redirect status code, which preserves the request
+    /// method and body.
+    ///
+    /// ```
+    /// use snx::Redirect;
+    ///
+    /// let redirect = Redirect::temporary("/maintenance");
+    /// ```
+    pub fn temporary(uri: &str) -> Self {
+        Self::with_status(StatusCode::TemporaryRedirect, uri)
+    }
+
+    /// Creates a redirect with a 308 Permanent Redirect status code, which preserves the request
+    /// method and body.
+    ///
+    /// ```
+    /// use snx::Redirect;
+    ///
+    /// let redirect = Redirect::permanent("/new-location");
+    /// ```
+    pub fn permanent(uri: &str) -> Self {
+        Self::with_status(StatusCode::PermanentRedirect, uri)
+    }
+
+    /// Creates a redirect with a 301 Moved Permanently status code.
+    ///
+    /// ```
+    /// use snx::Redirect;
+    ///
+    /// let redirect = Redirect::moved_permanently("/new-location");
+    /// ```
+    pub fn moved_permanently(uri: &str) -> Self {
+        Self::with_status(StatusCode::MovedPermanently, uri)
+    }
+
+    /// Creates a redirect back to the location in the `Referer` header of the request, or to the
+    /// fallback if the header is missing. Only relative and same-origin referers are followed, so
+    /// the client cannot be sent to another site.
+    ///
+    /// ```
+    /// use snx::{request::Request, Redirect};
+    ///
+    /// let req = Request::builder().header("Referer", "/posts/create").build();
+    /// let redirect = Redirect::back(&req, "/");
+    ///
+    /// assert_eq!(redirect.location(), "/posts/create");
+    ///
+    /// let req = Request::builder().header("Referer", "https://evil.example/").build();
+    /// let redirect = Redirect::back(&req, "/");
+    ///
+    /// assert_eq!(redirect.location(), "/");
+    /// ```
+    pub fn back(req: &Request, fallback: &str) -> Self {
+        let location = local_referer(req);
+
+        Self::see_other(location.as_deref().unwrap_or(fallback))
+    }
+
+    /// Creates a redirect to a named route, filling in its parameters. Returns `None` if the
+    /// route does not exist or a parameter is missing.
+    ///
+    /// ```no_run
+    /// use snx::{request::Request, Context, Redirect};
+    ///
+    /// fn store(ctx: Context, _: Request) -> Redirect {
+    ///     Redirect::route(&ctx, "posts.show", &[("id", "1")]).unwrap()
+    /// }
+    /// ```
+    pub fn route(ctx: &Context, name: &str, params: &[(&str, &str)]) -> Option<Self> {
+        Some(Self::see_other(&ctx.routes.url_for(name, params)?))
+    }
+
+    /// Creates a redirect with the given status code.
+    ///
+    /// Control characters such as CR and LF are stripped from the location, so a location built
+    /// from user input cannot inject headers into the response. This applies to every other
+    /// constructor as well.
+    ///
+    /// ```
+    /// use snx::{Redirect, StatusCode};
+    ///
+    /// let redirect = Redirect::with_status(StatusCode::MovedPermanently, "/new-location");
+    /// ```
+    pub fn with_status(status: StatusCode, uri: &str) -> Self {
+        Self {
+            status,
+            location: uri.chars().filter(|c| !c.is_control()).collect(),
+        }
+    }
+
+    /// Flashes a value into the session of the request, so it is available during the request
+    /// following the redirect. Does nothing if the request has no session.
+    ///
+    /// ```no_run
+    /// use snx::{request::Request, Context, Redirect};
+    ///
+    /// fn store(_: Context, req: Request) -> Redirect {
+    ///     Redirect::see_other("/posts").flash(&req, "status", "Post created!")
+    /// }
+    /// ```
+    #[cfg(feature = "sessions")]
+    pub fn flash(self, req: &Request, key: &str, value: impl serde::Serialize) -> Self {
+        if let Some(mut session) = req.session.clone() {
+            if let Err(err) = session.flash(key, value) {
+                tracing::error!("failed to flash {key} into session: {err}");
+            }
+        }
+
+        self
+    }
+
+    /// Flashes validation errors into the session of the request, so the form can show them after
+    /// the redirect using [crate::validation::errors].
+    ///
+    /// ```no_run
+    /// use snx::{request::Request, validation::ValidationErrors, Context, Redirect};
+    ///
+    /// fn store(_: Context, req: Request) -> Redirect {
+    ///     let mut errors = ValidationErrors::new();
+    ///     errors.add("title", "This title is already taken.");
+    ///
+    ///     Redirect::back(&req, "/posts/create")
+    ///         .with_errors(&req, errors)
+    ///         .with_input(&req)
+    /// }
+    /// ```
+    #[cfg(feature = "sessions")]
+    pub fn with_errors(self, req: &Request, errors: crate::validation::ValidationErrors) -> Self {
+        self.flash(req, crate::validation::ERRORS_FLASH_KEY, errors)
+    }
+
+    /// Flashes the URL-encoded form input of the request into its session, except passwords and
+    /// the CSRF token, so the form can be filled in again after the redirect using
+    /// [crate::validation::old].
+    #[cfg(all(feature = "sessions", feature = "forms"))]
+    pub fn with_input(self, req: &Request) -> Self {
+        let input = crate::validation::flashable_input(req);
+
+        self.flash(req, crate::validation::OLD_INPUT_FLASH_KEY, input)
+    }
+
+    /// Gets the status code of the redirect.
+    pub fn status(&self) -> &StatusCode {
+        &self.status
+    }
+
+    /// Gets the location of the redirect.
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+}
+
+impl IntoResponse for Redirect {
+    fn into_response(self) -> Response {
+        let mut res = Response::default();
+
+        *res.status_mut() = self.status;
+        res.headers_mut().insert("Location", &self.location);
+
+        res
+    }
+}
+
+/// Gets the path of the `Referer` header of the request if it is relative or points to the host of
+/// the request, so it is safe to redirect to.
+pub(crate) fn local_referer(req: &Request) -> Option<String> {
+    let referer = req.headers().get("referer")?;
+    if is_local_path(&referer) {
+        return Some(referer);
+    }
+
+    let rest = referer
+        .strip_prefix("https://")
+        .or_else(|| referer.strip_prefix("http://"))?;
+    let (authority, path) = rest.split_at(rest.find(['/', '?', '#']).unwrap_or(rest.len()));
+    let host = req.headers().get("host")?;
+    if !authority.eq_ignore_ascii_case(&host) {
+        return None;
+    }
+
+    let path = match path.starts_with('/') {
+        true => path.to_string(),
+        false => format!("/{path}"),
+    };
+
+    is_local_path(&path).then_some(path)
+}
+
+/// Returns whether the location is a path on the same host, rather than a protocol-relative URL
+/// such as `//evil.example`, which browsers resolve against another host.
+fn is_local_path(location: &str) -> bool {
+    location.starts_with('/')
+        && !location.starts_with("//")
+        && !location.starts_with("/\\")
+        && !location.chars().any(char::is_control)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn back(referer: &str) -> String {
+        let req = Request::builder()
+            .header("Host", "acme.com")
+            .header("Referer", referer)
+            .build();
+
+        Redirect::back(&req, "/fallback").location().to_string()
+    }
+
+    #[test]
+    fn strips_control_characters_from_the_location() {
+        let res =
+            Redirect::to("/posts\r\nSet-Cookie: session=stolen\r\n\r\n<html>").into_response();
+
+        assert_eq!(
+            res.headers().get_all("location").unwrap(),
+            ["/postsSet-Cookie: session=stolen<html>"]
+        );
+        assert!(res.headers().get("set-cookie").is_none());
+        assert_eq!(
+            Redirect::see_other("/next?to=\u{0}\u{7f}/home").location(),
+            "/next?to=/home"
+        );
+    }
+
+    #[test]
+    fn back_follows_relative_referers() {
+        assert_eq!(back("/posts/create?draft=1"), "/posts/create?draft=1");
+    }
+
+    #[test]
+    fn back_follows_same_origin_referers() {
+        assert_eq!(back("https://acme.com/posts/create"), "/posts/create");
+        assert_eq!(back("http://ACME.com"), "/");
+        assert_eq!(back("https://acme.com?page=2"), "/?page=2");
+    }
+
+    #[test]
+    fn back_ignores_referers_to_other_sites() {
+        assert_eq!(back("https://evil.example/posts"), "/fallback");
+        assert_eq!(back("https://acme.com.evil.example/"), "/fallback");
+        assert_eq!(back("https://acme.com@evil.example/"), "/fallback");
+        assert_eq!(back("//evil.example/posts"), "/fallback");
+        assert_eq!(back("/\\evil.example/posts"), "/fallback");
+        assert_eq!(back("https://acme.com//evil.example/"), "/fallback");
+        assert_eq!(back("javascript:alert(1)"), "/fallback");
+    }
+
+    #[test]
+    fn back_uses_the_fallback_without_referer() {
+        let req = Request::builder().build();
+
+        assert_eq!(Redirect::back(&req, "/fallback").location(), "/fallback");
+    }
+}
@@ -1,22 +1,30 @@
 use std::{
-    io::{self, Read, Write},
+    io::{self, Read},
     net, num,
     panic::{catch_unwind, AssertUnwindSafe},
     rc::Rc,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
 };
 
-use rayon::ThreadPoolBuilder;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use crate::{
+    health::Health,
     http::{
+        error_page,
         handler::{trigger, Handler},
-        request::Request,
+        request::{announced_body_length, ParseRequestError, Request},
         response::IntoResponse,
         StatusCode,
     },
     middleware::MiddlewareHandler,
-    router::{Router, RouterError},
+    router::{Route, Router, RouterError},
     Context,
 };
 
@@ -27,6 +35,31 @@ pub struct Server {
     ctx: Context,
     global_middleware: Vec<MiddlewareHandler>,
     num_threads: Option<usize>,
+    timeout_pool: Arc<ThreadPool>,
+    stopping: Arc<AtomicBool>,
+}
+
+/// A handle to shut down a [Server].
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle {
+    health: Health,
+    stopping: Arc<AtomicBool>,
+    addr: Option<net::SocketAddr>,
+}
+
+impl ShutdownHandle {
+    /// Marks the server as shutting down and stops it from accepting connections. The server
+    /// finishes handling the connections it has already accepted, after which [Server::serve]
+    /// returns.
+    pub fn shut_down(&self) {
+        self.health.shut_down();
+        self.stopping.store(true, Ordering::SeqCst);
+
+        // wake up the server, which is waiting for the next connection
+        if let Some(addr) = self.addr {
+            let _ = net::TcpStream::connect(addr);
+        }
+    }
 }
 
 type ChainOperator = Rc<Box<dyn Fn(Request) -> Box<dyn IntoResponse>>>;
@@ -36,10 +69,11 @@ impl Server {
     pub fn try_bind(
         addr: impl net::ToSocketAddrs,
         router: Router,
-        ctx: Context,
+        mut ctx: Context,
         global_middleware: Vec<MiddlewareHandler>,
     ) -> io::Result<Self> {
         let listener = net::TcpListener::bind(addr)?;
+        ctx.routes = router.named_routes();
 
         Ok(Self {
             listener,
@@ -47,33 +81,148 @@ impl Server {
             router,
             ctx,
             global_middleware,
+            timeout_pool: build_timeout_pool(default_num_threads()),
+            stopping: Arc::default(),
         })
     }
 
     /// Starts serving incoming HTTP requests.
+    ///
+    /// Returns once the server has been shut down using its [ShutdownHandle] and all accepted
+    /// connections have been handled.
     pub fn serve(self) {
-        let num_threads = self.num_threads.unwrap_or(
-            std::thread::available_parallelism()
-                .map(num::NonZero::get)
-                .unwrap_or(4),
-        );
+        let num_threads = self.num_threads.unwrap_or_else(default_num_threads);
 
         let pool = ThreadPoolBuilder::new()
             .num_threads(num_threads)
             .build()
             .unwrap();
 
-        for connection in self.listener.incoming() {
-            match connection {
-                Ok(stream) => pool.install(|| self.handle_connection(stream)),
-                Err(e) => tracing::info!("client failed to connect: {e}"),
+        self.ctx
+            .metrics
+            .gauge(
+                "snx_threadpool_threads",
+                "Number of threads in the threadpool.",
+                &[],
+            )
+            .set(num_threads as i64);
+
+        let queue_depth = self.ctx.metrics.gauge(
+            "snx_threadpool_queue_depth",
+            "Number of accepted connections waiting for a thread.",
+            &[],
+        );
+
+        // the scope only ends once all connections spawned in it have been handled
+        let server = &self;
+        pool.in_place_scope(|scope| {
+            for connection in server.listener.incoming() {
+                if server.stopping.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                match connection {
+                    Ok(stream) => {
+                        // counted until a thread picks up the connection
+                        queue_depth.inc();
+
+                        let queue_depth = queue_depth.clone();
+                        scope.spawn(move |_| {
+                            queue_depth.dec();
+
+                            // panics are reported by the panic hook, the connection is closed
+                            // and the server keeps handling other connections
+                            let _ =
+                                catch_unwind(AssertUnwindSafe(|| server.handle_connection(stream)));
+                        })
+                    }
+                    Err(e) => tracing::info!("client failed to connect: {e}"),
+                }
+            }
+        });
+
+        tracing::info!("server stopped");
+    }
+
+    /// Gets a handle to shut down the server from another thread.
+    ///
+    /// ```no_run
+    /// use std::{thread, time::Duration};
+    ///
+    /// use snx::{router::Router, Config, Context, Server};
+    ///
+    /// let config = Config::try_from_fs().unwrap();
+    /// let router = Router::builder("localhost").build().unwrap();
+    /// let server = Server::try_bind("127.0.0.1:3000", router, Context::new(config), vec![]).unwrap();
+    ///
+    /// let handle = server.shutdown_handle();
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_secs(60));
+    ///     handle.shut_down();
+    /// });
+    ///
+    /// server.serve();
+    /// ```
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        let mut addr = self.listener.local_addr().ok();
+        if let Some(addr) = addr.as_mut().filter(|addr| addr.ip().is_unspecified()) {
+            addr.set_ip(match addr.ip() {
+                net::IpAddr::V4(_) => net::Ipv4Addr::LOCALHOST.into(),
+                net::IpAddr::V6(_) => net::Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+
+        ShutdownHandle {
+            health: self.ctx.health.clone(),
+            stopping: self.stopping.clone(),
+            addr,
+        }
+    }
+
+    /// Installs a handler for termination signals, which marks the server as shutting down and
+    /// shuts it down once the shutdown delay has passed. Until then requests are still handled,
+    /// so the readiness endpoint can report unready to orchestrators. A second signal exits the
+    /// process immediately.
+    #[cfg(feature = "signals")]
+    pub fn handle_shutdown_signals(self) -> Self {
+        let health = self.ctx.health.clone();
+        let handle = self.shutdown_handle();
+        let delay = self
+            .ctx
+            .config
+            .server
+            .shutdown_delay
+            .as_deref()
+            .and_then(|delay| crate::config::parse_duration(delay).ok())
+            .unwrap_or(DEFAULT_SHUTDOWN_DELAY);
+
+        let result = ctrlc::set_handler(move || {
+            if health.is_shutting_down() {
+                std::process::exit(0);
             }
+
+            tracing::info!("shutting down in {}s", delay.as_secs());
+            health.shut_down();
+
+            let handle = handle.clone();
+            thread::spawn(move || {
+                thread::sleep(delay);
+                handle.shut_down();
+            });
+        });
+
+        if let Err(e) = result {
+            tracing::warn!("failed to install shutdown signal handler: {e}");
         }
+
+        self
     }
 
-    /// Sets the number of threads to be used in the threadpool.
+    /// Sets the number of threads to be used in the threadpool, and in the separate threadpool
+    /// running handlers of routes with a timeout.
     pub fn num_threads(mut self, amount: usize) -> Self {
         self.num_threads = Some(amount);
+        self.timeout_pool = build_timeout_pool(amount);
 
         self
     }
@@ -83,96 +232,468 @@ impl Server {
     /// Reads data from the stream, parses it into a [Request], dispatches it to the router,
     /// executes the associated handler and writes a response back to the stream.
     fn handle_connection(&self, mut stream: net::TcpStream) {
-        let mut buffer = [0; 8192];
+        let max_body_size = self
+            .ctx
+            .config
+            .server
+            .max_body_size
+            .unwrap_or(DEFAULT_MAX_BODY_SIZE);
 
-        match stream.read(&mut buffer) {
-            Ok(bytes_read) => {
-                if bytes_read == 0 {
-                    return;
-                }
+        let into_response: Box<dyn IntoResponse> = match read_request(&mut stream, max_body_size) {
+            Ok(None) => return,
+            Ok(Some(mut request)) => {
+                let host = request.headers().get("host").unwrap_or_default();
+                match self.router.at(&request.method(), &host, &request.path()) {
+                    Ok(route) => {
+                        request.params = route.parameters;
+                        request.extensions_mut().insert(route.route.pattern());
 
-                let into_response: Box<dyn IntoResponse> =
-                    match Request::try_parse_from_bytes(&buffer, stream.peer_addr().ok()) {
-                        Ok(mut request) => {
-                            let host = request.headers().get("host").unwrap();
-                            match self.router.at(&request.method(), &host, &request.path()) {
-                                Ok(route) => {
-                                    request.params = route.parameters;
-
-                                    self.execute(
-                                        route.route.handler().clone(),
-                                        route.route.middleware().clone(),
-                                        request,
-                                    )
-                                }
-                                Err(RouterError::NotFound) => self.execute(
-                                    Arc::new(Box::new(|_, _| StatusCode::NotFound)),
-                                    vec![],
-                                    request,
-                                ),
-                                Err(RouterError::MethodNotAllowed) => self.execute(
-                                    Arc::new(Box::new(|_, _| StatusCode::MethodNotAllowed)),
-                                    vec![],
-                                    request,
-                                ),
+                        match route.route.timeout() {
+                            Some(timeout) => {
+                                self.execute_with_timeout(route.route, timeout, request)
                             }
+                            None => self.execute(
+                                route.route.handler().clone(),
+                                route.route.middleware().clone(),
+                                request,
+                            ),
                         }
-                        Err(e) => {
-                            tracing::warn!("could not parse request: {e}");
+                    }
+                    Err(RouterError::NotFound) => self.execute(
+                        Arc::new(Box::new(|_, _| StatusCode::NotFound)),
+                        vec![],
+                        request,
+                    ),
+                    Err(RouterError::MethodNotAllowed) => self.execute(
+                        Arc::new(Box::new(|_, _| StatusCode::MethodNotAllowed)),
+                        vec![],
+                        request,
+                    ),
+                }
+            }
+            Err(ReadRequestError::Parse(e)) => {
+                tracing::warn!("could not parse request: {e}");
 
-                            Box::new(StatusCode::BadRequest)
-                        }
-                    };
+                self.unparsed_error(StatusCode::BadRequest)
+            }
+            Err(ReadRequestError::TooLarge) => {
+                tracing::warn!("request exceeds the maximum body size of {max_body_size} bytes");
 
-                let _ = stream.write_all(
-                    &into_response
-                        .into_response()
-                        .serialize_to_raw_http_response(),
-                );
+                self.unparsed_error(StatusCode::ContentTooLarge)
             }
-            Err(e) => tracing::warn!("could not read from client: {e}"),
+            Err(ReadRequestError::Io(e)) => {
+                tracing::warn!("could not read from client: {e}");
+
+                return;
+            }
+        };
+
+        if let Err(e) = into_response.into_response().write_to(&mut stream) {
+            tracing::warn!("could not write response to client: {e}");
+
+            // the response may have been cut off, so the client must not wait for the rest of it
+            let _ = stream.shutdown(net::Shutdown::Both);
         }
     }
 
-    /// Executes the given handler with the given middleware.
+    /// Creates the response to a request that could not be parsed, which is rendered as problem
+    /// details if configured.
+    fn unparsed_error(&self, status: StatusCode) -> Box<dyn IntoResponse> {
+        #[cfg(feature = "json")]
+        if self.ctx.config.server.problem_details.unwrap_or_default() {
+            return Box::new(crate::Problem::new(status));
+        }
+
+        Box::new(status)
+    }
+
+    /// Executes the given handler with the given route middleware and the global middleware.
     fn execute(
         &self,
         handler: Arc<Box<dyn Handler + Send + Sync>>,
         middleware: Vec<MiddlewareHandler>,
         request: Request,
     ) -> Box<dyn IntoResponse> {
+        // route middleware and global middleware are both stored from innermost to outermost,
+        // global middleware wrap the route middleware.
         let mut middleware = middleware.clone();
-        let ctx = self.ctx.clone();
-        let mut chain: Vec<ChainOperator> = Vec::with_capacity(middleware.len() + 1);
+        middleware.extend_from_slice(&self.global_middleware);
 
-        // first, add the actual handler call to the chain (this will be called last)
-        chain.push(Rc::new(Box::new(move |request: Request| {
+        execute_chain(self.ctx.clone(), handler, middleware, request)
+    }
+
+    /// Executes the handler and middleware of a route with a timeout.
+    ///
+    /// The route's handler and middleware run on a separate threadpool, so the connection can be
+    /// answered once the timeout has passed. Global middleware wrap the timed part as usual. A
+    /// handler cannot be stopped once it is running, but it can check [Request::is_cancelled] to
+    /// stop early. The pool has as many threads as the server, so handlers that keep running
+    /// after their timeout delay other timed requests instead of piling up threads.
+    fn execute_with_timeout(
+        &self,
+        route: &Route,
+        timeout: Duration,
+        request: Request,
+    ) -> Box<dyn IntoResponse> {
+        let handler = route.handler().clone();
+        let middleware = route.middleware().clone();
+        let description = format!("{} {}", route.method(), route.path());
+        let pool = self.timeout_pool.clone();
+        let status = self
+            .ctx
+            .config
+            .server
+            .timeout_status
+            .and_then(|status| StatusCode::try_from(status).ok())
+            .unwrap_or(StatusCode::ServiceUnavailable);
+
+        let timed = move |ctx: Context, request: Request| {
+            let (tx, rx) = mpsc::channel();
+            let (handler, middleware) = (handler.clone(), middleware.clone());
+            let req = request.clone();
+            let span = tracing::Span::current();
+
+            pool.spawn(move || {
+                let _guard = span.enter();
+                let res = execute_chain(ctx, handler, middleware, req).into_response();
+                let _ = tx.send(res);
+            });
+
+            match rx.recv_timeout(timeout) {
+                Ok(res) => res,
+                Err(RecvTimeoutError::Timeout) => {
+                    request.cancel();
+                    tracing::warn!(
+                        "request \"{} {}\" to route \"{description}\" exceeded its timeout of {}ms",
+                        request.method(),
+                        request.path(),
+                        timeout.as_millis()
+                    );
+
+                    status.clone().into_response()
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    StatusCode::InternalServerError.into_response()
+                }
+            }
+        };
+
+        self.execute(Arc::new(Box::new(timed)), vec![], request)
+    }
+}
+
+/// Executes the given handler wrapped by the given middleware, which are ordered from innermost
+/// to outermost.
+fn execute_chain(
+    ctx: Context,
+    handler: Arc<Box<dyn Handler + Send + Sync>>,
+    middleware: Vec<MiddlewareHandler>,
+    request: Request,
+) -> Box<dyn IntoResponse> {
+    let mut chain: Vec<ChainOperator> = Vec::with_capacity(middleware.len() + 1);
+
+    // first, add the actual handler call to the chain (this will be called last)
+    chain.push(Rc::new(Box::new({
+        let ctx = ctx.clone();
+        move |request: Request| {
             catch_unwind(AssertUnwindSafe(|| {
                 trigger(ctx.clone(), request.clone(), handler.clone())
             }))
-            .unwrap_or(Box::new(StatusCode::InternalServerError))
+            .unwrap_or_else(|payload| Box::new(error_page::respond(&ctx, &request, payload)))
+        }
+    })));
+
+    // second, loop over all middleware and add them from innermost to outermost
+    for handler in middleware {
+        let ctx = ctx.clone();
+        let op = chain.last().unwrap().clone();
+
+        chain.push(Rc::new(Box::new(move |request: Request| {
+            (handler)(
+                ctx.clone(),
+                request.clone(),
+                Box::new({
+                    let value = op.clone();
+                    move |req| value(req).into_response()
+                }),
+            )
         })));
+    }
 
-        // second, loop over all middleware for route (and global middleware) and add in reverse
-        // order.
-        middleware.extend_from_slice(&self.global_middleware);
-        for handler in middleware {
-            let ctx = self.ctx.clone();
-            let op = chain.last().unwrap().clone();
-
-            chain.push(Rc::new(Box::new(move |request: Request| {
-                (handler)(
-                    ctx.clone(),
-                    request.clone(),
-                    Box::new({
-                        let value = op.clone();
-                        move |req| value(req).into_response()
-                    }),
-                )
-            })));
+    // last, kick off the chain by calling the end of it (the outermost middleware)
+    chain.last().unwrap()(request)
+}
+
+/// The maximum size of the head (request line and headers) of a request in bytes.
+const MAX_HEAD_SIZE: usize = 8192;
+
+/// The default maximum size of the body of a request in bytes.
+pub(crate) const DEFAULT_MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+/// Gets the default number of threads of the threadpools.
+fn default_num_threads() -> usize {
+    thread::available_parallelism()
+        .map(num::NonZero::get)
+        .unwrap_or(4)
+}
+
+/// Builds the threadpool running handlers of routes with a timeout.
+fn build_timeout_pool(num_threads: usize) -> Arc<ThreadPool> {
+    Arc::new(
+        ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("snx-timeout-{i}"))
+            // panics are reported by the panic hook and answered with a 500, since the handler's
+            // channel is dropped
+            .panic_handler(|_| {})
+            .build()
+            .unwrap(),
+    )
+}
+
+/// The default time between receiving a termination signal and shutting down.
+#[cfg(feature = "signals")]
+const DEFAULT_SHUTDOWN_DELAY: Duration = Duration::from_secs(5);
+
+/// Represents an error that occurred while reading a request from a connection.
+#[derive(thiserror::Error, Debug)]
+enum ReadRequestError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Parse(#[from] ParseRequestError),
+    #[error("request is too large")]
+    TooLarge,
+}
+
+/// Reads from the stream until a complete request has been received.
+///
+/// Returns `Ok(None)` if the client closed the connection without sending any data.
+fn read_request(
+    stream: &mut net::TcpStream,
+    max_body_size: usize,
+) -> Result<Option<Request>, ReadRequestError> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 8192];
+
+    loop {
+        let bytes_read = stream.read(&mut chunk)?;
+        if bytes_read == 0 {
+            return match buffer.is_empty() {
+                true => Ok(None),
+                false => Err(ParseRequestError::Partial.into()),
+            };
+        }
+
+        buffer.extend_from_slice(&chunk[..bytes_read]);
+        if buffer.len() > MAX_HEAD_SIZE + max_body_size {
+            return Err(ReadRequestError::TooLarge);
+        }
+
+        // reject requests announcing a body that is too large before buffering it
+        if announced_body_length(&buffer)?.is_some_and(|length| length > max_body_size) {
+            return Err(ReadRequestError::TooLarge);
+        }
+
+        match Request::try_parse_from_bytes(&buffer, stream.peer_addr().ok()) {
+            Err(ParseRequestError::Partial) => continue,
+            result => return Ok(Some(result?)),
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::{middleware::tagging_middleware, request::Request, response::Response, Config};
+
+    tagging_middleware!(route);
+    tagging_middleware!(first);
+    tagging_middleware!(last);
+
+    /// Connects a client to a listener, returning the client and the accepted server side.
+    fn connection() -> (net::TcpStream, net::TcpStream) {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        (client, server)
+    }
+
+    #[test]
+    fn global_middleware_wrap_route_middleware_in_declaration_order() {
+        let server = Server::try_bind(
+            "127.0.0.1:0",
+            Router::builder("localhost").build().unwrap(),
+            Context::new(Config::for_tests("")),
+            vec![Arc::new(Box::new(first)), Arc::new(Box::new(last))],
+        )
+        .unwrap();
+
+        let res = server
+            .execute(
+                Arc::new(Box::new(|_, _| "hello")),
+                vec![Arc::new(Box::new(route))],
+                Request::builder().build(),
+            )
+            .into_response();
+
+        // every middleware appends its name after calling the next one, so the outermost one
+        // comes last
+        assert_eq!(
+            res.headers().get_all("x-middleware").unwrap(),
+            ["route", "first", "last"]
+        );
+    }
+
+    #[test]
+    fn rejects_too_large_bodies_before_receiving_them() {
+        let (mut client, mut server) = connection();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nContent-Length: 1048576\r\n\r\n")
+            .unwrap();
+
+        let result = read_request(&mut server, 1024);
+
+        assert!(matches!(result, Err(ReadRequestError::TooLarge)));
+    }
+
+    #[test]
+    fn reads_requests_within_the_body_limit() {
+        let (mut client, mut server) = connection();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello")
+            .unwrap();
+
+        let request = read_request(&mut server, 1024).unwrap().unwrap();
+
+        assert_eq!(request.bytes(), b"hello");
+    }
+
+    fn timed_server(config: &str, num_threads: usize) -> Server {
+        Server::try_bind(
+            "127.0.0.1:0",
+            Router::builder("localhost").build().unwrap(),
+            Context::new(Config::for_tests(config)),
+            vec![],
+        )
+        .unwrap()
+        .num_threads(num_threads)
+    }
+
+    fn timed_request(server: &Server, path: &str) -> Response {
+        let router = Router::builder("localhost")
+            .timeout(Duration::from_millis(100), |router| {
+                router
+                    .get("/fast", |_, _| "fast")
+                    .get("/slow", |_, _| {
+                        thread::sleep(Duration::from_millis(500));
+                        "slow"
+                    })
+                    .get("/panic", |_, _| -> &str { panic!("boom") })
+            })
+            .build()
+            .unwrap();
+        let route = router.at(&crate::Method::Get, "localhost", path).unwrap();
+
+        server
+            .execute_with_timeout(
+                route.route,
+                route.route.timeout().unwrap(),
+                Request::builder().path(path).build(),
+            )
+            .into_response()
+    }
+
+    #[test]
+    fn answers_requests_within_their_timeout() {
+        let res = timed_request(&timed_server("", 2), "/fast");
+
+        assert_eq!(*res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn answers_requests_exceeding_their_timeout_with_the_configured_status() {
+        assert_eq!(
+            *timed_request(&timed_server("", 2), "/slow").status(),
+            StatusCode::ServiceUnavailable
+        );
+        assert_eq!(
+            *timed_request(&timed_server("timeout_status = 504\n", 2), "/slow").status(),
+            StatusCode::GatewayTimeout
+        );
+    }
+
+    #[test]
+    fn answers_panicking_timed_handlers_with_500() {
+        let res = timed_request(&timed_server("", 2), "/panic");
+
+        assert_eq!(*res.status(), StatusCode::InternalServerError);
+    }
+
+    #[test]
+    fn runs_timed_handlers_on_a_bounded_pool() {
+        let server = timed_server("", 1);
+
+        // the slow handler keeps the only thread busy after its timeout, so the next request
+        // waits for it instead of spawning another thread
+        assert_eq!(
+            *timed_request(&server, "/slow").status(),
+            StatusCode::ServiceUnavailable
+        );
+        assert_eq!(
+            *timed_request(&server, "/fast").status(),
+            StatusCode::ServiceUnavailable
+        );
+    }
+
+    #[test]
+    fn rejects_other_timeout_statuses_when_the_config_loads() {
+        let config = Config::for_tests("timeout_status = 500\n");
+
+        assert!(matches!(
+            config.validate(),
+            Err(crate::config::InvalidConfigError::TimeoutStatus(500))
+        ));
+        assert!(Config::for_tests("timeout_status = 504\n")
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn finishes_accepted_connections_when_shutting_down() {
+        let router = Router::builder("localhost")
+            .get("/slow", |_, _| {
+                thread::sleep(Duration::from_millis(300));
+                "slow"
+            })
+            .build()
+            .unwrap();
+        let ctx = Context::new(Config::for_tests(""));
+        let health = ctx.health.clone();
+        let server = Server::try_bind("127.0.0.1:0", router, ctx, vec![])
+            .unwrap()
+            .num_threads(2);
+        let addr = server.listener.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let serving = thread::spawn(move || server.serve());
+
+        let mut client = net::TcpStream::connect(addr).unwrap();
+        client
+            .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        handle.shut_down();
+        serving.join().unwrap();
 
-        // last, kick off the chain by calling the end of it (the outermost middleware)
-        chain.last().unwrap()(request)
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("slow"));
+        assert!(health.is_shutting_down());
+        assert!(net::TcpStream::connect(addr).is_err());
     }
 }
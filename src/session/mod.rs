@@ -1,11 +1,26 @@
 mod memory;
 
-use std::{collections::HashMap, sync::Arc, sync::Mutex};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
 
 use jiff::Zoned;
 pub use memory::MemorySessionStore;
 use rand::Rng;
-use serde::{de::DeserializeOwned, ser::Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Key of the session value containing the flashed values.
+const FLASH_KEY: &str = "_flash";
+
+/// The flashed values of a session.
+#[derive(Serialize, Deserialize, Default)]
+struct Flash {
+    /// Values flashed during the previous request, which are available during this request.
+    current: HashMap<String, serde_json::Value>,
+    /// Values flashed during this request, which are available during the next request.
+    next: HashMap<String, serde_json::Value>,
+}
 
 /// A session is a way to store information across requests and associated with
 /// visitors.
@@ -45,15 +60,123 @@ impl Session {
     pub fn insert(&mut self, key: &str, value: impl Serialize) -> Result<(), Error> {
         self.data
             .insert(key.to_string(), serde_json::to_value(&value)?);
-        self.store.try_lock().unwrap().save(self)?;
+        self.lock_store()?.save(self)?;
 
         Ok(())
     }
 
+    /// Flashes a value into the session, which is available during the next request only. It can
+    /// be read with [Session::get_flash] or read and cleared with [Session::take_flash].
+    ///
+    /// ```no_run
+    /// use snx::{request::Request, Context, Redirect};
+    ///
+    /// fn store(_: Context, req: Request) -> Redirect {
+    ///     let mut session = req.session.clone().unwrap();
+    ///     session.flash("status", "Post created!").unwrap();
+    ///
+    ///     Redirect::see_other("/posts")
+    /// }
+    ///
+    /// fn index(_: Context, req: Request) -> String {
+    ///     let mut session = req.session.clone().unwrap();
+    ///
+    ///     session
+    ///         .take_flash::<String>("status")
+    ///         .unwrap()
+    ///         .unwrap_or_default()
+    /// }
+    /// ```
+    pub fn flash(&mut self, key: &str, value: impl Serialize) -> Result<(), Error> {
+        let value = serde_json::to_value(&value)?;
+
+        self.update_flash(|flash| {
+            flash.next.insert(key.to_string(), value);
+        })
+    }
+
+    /// Gets a flashed value without clearing it. Values flashed during the current request are
+    /// available as well.
+    pub fn get_flash<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, Error> {
+        let flash = self.flash_data()?;
+
+        Ok(flash
+            .next
+            .get(key)
+            .or(flash.current.get(key))
+            .cloned()
+            .map(serde_json::from_value)
+            .transpose()?)
+    }
+
+    /// Gets a flashed value and clears it, so it is not available for the rest of the request.
+    pub fn take_flash<T: DeserializeOwned>(&mut self, key: &str) -> Result<Option<T>, Error> {
+        let value =
+            self.update_flash(|flash| flash.next.remove(key).or(flash.current.remove(key)))?;
+
+        Ok(value.map(serde_json::from_value).transpose()?)
+    }
+
+    /// Keeps the flashed values of the current request for one more request.
+    pub fn reflash(&mut self) -> Result<(), Error> {
+        self.update_flash(|flash| {
+            for (key, value) in std::mem::take(&mut flash.current) {
+                flash.next.entry(key).or_insert(value);
+            }
+        })
+    }
+
+    /// Makes the values flashed during the previous request available during this request and
+    /// discards the ones flashed before that. Called once per request when the session is loaded.
+    pub(crate) fn age_flash(&mut self) -> Result<(), Error> {
+        if !self.data.contains_key(FLASH_KEY) {
+            return Ok(());
+        }
+
+        self.update_flash(|flash| flash.current = std::mem::take(&mut flash.next))
+    }
+
+    /// Discards the flashed values of this copy of the session, e.g. if they could not be aged.
+    pub(crate) fn discard_flash(&mut self) {
+        self.data.remove(FLASH_KEY);
+    }
+
+    /// Updates the flashed values while holding the lock on the store. The session data is
+    /// reloaded first, so changes made through other copies of the session, e.g. by another
+    /// request of the same visitor, are not overwritten.
+    fn update_flash<R>(&mut self, update: impl FnOnce(&mut Flash) -> R) -> Result<R, Error> {
+        let mut store = self.store.lock().map_err(|_| Error::Poisoned)?;
+
+        if let Some(stored) = store.load(self.id)? {
+            self.data = stored.data;
+        }
+
+        let mut flash = self.flash_data()?;
+        let result = update(&mut flash);
+        match flash.current.is_empty() && flash.next.is_empty() {
+            true => self.data.remove(FLASH_KEY),
+            false => self
+                .data
+                .insert(FLASH_KEY.to_string(), serde_json::to_value(flash)?),
+        };
+        store.save(self)?;
+
+        Ok(result)
+    }
+
+    /// Locks the store, failing if another thread panicked while holding the lock.
+    fn lock_store(&self) -> Result<MutexGuard<'_, Box<dyn SessionStore + Send + Sync>>, Error> {
+        self.store.lock().map_err(|_| Error::Poisoned)
+    }
+
+    fn flash_data(&self) -> Result<Flash, Error> {
+        Ok(self.get::<Flash>(FLASH_KEY)?.unwrap_or_default())
+    }
+
     /// Removes a value from the session data.
     pub fn remove(&mut self, key: &str) -> Result<(), Error> {
         self.data.remove(key).unwrap();
-        self.store.try_lock().unwrap().save(self)?;
+        self.lock_store()?.save(self)?;
 
         Ok(())
     }
@@ -71,11 +194,142 @@ pub trait SessionStore {
 
     /// Deletes a session from the store using the given id.
     fn delete(&mut self, id: u128) -> Result<(), Error>;
+
+    /// Checks whether the store is reachable, which is used by the readiness check.
+    ///
+    /// Defaults to loading a session that does not exist, stores backed by a server should
+    /// override this with a cheaper request if they have one.
+    fn ping(&mut self) -> Result<(), Error> {
+        self.load(0).map(|_| ())
+    }
 }
 
 /// Represents an error that occurred during session management.
 #[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     #[error(transparent)]
     SerdeJson(#[from] serde_json::Error),
+    #[error("session store lock is poisoned")]
+    Poisoned,
+}
+
+/// Creates a session that expires in an hour, stored in the given store.
+#[cfg(test)]
+pub(crate) fn test_session(
+    store: impl SessionStore + Send + Sync + 'static,
+) -> (Session, Arc<Mutex<Box<dyn SessionStore + Send + Sync>>>) {
+    let store: Arc<Mutex<Box<dyn SessionStore + Send + Sync>>> =
+        Arc::new(Mutex::new(Box::new(store)));
+    let session = Session::new(
+        Zoned::now()
+            .checked_add(jiff::Span::new().hours(1))
+            .unwrap(),
+        store.clone(),
+    );
+    store.lock().unwrap().create(session.clone()).unwrap();
+
+    (session, store)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    type Store = Arc<Mutex<Box<dyn SessionStore + Send + Sync>>>;
+
+    fn session() -> (Session, Store) {
+        test_session(MemorySessionStore::default())
+    }
+
+    /// Loads the session like a new request of the same visitor would.
+    fn next_request(session: &Session, store: &Store) -> Session {
+        let mut session = store.lock().unwrap().load(session.id).unwrap().unwrap();
+        session.age_flash().unwrap();
+
+        session
+    }
+
+    #[test]
+    fn keeps_flashed_values_for_the_next_request_only() {
+        let (mut session, store) = session();
+        session.flash("status", "Post created!").unwrap();
+        assert_eq!(
+            session.get_flash::<String>("status").unwrap().as_deref(),
+            Some("Post created!")
+        );
+
+        let session = next_request(&session, &store);
+        assert_eq!(
+            session.get_flash::<String>("status").unwrap().as_deref(),
+            Some("Post created!")
+        );
+
+        let session = next_request(&session, &store);
+        assert_eq!(session.get_flash::<String>("status").unwrap(), None);
+        assert!(!session.data.contains_key(FLASH_KEY));
+    }
+
+    #[test]
+    fn takes_and_reflashes_values() {
+        let (mut session, store) = session();
+        session.flash("status", "Post created!").unwrap();
+        session.flash("count", 3).unwrap();
+
+        let mut session = next_request(&session, &store);
+        assert_eq!(session.take_flash::<u32>("count").unwrap(), Some(3));
+        assert_eq!(session.take_flash::<u32>("count").unwrap(), None);
+        session.reflash().unwrap();
+
+        let session = next_request(&session, &store);
+        assert_eq!(
+            session.get_flash::<String>("status").unwrap().as_deref(),
+            Some("Post created!")
+        );
+        assert_eq!(session.get_flash::<u32>("count").unwrap(), None);
+    }
+
+    #[test]
+    fn keeps_values_flashed_concurrently_through_other_copies() {
+        let (session, store) = session();
+
+        let handles = (0..8)
+            .map(|i| {
+                let mut session = session.clone();
+                thread::spawn(move || {
+                    for j in 0..10 {
+                        session.flash(&format!("{i}-{j}"), j).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let session = next_request(&session, &store);
+        for i in 0..8 {
+            for j in 0..10 {
+                assert_eq!(
+                    session.get_flash::<u32>(&format!("{i}-{j}")).unwrap(),
+                    Some(j)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fails_if_the_store_lock_is_poisoned() {
+        let (mut session, store) = session();
+        let _ = thread::spawn(move || {
+            let _guard = store.lock().unwrap();
+            panic!("poisoning the store lock");
+        })
+        .join();
+
+        assert!(matches!(session.insert("key", 1), Err(Error::Poisoned)));
+        assert!(matches!(session.flash("key", 1), Err(Error::Poisoned)));
+    }
 }
@@ -0,0 +1,633 @@
+//! Validation of request payloads.
+//!
+//! Payloads implement [Validate] by applying rules to their fields with a [Validator]. Request
+//! bodies are deserialized and validated at once with [crate::request::Request::validated_json]
+//! and [crate::request::Request::validated_form].
+//!
+//! There is no derive macro for [Validate] yet. snx is a single crate without procedural macros
+//! and a derive would require a separate proc-macro crate, so for now rules are applied by
+//! implementing the trait by hand, which keeps them plain Rust that can be read and debugged like
+//! any other code.
+//!
+//! ```
+//! use snx::validation::{Validate, ValidationErrors, Validator};
+//!
+//! struct Author {
+//!     name: String,
+//! }
+//!
+//! impl Validate for Author {
+//!     fn validate(&self) -> Result<(), ValidationErrors> {
+//!         Validator::new().required("name", &self.name).finish()
+//!     }
+//! }
+//!
+//! struct PostPayload {
+//!     title: String,
+//!     rating: u8,
+//!     source: Option<String>,
+//!     author: Author,
+//! }
+//!
+//! impl Validate for PostPayload {
+//!     fn validate(&self) -> Result<(), ValidationErrors> {
+//!         Validator::new()
+//!             .required("title", &self.title)
+//!             .length("title", &self.title, 3..=100)
+//!             .range("rating", &self.rating, 1..=5)
+//!             .url("source", &self.source)
+//!             .nested("author", &self.author)
+//!             .finish()
+//!     }
+//! }
+//!
+//! let payload = PostPayload {
+//!     title: "hi".to_string(),
+//!     rating: 6,
+//!     source: None,
+//!     author: Author { name: "".to_string() },
+//! };
+//!
+//! let errors = payload.validate().unwrap_err();
+//!
+//! assert_eq!(
+//!     errors.get("title").unwrap(),
+//!     ["The title field must be between 3 and 100 characters."]
+//! );
+//! assert_eq!(
+//!     errors.get("rating").unwrap(),
+//!     ["The rating field must be between 1 and 5."]
+//! );
+//! assert_eq!(
+//!     errors.get("author.name").unwrap(),
+//!     ["The author.name field is required."]
+//! );
+//! ```
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::{Bound, RangeBounds},
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::response::{IntoResponse, Response};
+
+/// Key of the flashed value containing the errors of the last invalid form submission.
+#[cfg(feature = "sessions")]
+pub(crate) const ERRORS_FLASH_KEY: &str = "_errors";
+
+/// Key of the flashed value containing the input of the last invalid form submission.
+#[cfg(feature = "sessions")]
+pub(crate) const OLD_INPUT_FLASH_KEY: &str = "_old_input";
+
+/// A type whose values can be validated.
+pub trait Validate {
+    /// Validates the value, returning the error messages per field if it is invalid.
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// The error messages of an invalid value, keyed by field. Nested fields are separated by dots,
+/// e.g. `author.name`.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+#[serde(transparent)]
+pub struct ValidationErrors(BTreeMap<String, Vec<String>>);
+
+impl ValidationErrors {
+    /// Creates an empty set of errors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an error message to a field.
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0
+            .entry(field.to_string())
+            .or_default()
+            .push(message.into());
+    }
+
+    /// Gets the error messages of a field.
+    pub fn get(&self, field: &str) -> Option<&[String]> {
+        self.0.get(field).map(Vec::as_slice)
+    }
+
+    /// Returns whether a field has errors.
+    pub fn has(&self, field: &str) -> bool {
+        self.0.contains_key(field)
+    }
+
+    /// Returns whether there are no errors.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over the fields and their error messages.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.0
+            .iter()
+            .map(|(field, messages)| (field.as_str(), messages.as_slice()))
+    }
+
+    /// Renders the errors as a JSON object of fields to arrays of messages.
+    #[cfg(feature = "json")]
+    pub(crate) fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize validation errors into json")
+    }
+
+    /// Renders the errors as a JSON object of fields to arrays of messages.
+    #[cfg(not(feature = "json"))]
+    pub(crate) fn to_json(&self) -> String {
+        let fields = self
+            .iter()
+            .map(|(field, messages)| {
+                let messages = messages
+                    .iter()
+                    .map(|message| format!("\"{}\"", crate::http::escape_json(message)))
+                    .collect::<Vec<_>>();
+
+                format!(
+                    "\"{}\":[{}]",
+                    crate::http::escape_json(field),
+                    messages.join(",")
+                )
+            })
+            .collect::<Vec<_>>();
+
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages = self
+            .iter()
+            .flat_map(|(_, messages)| messages.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+
+        f.write_str(&messages.join(" "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Responds with `422 Unprocessable Content` and the errors per field as JSON, e.g.
+/// `{"message":"The given data was invalid.","errors":{"title":["The title field is required."]}}`.
+impl IntoResponse for ValidationErrors {
+    fn into_response(self) -> Response {
+        crate::Error::from(self).into_response()
+    }
+}
+
+/// Gets the errors of the last invalid form submission, which were flashed into the session by
+/// [crate::request::Request::validated_form] or [crate::Redirect::with_errors].
+#[cfg(feature = "sessions")]
+pub fn errors(req: &crate::request::Request) -> ValidationErrors {
+    req.session
+        .as_ref()
+        .and_then(|session| session.get_flash(ERRORS_FLASH_KEY).ok().flatten())
+        .unwrap_or_default()
+}
+
+/// Gets the value of a field of the last invalid form submission, so the form can be filled in
+/// again. The value still has to be escaped before rendering it into HTML.
+///
+/// ```
+/// use snx::{request::Request, validation};
+///
+/// let request = Request::builder().build();
+///
+/// assert_eq!(validation::old(&request, "title"), None);
+/// assert!(validation::errors(&request).is_empty());
+/// ```
+#[cfg(feature = "sessions")]
+pub fn old(req: &crate::request::Request, field: &str) -> Option<String> {
+    req.session
+        .as_ref()
+        .and_then(|session| {
+            session
+                .get_flash::<std::collections::HashMap<String, String>>(OLD_INPUT_FLASH_KEY)
+                .ok()
+                .flatten()
+        })
+        .and_then(|mut input| input.remove(field))
+}
+
+/// Gets the input of a URL-encoded form submission that can be flashed into the session, which
+/// excludes passwords and the CSRF token.
+#[cfg(all(feature = "sessions", feature = "forms"))]
+pub(crate) fn flashable_input(
+    req: &crate::request::Request,
+) -> std::collections::HashMap<String, String> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(req.bytes())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(field, _)| field != crate::csrf::FIELD && !field.contains("password"))
+        .collect()
+}
+
+/// A value that can be required to be present.
+pub trait Presence {
+    /// Returns whether the value is present, i.e. not missing, blank or empty.
+    fn is_present(&self) -> bool;
+}
+
+impl Presence for String {
+    fn is_present(&self) -> bool {
+        !self.trim().is_empty()
+    }
+}
+
+impl Presence for &str {
+    fn is_present(&self) -> bool {
+        !self.trim().is_empty()
+    }
+}
+
+impl<T> Presence for Vec<T> {
+    fn is_present(&self) -> bool {
+        !self.is_empty()
+    }
+}
+
+impl<T: Presence> Presence for Option<T> {
+    fn is_present(&self) -> bool {
+        self.as_ref().is_some_and(Presence::is_present)
+    }
+}
+
+/// A value that text rules like [Validator::email] can be applied to.
+pub trait Text {
+    /// Gets the text of the value, or `None` if it is missing.
+    fn text(&self) -> Option<&str>;
+}
+
+impl Text for String {
+    fn text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl Text for &str {
+    fn text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl<T: Text> Text for Option<T> {
+    fn text(&self) -> Option<&str> {
+        self.as_ref().and_then(Text::text)
+    }
+}
+
+/// Applies rules to the fields of a value and collects the error messages of the rules that
+/// failed.
+///
+/// Rules other than [Validator::required] skip missing and empty values, so optional fields are
+/// only validated if they are filled in.
+#[derive(Default, Debug)]
+pub struct Validator {
+    errors: ValidationErrors,
+}
+
+impl Validator {
+    /// Creates a validator without errors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires a field to be present, i.e. not missing, blank or empty.
+    pub fn required(self, field: &str, value: &impl Presence) -> Self {
+        self.check(field, value.is_present(), "is required")
+    }
+
+    /// Requires the amount of characters of a text field to be within a range.
+    pub fn length(self, field: &str, value: &impl Text, range: impl RangeBounds<usize>) -> Self {
+        let Some(text) = filled(value) else {
+            return self;
+        };
+
+        let message = format!("must be {} characters", describe(&range));
+        self.check(field, range.contains(&text.chars().count()), &message)
+    }
+
+    /// Requires a field to be within a range.
+    pub fn range<T: PartialOrd + fmt::Display>(
+        self,
+        field: &str,
+        value: &T,
+        range: impl RangeBounds<T>,
+    ) -> Self {
+        let message = format!("must be {}", describe(&range));
+        self.check(field, range.contains(value), &message)
+    }
+
+    /// Requires a text field to be an email address.
+    pub fn email(self, field: &str, value: &impl Text) -> Self {
+        let Some(text) = filled(value) else {
+            return self;
+        };
+
+        self.check(field, is_email(text), "must be a valid email address")
+    }
+
+    /// Requires a text field to be an HTTP or HTTPS URL.
+    pub fn url(self, field: &str, value: &impl Text) -> Self {
+        let Some(text) = filled(value) else {
+            return self;
+        };
+
+        self.check(field, is_url(text), "must be a valid URL")
+    }
+
+    /// Requires a text field to match a regular expression.
+    pub fn regex(self, field: &str, value: &impl Text, regex: &Regex) -> Self {
+        let Some(text) = filled(value) else {
+            return self;
+        };
+
+        self.check(field, regex.is_match(text), "format is invalid")
+    }
+
+    /// Applies a custom rule, which returns the error message if the field is invalid.
+    ///
+    /// ```
+    /// use snx::validation::Validator;
+    ///
+    /// let password = "hunter2";
+    /// let confirmation = "hunter3";
+    ///
+    /// let errors = Validator::new()
+    ///     .custom("password", || match password == confirmation {
+    ///         true => Ok(()),
+    ///         false => Err("The password confirmation does not match.".to_string()),
+    ///     })
+    ///     .finish()
+    ///     .unwrap_err();
+    ///
+    /// assert!(errors.has("password"));
+    /// ```
+    pub fn custom(mut self, field: &str, rule: impl FnOnce() -> Result<(), String>) -> Self {
+        if let Err(message) = rule() {
+            self.errors.add(field, message);
+        }
+
+        self
+    }
+
+    /// Validates a nested value, prefixing the fields of its errors with the name of the field.
+    pub fn nested(mut self, field: &str, value: &impl Validate) -> Self {
+        if let Err(errors) = value.validate() {
+            for (nested, messages) in errors.0 {
+                for message in messages {
+                    let message = message.replacen(
+                        &format!("The {nested} field"),
+                        &format!("The {field}.{nested} field"),
+                        1,
+                    );
+                    self.errors.add(&format!("{field}.{nested}"), message);
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Finishes validation, returning the collected errors if any rule failed.
+    pub fn finish(self) -> Result<(), ValidationErrors> {
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(self.errors),
+        }
+    }
+
+    fn check(mut self, field: &str, valid: bool, message: &str) -> Self {
+        if !valid {
+            self.errors
+                .add(field, format!("The {field} field {message}."));
+        }
+
+        self
+    }
+}
+
+/// Gets the text of a value if it is not missing or empty.
+fn filled(value: &impl Text) -> Option<&str> {
+    value.text().filter(|text| !text.is_empty())
+}
+
+/// Describes a range for error messages, e.g. "between 1 and 5".
+fn describe<T: fmt::Display>(range: &impl RangeBounds<T>) -> String {
+    match (range.start_bound(), range.end_bound()) {
+        (Bound::Included(min), Bound::Included(max)) => format!("between {min} and {max}"),
+        (Bound::Included(min), Bound::Excluded(max)) => format!("at least {min} and below {max}"),
+        (Bound::Included(min), Bound::Unbounded) => format!("at least {min}"),
+        (Bound::Excluded(min), Bound::Unbounded) => format!("greater than {min}"),
+        (Bound::Unbounded, Bound::Included(max)) => format!("at most {max}"),
+        (Bound::Unbounded, Bound::Excluded(max)) => format!("less than {max}"),
+        (Bound::Excluded(min), Bound::Included(max)) => {
+            format!("greater than {min} and at most {max}")
+        }
+        (Bound::Excluded(min), Bound::Excluded(max)) => {
+            format!("greater than {min} and less than {max}")
+        }
+        (Bound::Unbounded, Bound::Unbounded) => "anything".to_string(),
+    }
+}
+
+/// Returns whether a value looks like an email address, e.g. `user@example.com`.
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.rsplit_once('@') else {
+        return false;
+    };
+
+    !local.is_empty()
+        && local.len() <= 64
+        && domain.contains('.')
+        && !value.chars().any(|c| c.is_whitespace() || c.is_control())
+        && domain
+            .split('.')
+            .all(|label| !label.is_empty() && !label.starts_with('-') && !label.ends_with('-'))
+}
+
+/// Returns whether a value is an HTTP or HTTPS URL with a host.
+fn is_url(value: &str) -> bool {
+    let Some(rest) = value
+        .strip_prefix("https://")
+        .or_else(|| value.strip_prefix("http://"))
+    else {
+        return false;
+    };
+
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = host.split(':').next().unwrap_or_default();
+
+    !host.is_empty()
+        && !host.starts_with('.')
+        && !host.ends_with('.')
+        && !value.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Author {
+        name: String,
+    }
+
+    impl Validate for Author {
+        fn validate(&self) -> Result<(), ValidationErrors> {
+            Validator::new().required("name", &self.name).finish()
+        }
+    }
+
+    #[test]
+    fn requires_present_values() {
+        let errors = Validator::new()
+            .required("title", &"  ")
+            .required("tags", &Vec::<String>::new())
+            .required("source", &None::<String>)
+            .required("body", &"hello")
+            .finish()
+            .unwrap_err();
+
+        assert!(errors.has("title") && errors.has("tags") && errors.has("source"));
+        assert!(!errors.has("body"));
+    }
+
+    #[test]
+    fn skips_missing_and_empty_values_for_other_rules() {
+        let result = Validator::new()
+            .length("title", &"", 3..=100)
+            .email("email", &None::<String>)
+            .url("source", &"")
+            .finish();
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn counts_characters_instead_of_bytes() {
+        assert!(Validator::new()
+            .length("name", &"Zoë", 3..=3)
+            .finish()
+            .is_ok());
+        assert_eq!(
+            Validator::new()
+                .length("name", &"Zoë", 4..)
+                .finish()
+                .unwrap_err()
+                .get("name")
+                .unwrap(),
+            ["The name field must be at least 4 characters."]
+        );
+    }
+
+    #[test]
+    fn describes_ranges() {
+        let message = |range: std::ops::Range<u8>| {
+            Validator::new()
+                .range("rating", &9, range)
+                .finish()
+                .unwrap_err()
+                .get("rating")
+                .unwrap()[0]
+                .clone()
+        };
+
+        assert_eq!(
+            message(1..6),
+            "The rating field must be at least 1 and below 6."
+        );
+        assert!(Validator::new().range("rating", &5, ..=5).finish().is_ok());
+    }
+
+    #[test]
+    fn validates_emails() {
+        for valid in ["user@example.com", "first.last+tag@mail.example.co"] {
+            assert!(is_email(valid), "{valid}");
+        }
+        for invalid in [
+            "user",
+            "@example.com",
+            "user@localhost",
+            "user@-example.com",
+            "user@example..com",
+            "us er@example.com",
+        ] {
+            assert!(!is_email(invalid), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn validates_urls() {
+        for valid in [
+            "https://example.com",
+            "http://user@example.com:8080/path?q=1",
+        ] {
+            assert!(is_url(valid), "{valid}");
+        }
+        for invalid in [
+            "example.com",
+            "ftp://example.com",
+            "https://",
+            "https://.example.com",
+            "https://example.com/a b",
+            "javascript:alert(1)",
+        ] {
+            assert!(!is_url(invalid), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn prefixes_nested_errors() {
+        let errors = Validator::new()
+            .nested(
+                "author",
+                &Author {
+                    name: String::new(),
+                },
+            )
+            .finish()
+            .unwrap_err();
+
+        assert_eq!(
+            errors.get("author.name").unwrap(),
+            ["The author.name field is required."]
+        );
+    }
+
+    #[test]
+    fn renders_errors_as_json() {
+        let mut errors = ValidationErrors::new();
+        errors.add("title", "The \"title\" is required.");
+        errors.add("title", "The title is too short.");
+        errors.add("body\n", "Invalid.");
+
+        assert_eq!(
+            errors.to_json(),
+            r#"{"body\n":["Invalid."],"title":["The \"title\" is required.","The title is too short."]}"#
+        );
+    }
+
+    #[test]
+    fn responds_with_unprocessable_content() {
+        let mut errors = ValidationErrors::new();
+        errors.add("title", "The title field is required.");
+        let res = errors.into_response();
+
+        assert_eq!(*res.status(), crate::StatusCode::UnprocessableContent);
+        assert_eq!(
+            res.body().as_ref().and_then(|body| body.as_bytes()),
+            Some(
+                r#"{"message":"The given data was invalid.","errors":{"title":["The title field is required."]}}"#
+                    .as_bytes()
+            )
+        );
+    }
+}